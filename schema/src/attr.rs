@@ -0,0 +1,479 @@
+//! Parsing of the `#[npsd(...)]` attributes accepted by the derives.
+
+use std::collections::HashMap;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{punctuated::Punctuated, spanned::Spanned, token::Comma, Attribute, Data, Error, Expr, ExprLit, ExprRange, ExprUnary, Field, Ident, Lit, LitInt, LitStr, Meta, Path, RangeLimits, Result, UnOp, Variant};
+use syn::meta::ParseNestedMeta;
+
+/// The integer type an enum tag is written as.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum TagType {
+    U8,
+    U16,
+    U32,
+    /// A `usize` varint, the default.
+    Usize,
+}
+
+impl TagType {
+    fn from_ident(ident: &Ident) -> Option<Self> {
+        match ident.to_string().as_str() {
+            "u8" => Some(TagType::U8),
+            "u16" => Some(TagType::U16),
+            "u32" => Some(TagType::U32),
+            "usize" => Some(TagType::Usize),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            TagType::U8 => "u8",
+            TagType::U16 => "u16",
+            TagType::U32 => "u32",
+            TagType::Usize => "usize",
+        }
+    }
+
+    fn max(self) -> u64 {
+        match self {
+            TagType::U8 => u8::MAX as u64,
+            TagType::U16 => u16::MAX as u64,
+            TagType::U32 => u32::MAX as u64,
+            TagType::Usize => u64::MAX,
+        }
+    }
+
+    pub(crate) fn ty(self) -> TokenStream {
+        let ty = Ident::new(self.name(), proc_macro2::Span::call_site());
+        quote! { #ty }
+    }
+
+    /// The number of bits a tag of this type takes when any value of the type can be on the wire.
+    pub(crate) fn bits(self) -> u32 {
+        match self {
+            TagType::U8 => 8,
+            TagType::U16 => 16,
+            TagType::U32 => 32,
+            TagType::Usize => 64,
+        }
+    }
+
+    /// A literal of `value` suffixed with this type, e.g. `5u8`.
+    pub(crate) fn literal(self, value: u64) -> LitInt {
+        LitInt::new(&format!("{}{}", value, self.name()), proc_macro2::Span::call_site())
+    }
+}
+
+/// The values of a `#[npsd(range = start..end)]` field, `end` excluded.
+#[derive(Clone, Copy)]
+pub(crate) struct Range {
+    pub start: i128,
+    pub end: i128,
+}
+
+impl Range {
+    /// The number of values in the range.
+    pub(crate) fn len(self) -> u128 {
+        self.end.abs_diff(self.start)
+    }
+
+    /// The number of bits an offset into the range takes.
+    pub(crate) fn bits(self) -> u32 {
+        (u128::BITS - (self.len() - 1).leading_zeros()).max(1)
+    }
+
+    /// The smallest unsigned type holding an offset into the range, what middlewares that don't
+    /// pack bits write.
+    pub(crate) fn ty(self) -> TokenStream {
+        match self.bits() {
+            0..=8 => quote! { u8 },
+            9..=16 => quote! { u16 },
+            17..=32 => quote! { u32 },
+            33..=64 => quote! { u64 },
+            _ => quote! { u128 },
+        }
+    }
+}
+
+impl std::fmt::Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// An integer literal bound of a range, possibly negative.
+fn range_bound(expr: &Expr) -> Result<i128> {
+    match expr {
+        Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) => lit.base10_parse(),
+        Expr::Unary(ExprUnary { op: UnOp::Neg(_), expr, .. }) => Ok(-range_bound(expr)?),
+        _ => Err(Error::new(expr.span(), "Range bounds must be integer literals")),
+    }
+}
+
+fn parse_range(range: ExprRange) -> Result<Range> {
+    let (Some(start), Some(end)) = (&range.start, &range.end) else {
+        return Err(Error::new(range.span(), "`range` must have both bounds, e.g. `0..100`"));
+    };
+
+    let start = range_bound(start)?;
+    let end = match range.limits {
+        RangeLimits::HalfOpen(_) => range_bound(end)?,
+        RangeLimits::Closed(_) => range_bound(end)?.checked_add(1).ok_or_else(|| Error::new(end.span(), "Range bound overflows"))?,
+    };
+
+    if start >= end {
+        return Err(Error::new(range.span(), "`range` must not be empty"));
+    }
+
+    Ok(Range { start, end })
+}
+
+/// The mapping of a `#[npsd(quantize(min = a, max = b, bits = n))]` float field onto integers.
+#[derive(Clone, Copy)]
+pub(crate) struct Quantize {
+    pub min: f64,
+    pub max: f64,
+    pub bits: u32,
+}
+
+impl Quantize {
+    /// The smallest unsigned type holding a level, what middlewares that don't pack bits write.
+    pub(crate) fn ty(self) -> TokenStream {
+        match self.bits {
+            0..=8 => quote! { u8 },
+            9..=16 => quote! { u16 },
+            17..=32 => quote! { u32 },
+            _ => quote! { u64 },
+        }
+    }
+}
+
+/// A float literal bound of a quantized field, possibly negative.
+fn float_bound(expr: &Expr) -> Result<f64> {
+    match expr {
+        Expr::Lit(ExprLit { lit: Lit::Float(lit), .. }) => lit.base10_parse(),
+        Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) => lit.base10_parse(),
+        Expr::Unary(ExprUnary { op: UnOp::Neg(_), expr, .. }) => Ok(-float_bound(expr)?),
+        _ => Err(Error::new(expr.span(), "Quantize bounds must be number literals")),
+    }
+}
+
+fn parse_quantize(meta: &ParseNestedMeta) -> Result<Quantize> {
+    let (mut min, mut max, mut bits) = (None, None, None);
+
+    meta.parse_nested_meta(|meta| {
+        if meta.path.is_ident("min") {
+            min = Some(float_bound(&meta.value()?.parse()?)?);
+        } else if meta.path.is_ident("max") {
+            max = Some(float_bound(&meta.value()?.parse()?)?);
+        } else if meta.path.is_ident("bits") {
+            bits = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+        } else {
+            return Err(meta.error("Unknown quantize argument, expected `min`, `max` or `bits`"));
+        }
+
+        Ok(())
+    })?;
+
+    let (Some(min), Some(max), Some(bits)) = (min, max, bits) else {
+        return Err(meta.error("`quantize` needs `min`, `max` and `bits`"));
+    };
+
+    if !(min.is_finite() && max.is_finite() && min < max) {
+        return Err(meta.error("`quantize` needs finite bounds with `min` below `max`"));
+    }
+
+    if !(1..=64).contains(&bits) {
+        return Err(meta.error("`quantize` bits must be between 1 and 64"));
+    }
+
+    Ok(Quantize { min, max, bits })
+}
+
+/// Attributes on the deriving type itself.
+#[derive(Default)]
+pub(crate) struct ContainerAttrs {
+    /// `#[npsd(tag_type = u8)]`, falling back to `#[repr(u8)]`.
+    pub tag_type: Option<TagType>,
+    /// `#[npsd(transparent)]`, the struct is encoded as its only field.
+    pub transparent: bool,
+    /// `#[npsd(tagged)]`, fields are written with an id and a length so they can be skipped.
+    pub tagged: bool,
+    /// `#[npsd(open)]`, variant bodies are length prefixed so unknown variants can be skipped.
+    pub open: bool,
+}
+
+/// Attributes on an enum variant.
+#[derive(Default)]
+pub(crate) struct VariantAttrs {
+    /// `#[npsd(tag = N)]`.
+    pub tag: Option<LitInt>,
+    /// `#[npsd(other)]`, the variant catches unknown tags of an open enum.
+    pub other: bool,
+}
+
+/// Attributes on a struct or variant field.
+#[derive(Default)]
+pub(crate) struct FieldAttrs {
+    /// `#[npsd(skip)]`, the field isn't encoded and decodes as its default.
+    pub skip: bool,
+    /// `#[npsd(default)]` or `#[npsd(default = "path")]`.
+    pub default: Option<Option<Path>>,
+    /// `#[npsd(with = "module")]`, encodes through the functions of `module`.
+    pub with: Option<Path>,
+    /// `#[npsd(validate = "path")]`, checks the decoded value.
+    pub validate: Option<Path>,
+    /// `#[npsd(id = N)]`, the id of the field in a tagged container.
+    pub id: Option<LitInt>,
+    /// `#[npsd(since = N)]`, the first protocol version with the field.
+    pub since: Option<u32>,
+    /// `#[npsd(until = N)]`, the first protocol version without the field.
+    pub until: Option<u32>,
+    /// `#[npsd(bits = N)]`, the number of bits of a `Bitmap` field.
+    pub bits: Option<u32>,
+    /// `#[npsd(range = start..end)]`, the values an integer field can take.
+    pub range: Option<Range>,
+    /// `#[npsd(quantize(min = a, max = b, bits = n))]`, a float field written as a fixed-point integer.
+    pub quantize: Option<Quantize>,
+    /// `#[npsd(delta)]`, a `DeltaSchema` field written as its own delta against the baseline.
+    pub delta: bool,
+}
+
+impl FieldAttrs {
+    /// Whether the field is only on the wire for some protocol versions.
+    pub(crate) fn versioned(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+
+    /// The condition under which a versioned field is on the wire, `has_version` is the path
+    /// of the `HasVersion` trait.
+    pub(crate) fn version_check(&self, has_version: &TokenStream) -> Option<TokenStream> {
+        let since = self.since.map(|since| quote! { #has_version::version(&*ctx) >= #since });
+        let until = self.until.map(|until| quote! { #has_version::version(&*ctx) < #until });
+
+        match (since, until) {
+            (Some(since), Some(until)) => Some(quote! { (#since && #until) }),
+            (Some(check), None) | (None, Some(check)) => Some(quote! { (#check) }),
+            (None, None) => None,
+        }
+    }
+
+    /// The value of a field that isn't on the wire.
+    pub(crate) fn default_value(&self) -> TokenStream {
+        match &self.default {
+            Some(Some(path)) => quote! { #path() },
+            _ => quote! { ::core::default::Default::default() },
+        }
+    }
+}
+
+pub(crate) fn container_attrs(attrs: &[Attribute]) -> Result<ContainerAttrs> {
+    let mut container = ContainerAttrs::default();
+    let mut repr = None;
+
+    for attr in attrs {
+        if attr.path().is_ident("npsd") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tag_type") {
+                    let ident: Ident = meta.value()?.parse()?;
+
+                    container.tag_type = Some(TagType::from_ident(&ident).ok_or_else(|| {
+                        Error::new(ident.span(), "`tag_type` must be one of `u8`, `u16`, `u32` or `usize`")
+                    })?);
+
+                    Ok(())
+                } else if meta.path.is_ident("transparent") {
+                    container.transparent = true;
+                    Ok(())
+                } else if meta.path.is_ident("tagged") {
+                    container.tagged = true;
+                    Ok(())
+                } else if meta.path.is_ident("open") {
+                    container.open = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("Unknown npsd container attribute"))
+                }
+            })?;
+        } else if attr.path().is_ident("repr") {
+            let metas = attr.parse_args_with(Punctuated::<Meta, Comma>::parse_terminated)?;
+
+            for meta in metas {
+                if let Meta::Path(path) = meta {
+                    if let Some(ident) = path.get_ident() {
+                        repr = repr.or(TagType::from_ident(ident));
+                    }
+                }
+            }
+        }
+    }
+
+    container.tag_type = container.tag_type.or(repr);
+
+    Ok(container)
+}
+
+pub(crate) fn variant_attrs(attrs: &[Attribute]) -> Result<VariantAttrs> {
+    let mut variant = VariantAttrs::default();
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("npsd")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                variant.tag = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("other") {
+                variant.other = true;
+                Ok(())
+            } else {
+                Err(meta.error("Unknown npsd variant attribute"))
+            }
+        })?;
+    }
+
+    Ok(variant)
+}
+
+pub(crate) fn field_attrs(attrs: &[Attribute]) -> Result<FieldAttrs> {
+    let mut field = FieldAttrs::default();
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("npsd")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                field.skip = true;
+            } else if meta.path.is_ident("default") {
+                field.default = Some(match meta.value() {
+                    Ok(value) => Some(value.parse::<LitStr>()?.parse()?),
+                    Err(_) => None,
+                });
+            } else if meta.path.is_ident("with") {
+                field.with = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else if meta.path.is_ident("validate") {
+                field.validate = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else if meta.path.is_ident("id") {
+                field.id = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("since") {
+                field.since = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("until") {
+                field.until = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("delta") {
+                field.delta = true;
+            } else if meta.path.is_ident("quantize") {
+                field.quantize = Some(parse_quantize(&meta)?);
+            } else if meta.path.is_ident("range") {
+                field.range = Some(parse_range(meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("bits") {
+                let bits: LitInt = meta.value()?.parse()?;
+
+                field.bits = Some(match bits.base10_parse()? {
+                    bits @ 1..=128 => bits,
+                    _ => return Err(Error::new(bits.span(), "`bits` must be between 1 and 128")),
+                });
+            } else {
+                return Err(meta.error("Unknown npsd field attribute"));
+            }
+
+            Ok(())
+        })?;
+    }
+
+    if field.skip && (field.with.is_some() || field.validate.is_some() || field.versioned() || field.range.is_some() || field.quantize.is_some() || field.delta) {
+        return Err(Error::new(attrs[0].span(), "A skipped field can't have `with`, `validate`, `since`, `until`, `range`, `quantize` or `delta`"));
+    }
+
+    if [field.with.is_some(), field.range.is_some(), field.quantize.is_some(), field.delta].into_iter().filter(|set| *set).count() > 1 {
+        return Err(Error::new(attrs[0].span(), "A field can only have one of `with`, `range`, `quantize` and `delta`"));
+    }
+
+    if field.delta && field.validate.is_some() {
+        return Err(Error::new(attrs[0].span(), "A `delta` field can't have `validate`"));
+    }
+
+    if let (Some(since), Some(until)) = (field.since, field.until) {
+        if since >= until {
+            return Err(Error::new(attrs[0].span(), format!("Field is never on the wire, `since = {}` must be below `until = {}`", since, until)));
+        }
+    }
+
+    Ok(field)
+}
+
+/// Whether any field of the type is versioned, so the context must implement `HasVersion`.
+pub(crate) fn versioned(data: &Data) -> Result<bool> {
+    let fields: Vec<&Field> = match data {
+        Data::Struct(data) => data.fields.iter().collect(),
+        Data::Enum(data) => data.variants.iter().flat_map(|variant| variant.fields.iter()).collect(),
+        Data::Union(_) => Vec::new(),
+    };
+
+    for field in fields {
+        if field_attrs(&field.attrs)?.versioned() {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Resolves the wire tag of every variant, `None` for the `#[npsd(other)]` variant.
+///
+/// A variant uses its `#[npsd(tag = N)]`, else its explicit discriminant, else the previous
+/// tag plus one, like Rust discriminants do. Without any of those the tag is the position
+/// of the variant.
+pub(crate) fn variant_tags(attrs: &[Attribute], variants: &Punctuated<Variant, Comma>) -> Result<(TagType, Vec<Option<LitInt>>)> {
+    let container = container_attrs(attrs)?;
+    let tag_type = container.tag_type.unwrap_or(TagType::Usize);
+
+    let mut tags = Vec::with_capacity(variants.len());
+    let mut seen: HashMap<u64, &Ident> = HashMap::new();
+    let mut other: Option<&Ident> = None;
+    let mut implicit = Some(0u64);
+
+    for variant in variants {
+        let variant_attrs = variant_attrs(&variant.attrs)?;
+
+        if variant_attrs.other {
+            if !container.open {
+                return Err(Error::new(variant.span(), "`#[npsd(other)]` requires `#[npsd(open)]` on the enum"));
+            }
+
+            if let Some(other) = other {
+                return Err(Error::new(variant.span(), format!("`{}` is already the `#[npsd(other)]` variant", other)));
+            }
+
+            if variant.fields.len() != 2 || variant_attrs.tag.is_some() {
+                return Err(Error::new(variant.span(), "The `#[npsd(other)]` variant takes the tag and the bytes of the unknown variant, and has no tag of its own"));
+            }
+
+            other = Some(&variant.ident);
+            tags.push(None);
+            continue;
+        }
+
+        let value = if let Some(tag) = variant_attrs.tag {
+            tag.base10_parse::<u64>()?
+        } else if let Some((_, discriminant)) = &variant.discriminant {
+            match discriminant {
+                Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) => lit.base10_parse::<u64>()?,
+                _ => return Err(Error::new(discriminant.span(), "Discriminant must be a non-negative integer literal, or use `#[npsd(tag = N)]`")),
+            }
+        } else {
+            implicit.ok_or_else(|| Error::new(variant.span(), "Tag overflows after the previous variant"))?
+        };
+
+        if value > tag_type.max() {
+            return Err(Error::new(variant.span(), format!("Tag `{}` doesn't fit into `{}`", value, tag_type.name())));
+        }
+
+        if let Some(other) = seen.insert(value, &variant.ident) {
+            return Err(Error::new(variant.span(), format!("Tag `{}` of `{}` is already used by `{}`", value, variant.ident, other)));
+        }
+
+        tags.push(Some(tag_type.literal(value)));
+        implicit = value.checked_add(1);
+    }
+
+    Ok((tag_type, tags))
+}
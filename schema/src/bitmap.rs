@@ -0,0 +1,283 @@
+//! Generation of the `into_payload` and `from_payload` bodies shared by `Bitmap` and `AsyncBitmap`.
+//!
+//! Fields are packed in declaration order from the lowest bit up, a `bool` takes one bit and a
+//! `#[npsd(bits = N)]` field takes `N` bits through `BitField`. The bits are stored in the smallest
+//! of `u8`, `u16`, `u32`, `u64` and `u128` that fits them, or in a `[u8; N]` bitset beyond that,
+//! where bit `i` is bit `i % 8` of byte `i / 8`.
+
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, quote_spanned};
+use syn::{spanned::Spanned, Error, Field, Fields, Ident, Index, Result, Type};
+
+use crate::attr::{self, FieldAttrs};
+use crate::body::Body;
+use crate::located;
+
+/// A field and the bits it occupies.
+struct Slot<'a> {
+    field: &'a Field,
+    attrs: FieldAttrs,
+    /// The member of the struct, a name or an index.
+    member: TokenStream,
+    /// The path of the field in errors.
+    path: Vec<String>,
+    /// The first bit of the field.
+    offset: u32,
+}
+
+impl<'a> Slot<'a> {
+    /// The number of bits of the field, `None` for a `bool` flag.
+    fn width(&self) -> Option<u32> {
+        self.attrs.bits
+    }
+}
+
+/// The integer or bitset holding a number of bits, `__payload_bits` in the generated code. Also
+/// the change mask of `DeltaSchema`.
+#[derive(Clone, Copy)]
+pub(crate) struct Storage {
+    bits: u32,
+}
+
+impl Storage {
+    pub(crate) fn new(bits: u32) -> Self {
+        Storage { bits }
+    }
+
+    /// The integer type holding the bits, `None` when they are stored as a byte array.
+    fn integer(&self) -> Option<Ident> {
+        let name = match self.bits {
+            0..=8 => "u8",
+            9..=16 => "u16",
+            17..=32 => "u32",
+            33..=64 => "u64",
+            65..=128 => "u128",
+            _ => return None,
+        };
+
+        Some(Ident::new(name, Span::call_site()))
+    }
+
+    /// The number of bytes of the bitset.
+    fn len(&self) -> usize {
+        self.bits.div_ceil(8) as usize
+    }
+
+    pub(crate) fn ty(&self) -> Type {
+        match self.integer() {
+            Some(integer) => syn::parse_quote! { #integer },
+            None => {
+                let len = self.len();
+                syn::parse_quote! { [u8; #len] }
+            },
+        }
+    }
+
+    /// The storage with no bit set.
+    pub(crate) fn empty(&self) -> TokenStream {
+        match self.integer() {
+            Some(_) => quote! { 0 },
+            None => {
+                let len = self.len();
+                quote! { [0u8; #len] }
+            },
+        }
+    }
+
+    /// The statement setting bit `offset` of `__payload_bits`.
+    pub(crate) fn set(&self, offset: u32) -> TokenStream {
+        match self.integer() {
+            Some(_) => quote! { __payload_bits |= 1 << #offset; },
+            None => {
+                let (byte, bit) = ((offset / 8) as usize, offset % 8);
+                quote! { __payload_bits[#byte] |= 1 << #bit; }
+            },
+        }
+    }
+
+    /// The expression testing bit `offset` of `__payload_bits`.
+    pub(crate) fn test(&self, offset: u32) -> TokenStream {
+        match self.integer() {
+            Some(_) => quote! { (__payload_bits & (1 << #offset)) != 0 },
+            None => {
+                let (byte, bit) = ((offset / 8) as usize, offset % 8);
+                quote! { (__payload_bits[#byte] & (1 << #bit)) != 0 }
+            },
+        }
+    }
+}
+
+/// The bits of all fields of a bitmap struct.
+pub(crate) struct Layout<'a> {
+    slots: Vec<Slot<'a>>,
+    storage: Storage,
+}
+
+impl<'a> Layout<'a> {
+    pub(crate) fn new(ident: &Ident, fields: &'a Fields) -> Result<Self> {
+        let mut slots = Vec::with_capacity(fields.len());
+        let mut offset = 0u32;
+
+        for (i, field) in fields.iter().enumerate() {
+            let attrs = attr::field_attrs(&field.attrs)?;
+
+            if attrs.with.is_some() || attrs.validate.is_some() || attrs.versioned() || attrs.id.is_some() || attrs.range.is_some() || attrs.quantize.is_some() || attrs.delta {
+                return Err(Error::new(field.span(), "Bitmap fields only accept `bits`, `skip` and `default`"));
+            }
+
+            if attrs.skip && attrs.bits.is_some() {
+                return Err(Error::new(field.span(), "A skipped field can't have `bits`"));
+            }
+
+            let (member, name) = match &field.ident {
+                Some(name) => (quote! { #name }, name.to_string()),
+                None => {
+                    let index = Index::from(i);
+                    (quote! { #index }, i.to_string())
+                },
+            };
+
+            let width = if attrs.skip { 0 } else { attrs.bits.unwrap_or(1) };
+
+            slots.push(Slot {
+                field,
+                attrs,
+                member,
+                path: vec![ident.to_string(), name],
+                offset,
+            });
+
+            offset += width;
+        }
+
+        Ok(Layout { slots, storage: Storage::new(offset) })
+    }
+
+    /// Packs the fields of `self` into `__payload_bits` and writes it to `mw`.
+    pub(crate) fn encode(&self, body: &Body, mw: &Ident) -> TokenStream {
+        let error = body.error();
+        let storage = self.storage.ty();
+        let integer = self.storage.integer();
+
+        let fields = self.slots.iter().filter(|slot| !slot.attrs.skip).map(|slot| {
+            let Slot { field, member, offset, .. } = slot;
+            let span = field.span();
+
+            let Some(width) = slot.width() else {
+                let set = self.storage.set(*offset);
+
+                return quote_spanned! { span =>
+                    if self.#member {
+                        #set
+                    }
+                };
+            };
+
+            let path = slot.path.join(".");
+            let check = (width < 128).then(|| quote! {
+                if __payload_value >> #width != 0 {
+                    return Err(#error::OutOfRange(format!("`{}` doesn't fit into {} bits of `{}`", __payload_value, #width, #path)));
+                }
+            });
+
+            let pack = match &integer {
+                Some(integer) => quote! {
+                    __payload_bits |= (__payload_value as #integer) << #offset;
+                },
+                None => quote! {
+                    for __payload_bit in 0..#width {
+                        if (__payload_value >> __payload_bit) & 1 != 0 {
+                            let __payload_at = #offset + __payload_bit;
+                            __payload_bits[(__payload_at / 8) as usize] |= 1 << (__payload_at % 8);
+                        }
+                    }
+                },
+            };
+
+            let bit_field = bit_field(body);
+
+            quote_spanned! { span =>
+                {
+                    let __payload_value: u128 = #bit_field::to_bits(&self.#member);
+                    #check
+                    #pack
+                }
+            }
+        });
+
+        let write = body.write(mw, &quote! { &__payload_bits });
+        let empty = self.storage.empty();
+
+        quote! {
+            let mut __payload_bits: #storage = #empty;
+            #(#fields)*
+            #write;
+            Ok(())
+        }
+    }
+
+    /// Reads `__payload_bits` from `mw` and unpacks the fields of `name` from it.
+    pub(crate) fn decode(&self, body: &Body, mw: &Ident, name: &Ident) -> TokenStream {
+        let storage = self.storage.ty();
+        let integer = self.storage.integer();
+        let read = body.read(mw, Some(&storage));
+        let read_located = located(&[name.to_string()]);
+
+        let fields = self.slots.iter().map(|slot| {
+            let Slot { field, attrs, member, offset, path } = slot;
+            let span = field.span();
+
+            if attrs.skip {
+                let default = attrs.default_value();
+                return quote_spanned! { span => #member: #default };
+            }
+
+            let Some(width) = slot.width() else {
+                let test = self.storage.test(*offset);
+                return quote_spanned! { span => #member: #test };
+            };
+
+            let mask = if width == 128 { quote! { u128::MAX } } else { quote! { ((1u128 << #width) - 1) } };
+
+            let unpack = match &integer {
+                Some(_) => quote! { ((__payload_bits >> #offset) as u128) & #mask },
+                None => quote! {
+                    {
+                        let mut __payload_value = 0u128;
+
+                        for __payload_bit in 0..#width {
+                            let __payload_at = #offset + __payload_bit;
+
+                            if __payload_bits[(__payload_at / 8) as usize] & (1 << (__payload_at % 8)) != 0 {
+                                __payload_value |= 1 << __payload_bit;
+                            }
+                        }
+
+                        __payload_value
+                    }
+                },
+            };
+
+            let bit_field = bit_field(body);
+            let located = located(path);
+
+            quote_spanned! { span => #member: #bit_field::from_bits(#unpack) #located }
+        });
+
+        quote! {
+            let __payload_bits: #storage = #read #read_located;
+
+            Ok(#name {
+                #(#fields),*
+            })
+        }
+    }
+}
+
+fn bit_field(body: &Body) -> TokenStream {
+    if body.internal {
+        quote! { crate::BitField }
+    } else {
+        quote! { npsd::BitField }
+    }
+}
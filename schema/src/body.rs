@@ -0,0 +1,918 @@
+//! Generation of the `into_payload` and `from_payload` bodies shared by `Schema` and `AsyncSchema`.
+
+use std::collections::HashMap;
+
+use proc_macro2::{Literal, Span, TokenStream};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{spanned::Spanned, Attribute, Data, DataEnum, Error, Expr, Field, Fields, GenericArgument, Ident, Index, LitInt, PathArguments, Result, Type};
+
+use crate::attr::{self, ContainerAttrs, FieldAttrs, Quantize, TagType};
+use crate::located;
+
+/// The settings a body is generated with.
+pub(crate) struct Body<'a> {
+    pub ident: &'a Ident,
+    pub context: &'a Ident,
+    pub asynchronous: bool,
+    pub internal: bool,
+}
+
+/// A field together with its attributes and its value in the generated code.
+pub(crate) struct Member<'a> {
+    pub field: &'a Field,
+    pub attrs: FieldAttrs,
+    /// The pattern binding the field in an enum match, or the member of a struct.
+    pub binding: TokenStream,
+    /// A reference to the field when encoding.
+    pub value: TokenStream,
+    /// The path of the field in decode errors.
+    pub path: Vec<String>,
+}
+
+impl<'a> Body<'a> {
+    pub(crate) fn error(&self) -> TokenStream {
+        if self.internal {
+            quote! { Error }
+        } else {
+            quote! { npsd::Error }
+        }
+    }
+
+    fn next(&self) -> TokenStream {
+        if self.internal {
+            quote! { crate::Next }
+        } else {
+            quote! { npsd::Next }
+        }
+    }
+
+    fn next_scoped(&self) -> TokenStream {
+        if self.internal {
+            quote! { crate::NextScoped }
+        } else {
+            quote! { npsd::NextScoped }
+        }
+    }
+
+    pub(crate) fn has_version(&self) -> TokenStream {
+        if self.internal {
+            quote! { crate::HasVersion }
+        } else {
+            quote! { npsd::HasVersion }
+        }
+    }
+
+    /// A `Quantizer` for the bounds of a `quantize` field.
+    fn quantizer(&self, quantize: Quantize) -> TokenStream {
+        let (min, max, bits) = (Literal::f64_suffixed(quantize.min), Literal::f64_suffixed(quantize.max), quantize.bits);
+
+        if self.internal {
+            quote! { crate::Quantizer::new(#min, #max, #bits) }
+        } else {
+            quote! { npsd::Quantizer::new(#min, #max, #bits) }
+        }
+    }
+
+    /// The middleware trait, `Middleware` or `AsyncMiddleware`.
+    pub(crate) fn middleware(&self) -> TokenStream {
+        match (self.internal, self.asynchronous) {
+            (true, false) => quote! { Middleware },
+            (true, true) => quote! { AsyncMiddleware },
+            (false, false) => quote! { npsd::Middleware },
+            (false, true) => quote! { npsd::AsyncMiddleware },
+        }
+    }
+
+    /// Writes `value`, a reference to a value of a payload type, into the middleware `mw`.
+    pub(crate) fn write(&self, mw: &Ident, value: &TokenStream) -> TokenStream {
+        let middleware = self.middleware();
+
+        if self.asynchronous {
+            quote! { #middleware::poll_into_payload(#mw, #value, ctx).await? }
+        } else {
+            quote! { #middleware::into_payload(#mw, #value, ctx)? }
+        }
+    }
+
+    /// Reads a value of a payload type from the middleware `mw`, without `?`.
+    pub(crate) fn read(&self, mw: &Ident, ty: Option<&syn::Type>) -> TokenStream {
+        let context = self.context;
+        let middleware = self.middleware();
+        let turbofish = ty.map(|ty| quote! { ::<#context, #ty> });
+
+        if self.asynchronous {
+            quote! { #middleware::poll_from_payload #turbofish (#mw, ctx).await }
+        } else {
+            quote! { #middleware::from_payload #turbofish (#mw, ctx) }
+        }
+    }
+
+    /// Writes `value`, a reference to a `BitField` payload type, into `bits` bits of the middleware `mw`.
+    fn write_bits(&self, mw: &Ident, value: &TokenStream, bits: u32) -> TokenStream {
+        let middleware = self.middleware();
+
+        if self.asynchronous {
+            quote! { #middleware::poll_into_bits(#mw, #value, #bits, ctx).await? }
+        } else {
+            quote! { #middleware::into_bits(#mw, #value, #bits, ctx)? }
+        }
+    }
+
+    /// Reads a `BitField` payload type of `bits` bits from the middleware `mw`, without `?`.
+    fn read_bits(&self, mw: &Ident, ty: &TokenStream, bits: u32) -> TokenStream {
+        let context = self.context;
+        let middleware = self.middleware();
+
+        if self.asynchronous {
+            quote! { #middleware::poll_from_bits::<#context, #ty>(#mw, #bits, ctx).await }
+        } else {
+            quote! { #middleware::from_bits::<#context, #ty>(#mw, #bits, ctx) }
+        }
+    }
+
+    /// Reserves room for `len` values of `ty` in the middleware `mw`.
+    fn reserve(&self, mw: &Ident, ty: &Type, len: &TokenStream) -> TokenStream {
+        let middleware = self.middleware();
+
+        if self.asynchronous {
+            quote! { #middleware::poll_reserve::<#ty>(#mw, #len).await? }
+        } else {
+            quote! { #middleware::reserve::<#ty>(#mw, #len)? }
+        }
+    }
+
+    /// Writes the bytes of `bytes`, a `&[u8]`, into the middleware `mw`.
+    fn write_bytes(&self, mw: &Ident, bytes: &TokenStream) -> TokenStream {
+        let middleware = self.middleware();
+
+        if self.asynchronous {
+            quote! { #middleware::poll_write(#mw, #bytes).await? }
+        } else {
+            quote! { #middleware::write(#mw, #bytes)? }
+        }
+    }
+
+    /// Reads `len` bytes from the middleware `mw`, without `?`.
+    fn read_bytes(&self, mw: &Ident, len: &TokenStream) -> TokenStream {
+        let middleware = self.middleware();
+
+        if self.asynchronous {
+            quote! { #middleware::poll_read::<u8>(#mw, #len).await }
+        } else {
+            quote! { #middleware::read::<u8>(#mw, #len) }
+        }
+    }
+
+    /// Calls the scope method `name` of the middleware `mw` with `args`, without `?`.
+    fn scope(&self, mw: &Ident, name: &str, args: TokenStream) -> TokenStream {
+        let middleware = self.middleware();
+
+        if self.asynchronous {
+            let name = format_ident!("poll_{}", name);
+            quote! { #middleware::#name(#mw #args).await }
+        } else {
+            let name = format_ident!("{}", name);
+            quote! { #middleware::#name(#mw #args) }
+        }
+    }
+
+    /// Encodes `body` into a scratch buffer bound to `__payload_scratch`, then writes the buffer
+    /// to `mw` with a `usize` length prefix. The scratch buffer packs bits when `mw` does, so the
+    /// part can be decoded in place.
+    fn write_scoped(&self, mw: &Ident, body: TokenStream) -> TokenStream {
+        let next = self.next();
+        let next_scoped = self.next_scoped();
+        let generic = Ident::new(crate::DEFAULT_MIDDLEWARE, Span::call_site());
+        let write_len = self.write(mw, &quote! { &__payload_bytes.len() });
+        let into_scope = self.scope(mw, "into_scope", quote! {});
+        let write_bytes = self.write_bytes(mw, &quote! { &__payload_bytes });
+
+        quote! {
+            let __payload_bytes = if #generic::PACKS_BITS {
+                let __payload_scratch = &mut #next_scoped::new();
+                #body
+                __payload_scratch.serialized()
+            } else {
+                let __payload_scratch = &mut #next::default();
+                #body
+                __payload_scratch.serialized()
+            };
+
+            #write_len;
+            #into_scope?;
+            #write_bytes;
+        }
+    }
+
+    /// Reads a length prefix from `mw` into `__payload_len`, and enters a part of that many bytes
+    /// that is decoded in place. Errors are located at `path`.
+    fn read_scoped(&self, mw: &Ident, path: &[String]) -> TokenStream {
+        let located = located(path);
+        let read_len = self.read(mw, None);
+        let from_scope = self.scope(mw, "from_scope", quote! { , __payload_len });
+
+        quote! {
+            let __payload_len: usize = #read_len #located;
+            #from_scope #located;
+        }
+    }
+
+    /// Leaves the part entered by `read_scoped`, errors are located at `path`.
+    fn read_scoped_end(&self, mw: &Ident, path: &[String]) -> TokenStream {
+        let located = located(path);
+        let from_scope_end = self.scope(mw, "from_scope_end", quote! {});
+
+        quote! {
+            #from_scope_end #located;
+        }
+    }
+
+    pub(crate) fn encode_member(&self, mw: &Ident, member: &Member) -> TokenStream {
+        let Member { field, attrs, value, path, .. } = member;
+        let span = field.span();
+
+        if attrs.skip {
+            return quote! {};
+        }
+
+        if let Some(range) = attrs.range {
+            let ty = &field.ty;
+            let error = self.error();
+            let (start, end) = (Literal::i128_suffixed(range.start), Literal::i128_suffixed(range.end));
+            let (offset_ty, bits) = (range.ty(), range.bits());
+            let (range, path) = (range.to_string(), path.join("."));
+            let write = self.write_bits(mw, &quote! { &((__payload_value - #start) as #offset_ty) }, bits);
+
+            return quote_spanned! { span =>
+                {
+                    let __payload_value: &#ty = #value;
+                    let __payload_value = *__payload_value as i128;
+
+                    if !(#start..#end).contains(&__payload_value) {
+                        return Err(#error::OutOfRange(format!("`{}` is outside of `{}` of `{}`", __payload_value, #range, #path)));
+                    }
+
+                    #write;
+                }
+            };
+        }
+
+        if let Some(quantize) = attrs.quantize {
+            let ty = &field.ty;
+            let quantizer = self.quantizer(quantize);
+            let level_ty = quantize.ty();
+            let write = self.write_bits(mw, &quote! { &(__payload_quantizer.quantize(*__payload_item as f64) as #level_ty) }, quantize.bits);
+
+            let body = match Quantized::of(ty) {
+                Some(Quantized::Scalar) => quote! {
+                    let __payload_item: &#ty = #value;
+                    #write;
+                },
+                Some(Quantized::Array(..)) => quote! {
+                    let __payload_items: &#ty = #value;
+
+                    for __payload_item in __payload_items.iter() {
+                        #write;
+                    }
+                },
+                Some(Quantized::Vec(_)) => {
+                    let write_len = self.write(mw, &quote! { &__payload_items.len() });
+
+                    quote! {
+                        let __payload_items: &#ty = #value;
+                        #write_len;
+
+                        for __payload_item in __payload_items.iter() {
+                            #write;
+                        }
+                    }
+                },
+                None => unreachable!("checked in `members`"),
+            };
+
+            return quote_spanned! { span =>
+                {
+                    let __payload_quantizer = #quantizer;
+                    #body
+                }
+            };
+        }
+
+        match (&attrs.with, self.asynchronous) {
+            (Some(with), false) => quote_spanned! { span => #with::into_payload(#value, ctx, #mw)?; },
+            (Some(with), true) => quote_spanned! { span => #with::poll_into_payload(#value, ctx, #mw).await?; },
+            (None, _) => {
+                let write = self.write(mw, value);
+                quote_spanned! { span => #write; }
+            },
+        }
+    }
+
+    /// The expression decoding a member, `located` decides whether errors get the member's path.
+    pub(crate) fn decode_member(&self, mw: &Ident, member: &Member, located: bool) -> TokenStream {
+        let Member { field, attrs, path, .. } = member;
+        let ty = &field.ty;
+        let error = self.error();
+
+        if attrs.skip {
+            return attrs.default_value();
+        }
+
+        let located = if located {
+            crate::located(path)
+        } else {
+            quote! { ? }
+        };
+
+        let read = match (&attrs.with, self.asynchronous) {
+            (Some(with), false) => quote! { #with::from_payload(ctx, #mw) #located },
+            (Some(with), true) => quote! { #with::poll_from_payload(ctx, #mw).await #located },
+            (None, _) => match (attrs.range, attrs.quantize) {
+                (_, Some(quantize)) => {
+                    let quantizer = self.quantizer(quantize);
+                    let level_ty = quantize.ty();
+                    let read_bits = self.read_bits(mw, &level_ty, quantize.bits);
+                    let dequantize = |elem: &Type| quote! {
+                        {
+                            let __payload_level: #level_ty = #read_bits #located;
+                            __payload_quantizer.dequantize(__payload_level as u64) #located as #elem
+                        }
+                    };
+
+                    let body = match Quantized::of(ty) {
+                        Some(Quantized::Scalar) => dequantize(ty),
+                        Some(Quantized::Array(elem, len)) => {
+                            let item = dequantize(elem);
+
+                            quote! {
+                                {
+                                    let mut __payload_items = [0.0 as #elem; #len];
+
+                                    for __payload_item in __payload_items.iter_mut() {
+                                        *__payload_item = #item;
+                                    }
+
+                                    __payload_items
+                                }
+                            }
+                        },
+                        Some(Quantized::Vec(elem)) => {
+                            let read_len = self.read(mw, None);
+                            let reserve = self.reserve(mw, elem, &quote! { __payload_len });
+                            let item = dequantize(elem);
+
+                            quote! {
+                                {
+                                    let __payload_len: usize = #read_len #located;
+                                    #reserve;
+
+                                    let mut __payload_items: #ty = Vec::with_capacity(__payload_len);
+
+                                    for _ in 0..__payload_len {
+                                        __payload_items.push(#item);
+                                    }
+
+                                    __payload_items
+                                }
+                            }
+                        },
+                        None => unreachable!("checked in `members`"),
+                    };
+
+                    quote! {
+                        {
+                            let __payload_quantizer = #quantizer;
+                            #body
+                        }
+                    }
+                },
+                (Some(range), _) => {
+                    let start = Literal::i128_suffixed(range.start);
+                    let len = Literal::u128_suffixed(range.len());
+                    let offset_ty = range.ty();
+                    let read_bits = self.read_bits(mw, &offset_ty, range.bits());
+                    let (range, path) = (range.to_string(), path.join("."));
+
+                    quote! {
+                        {
+                            let __payload_offset: #offset_ty = #read_bits #located;
+                            let __payload_offset: #offset_ty = if (__payload_offset as u128) < #len {
+                                Ok(__payload_offset)
+                            } else {
+                                Err(#error::OutOfRange(format!("`{}` is outside of `{}` of `{}`", #start + __payload_offset as i128, #range, #path)))
+                            } #located;
+
+                            (#start + __payload_offset as i128) as #ty
+                        }
+                    }
+                },
+                (None, None) => {
+                    let read = self.read(mw, Some(ty));
+                    quote! { #read #located }
+                },
+            },
+        };
+
+        match &attrs.validate {
+            Some(validate) => quote! {
+                {
+                    let value: #ty = #read;
+
+                    if let Err(error) = #validate(&value) {
+                        return Err(#error::Validation(error.to_string()).at(next.position(), &[#( #path ),*]));
+                    }
+
+                    value
+                }
+            },
+            None => read,
+        }
+    }
+
+    pub(crate) fn members<'f>(fields: &'f Fields, owner: &str, in_enum: bool) -> Result<Vec<Member<'f>>> {
+        fields.iter().enumerate().map(|(i, field)| {
+            let attrs = attr::field_attrs(&field.attrs)?;
+
+            if attrs.bits.is_some() {
+                return Err(Error::new(field.span(), "`bits` is only supported by `Bitmap` and `AsyncBitmap`"));
+            }
+
+            if attrs.quantize.is_some() && Quantized::of(&field.ty).is_none() {
+                return Err(Error::new(field.ty.span(), "`quantize` is only supported on `f32`, `f64`, and arrays and `Vec`s of them"));
+            }
+
+            Ok(match &field.ident {
+                Some(name) => Member {
+                    field,
+                    attrs,
+                    binding: quote! { #name },
+                    value: if in_enum { quote! { &#name } } else { quote! { &self.#name } },
+                    path: vec![owner.to_string(), name.to_string()],
+                },
+                None if in_enum => {
+                    let name = Ident::new(&format!("__self_{}", i), Span::call_site());
+
+                    Member {
+                        field,
+                        attrs,
+                        binding: quote! { #name },
+                        value: quote! { &#name },
+                        path: vec![owner.to_string(), i.to_string()],
+                    }
+                },
+                None => {
+                    let index = Index::from(i);
+
+                    Member {
+                        field,
+                        attrs,
+                        binding: quote! { #index },
+                        value: quote! { &self.#index },
+                        path: vec![owner.to_string(), i.to_string()],
+                    }
+                },
+            })
+        }).collect()
+    }
+
+    /// The statements encoding `members`.
+    fn encode_fields(&self, mw: &Ident, members: &[Member], tagged: bool) -> Result<TokenStream> {
+        let next = mw.clone();
+
+        let has_version = self.has_version();
+
+        if !tagged {
+            let fields = members.iter().map(|member| {
+                let field = self.encode_member(&next, member);
+
+                match member.attrs.version_check(&has_version) {
+                    Some(check) => quote! { if #check { #field } },
+                    None => field,
+                }
+            });
+
+            return Ok(quote! { #( #fields )* });
+        }
+
+        let scratch = scratch_ident();
+        let encoded = field_ids(members)?;
+        let present = encoded.iter().map(|(i, _)| match members[*i].attrs.version_check(&has_version) {
+            Some(check) => quote! { (#check as usize) },
+            None => quote! { 1usize },
+        });
+        let write_count = self.write(&next, &quote! { &(0usize #( + #present )*) });
+
+        let fields = encoded.iter().map(|(i, id)| {
+            let member = &members[*i];
+            let write_id = self.write(&next, &quote! { &#id });
+            let field = self.write_scoped(&next, self.encode_member(&scratch, member));
+            let field = quote! {
+                #write_id;
+                #field
+            };
+
+            match member.attrs.version_check(&has_version) {
+                Some(check) => quote! { if #check { #field } },
+                None => field,
+            }
+        });
+
+        Ok(quote! {
+            #write_count;
+            #( #fields )*
+        })
+    }
+
+    /// The expression constructing `path` from decoded members. Errors of a transparent struct
+    /// aren't located at its member.
+    fn decode_fields(&self, mw: &Ident, path: TokenStream, owner: &str, fields: &Fields, members: &[Member], container: &ContainerAttrs) -> Result<TokenStream> {
+        let next = mw.clone();
+        let located = !container.transparent;
+
+        if !container.tagged {
+            let has_version = self.has_version();
+            let values = members.iter().map(|member| {
+                let value = self.decode_member(&next, member, located);
+
+                match member.attrs.version_check(&has_version) {
+                    Some(check) => {
+                        let default = member.attrs.default_value();
+                        quote! { if #check { #value } else { #default } }
+                    },
+                    None => value,
+                }
+            });
+
+            return Ok(construct(path, fields, values));
+        }
+
+        let error = self.error();
+        let owner = [owner.to_string()];
+        let located_owner = crate::located(&owner);
+        let read_usize = self.read(&next, None);
+        let read_scoped = self.read_scoped(&next, &owner);
+        let read_scoped_end = self.read_scoped_end(&next, &owner);
+        let skip = self.read_bytes(&next, &quote! { __payload_len });
+
+        let slot = |i: usize| format_ident!("__payload_field_{}", i);
+        let ids = field_ids(members)?;
+
+        let slots = ids.iter().map(|(i, _)| {
+            let slot = slot(*i);
+            let ty = &members[*i].field.ty;
+            quote! { let mut #slot: Option<#ty> = None; }
+        });
+
+        let arms = ids.iter().map(|(i, id)| {
+            let slot = slot(*i);
+            let value = self.decode_member(&next, &members[*i], true);
+            quote! { #id => #slot = Some(#value), }
+        });
+
+        let values = members.iter().enumerate().map(|(i, member)| {
+            let slot = slot(i);
+            let path = &member.path;
+            let name = path.join(".");
+
+            if member.attrs.skip {
+                member.attrs.default_value()
+            } else if member.attrs.default.is_some() || member.attrs.versioned() {
+                let default = member.attrs.default_value();
+                quote! { match #slot { Some(value) => value, None => #default } }
+            } else {
+                quote! {
+                    match #slot {
+                        Some(value) => value,
+                        None => return Err(#error::MissingField(#name.to_string()).at(next.position(), &[#( #path ),*])),
+                    }
+                }
+            }
+        });
+
+        let value = construct(path, fields, values);
+
+        Ok(quote! {
+            {
+                #( #slots )*
+                let __payload_count: usize = #read_usize #located_owner;
+
+                for _ in 0..__payload_count {
+                    let __payload_id: usize = #read_usize #located_owner;
+                    #read_scoped
+
+                    match __payload_id {
+                        #( #arms )*
+                        _ => {
+                            #skip #located_owner;
+                        },
+                    }
+
+                    #read_scoped_end
+                }
+
+                #value
+            }
+        })
+    }
+
+    /// The match pattern of an enum variant, skipped fields are ignored.
+    fn pattern(path: TokenStream, fields: &Fields, members: &[Member]) -> TokenStream {
+        let bindings = members.iter().map(|member| {
+            let binding = &member.binding;
+
+            match (member.attrs.skip, fields) {
+                (true, Fields::Named(_)) => quote! { #binding: _ },
+                (true, _) => quote! { _ },
+                (false, _) => quote! { #binding },
+            }
+        });
+
+        match fields {
+            Fields::Named(_) => quote! { #path { #( #bindings, )* } },
+            Fields::Unnamed(_) => quote! { #path ( #( #bindings, )* ) },
+            Fields::Unit => quote! { #path },
+        }
+    }
+
+    /// Checks that a transparent container has a single encoded field.
+    fn transparent(&self, container: &ContainerAttrs, data: &Data) -> Result<()> {
+        if !container.transparent {
+            return Ok(());
+        }
+
+        if container.tagged {
+            return Err(Error::new(self.ident.span(), "`#[npsd(transparent)]` can't be combined with `#[npsd(tagged)]`"));
+        }
+
+        let mut encoded = 0;
+
+        if let Data::Struct(data) = data {
+            for field in &data.fields {
+                if !attr::field_attrs(&field.attrs)?.skip {
+                    encoded += 1;
+                }
+            }
+        }
+
+        if encoded != 1 {
+            return Err(Error::new(self.ident.span(), "`#[npsd(transparent)]` requires a struct with exactly one field that isn't skipped"));
+        }
+
+        Ok(())
+    }
+
+    /// The statements encoding `self`. `tags` holds the tag of every variant, `None` for the
+    /// `#[npsd(other)]` variant.
+    pub(crate) fn sender(&self, attrs: &[Attribute], data: &Data, tags: &[Option<LitInt>]) -> Result<TokenStream> {
+        let ident = self.ident;
+        let next = next_ident();
+        let scratch = scratch_ident();
+        let container = attr::container_attrs(attrs)?;
+        self.transparent(&container, data)?;
+
+        match data {
+            Data::Struct(_) if container.open => Err(Error::new(ident.span(), "`#[npsd(open)]` is only supported on enums")),
+            Data::Struct(data) => {
+                let members = Self::members(&data.fields, &ident.to_string(), false)?;
+                self.encode_fields(&next, &members, container.tagged)
+            },
+            Data::Enum(DataEnum { variants, .. }) => {
+                let tag_ty = container.tag_type.unwrap_or(TagType::Usize).ty();
+                let tag_bits = tag_bits(&container, tags)?;
+
+                let cases = variants.iter().zip(tags).map(|(variant, tag)| {
+                    let variant_ident = &variant.ident;
+                    let variant_span = variant.span();
+                    let members = Self::members(&variant.fields, &format!("{}::{}", ident, variant_ident), true)?;
+                    let pattern = Self::pattern(quote! { #ident::#variant_ident }, &variant.fields, &members);
+
+                    let body = match tag {
+                        Some(tag) => {
+                            let write_tag = self.write_bits(&next, &quote! { &#tag }, tag_bits);
+
+                            let fields = if container.open {
+                                self.write_scoped(&next, self.encode_fields(&scratch, &members, container.tagged)?)
+                            } else {
+                                self.encode_fields(&next, &members, container.tagged)?
+                            };
+
+                            quote! {
+                                #write_tag;
+                                #fields
+                            }
+                        },
+                        None => {
+                            let write_tag = self.write_bits(&next, &quote! { __payload_tag }, tag_bits);
+                            let tag = &members[0].value;
+                            let bytes = &members[1].value;
+                            let write_len = self.write(&next, &quote! { &__payload_raw.len() });
+                            let into_scope = self.scope(&next, "into_scope", quote! {});
+                            let write_bytes = self.write_bytes(&next, &quote! { __payload_raw });
+
+                            quote! {
+                                let __payload_tag: &#tag_ty = #tag;
+                                #write_tag;
+                                let __payload_raw: &[u8] = ::core::convert::AsRef::<[u8]>::as_ref(#bytes);
+                                #write_len;
+                                #into_scope?;
+                                #write_bytes;
+                            }
+                        },
+                    };
+
+                    Ok(quote_spanned! { variant_span =>
+                        #pattern => {
+                            #body
+                        }
+                    })
+                }).collect::<Result<Vec<_>>>()?;
+
+                Ok(quote! {
+                    match self {
+                        #( #cases, )*
+                    }
+                })
+            },
+            Data::Union(_) => Err(Error::new(ident.span(), "Union types are not supported by this macro.")),
+        }
+    }
+
+    /// The expression decoding `Self`.
+    pub(crate) fn receiver(&self, attrs: &[Attribute], data: &Data, tag_ty: &TokenStream, tags: &[Option<LitInt>]) -> Result<TokenStream> {
+        let ident = self.ident;
+        let error = self.error();
+        let next = next_ident();
+        let container = attr::container_attrs(attrs)?;
+        self.transparent(&container, data)?;
+
+        match data {
+            Data::Struct(data) => {
+                let owner = ident.to_string();
+                let members = Self::members(&data.fields, &owner, false)?;
+                let value = self.decode_fields(&next, quote! { #ident }, &owner, &data.fields, &members, &container)?;
+
+                Ok(quote! { Ok(#value) })
+            },
+            Data::Enum(DataEnum { variants, .. }) => {
+                let ident_name = ident.to_string();
+                let mut fallback = quote! {
+                    _ => Err(#error::UnknownVariant(format!("Unknown tag `{}` for enum", variant_tag)).at(next.position(), &[#ident_name])),
+                };
+
+                let mut arms = Vec::new();
+
+                for (variant, tag) in variants.iter().zip(tags) {
+                    let variant_ident = &variant.ident;
+
+                    match tag {
+                        Some(tag) => {
+                            let owner = format!("{}::{}", ident, variant_ident);
+                            let members = Self::members(&variant.fields, &owner, true)?;
+                            let value = self.decode_fields(&next, quote! { #ident::#variant_ident }, &owner, &variant.fields, &members, &container)?;
+
+                            arms.push(quote! { #tag => Ok(#value), });
+                        },
+                        None => {
+                            let located = located(std::slice::from_ref(&ident_name));
+                            let read_bytes = self.read_bytes(&next, &quote! { __payload_len });
+                            let values = [quote! { variant_tag }, quote! { ::core::convert::From::from(__payload_bytes) }];
+                            let value = construct(quote! { #ident::#variant_ident }, &variant.fields, values.into_iter());
+
+                            fallback = quote! {
+                                _ => {
+                                    let __payload_bytes: &[u8] = #read_bytes #located;
+                                    Ok(#value)
+                                },
+                            };
+                        },
+                    }
+                }
+
+                let located = located(std::slice::from_ref(&ident_name));
+                let read_tag = self.read_bits(&next, tag_ty, tag_bits(&container, tags)?);
+
+                if !container.open {
+                    return Ok(quote! {
+                        let variant_tag: #tag_ty = #read_tag #located;
+
+                        match variant_tag {
+                            #( #arms )*
+                            #fallback
+                        }
+                    });
+                }
+
+                let read_scoped = self.read_scoped(&next, std::slice::from_ref(&ident_name));
+                let read_scoped_end = self.read_scoped_end(&next, std::slice::from_ref(&ident_name));
+
+                Ok(quote! {
+                    let variant_tag: #tag_ty = #read_tag #located;
+                    #read_scoped
+
+                    let __payload_value: ::core::result::Result<Self, #error> = match variant_tag {
+                        #( #arms )*
+                        #fallback
+                    };
+                    let __payload_value = __payload_value?;
+
+                    #read_scoped_end
+                    Ok(__payload_value)
+                })
+            },
+            Data::Union(_) => Err(Error::new(ident.span(), "Union types are not supported by this macro.")),
+        }
+    }
+}
+
+pub(crate) fn next_ident() -> Ident {
+    Ident::new("next", Span::call_site())
+}
+
+fn scratch_ident() -> Ident {
+    Ident::new("__payload_scratch", Span::call_site())
+}
+
+/// The expression constructing `path` from the values of its fields.
+fn construct(path: TokenStream, fields: &Fields, values: impl Iterator<Item = TokenStream>) -> TokenStream {
+    match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|field| &field.ident);
+            quote! { #path { #( #names: #values ),* } }
+        },
+        Fields::Unnamed(_) => quote! { #path ( #( #values ),* ) },
+        Fields::Unit => quote! { #path },
+    }
+}
+
+/// The positions and wire ids of the encoded members of a tagged container. The id is
+/// `#[npsd(id = N)]`, or the position of the field.
+fn field_ids(members: &[Member]) -> Result<Vec<(usize, LitInt)>> {
+    let mut ids: Vec<(usize, LitInt)> = Vec::new();
+    let mut seen: HashMap<usize, &Member> = HashMap::new();
+
+    for (i, member) in members.iter().enumerate() {
+        if member.attrs.skip {
+            continue;
+        }
+
+        let id = match &member.attrs.id {
+            Some(id) => id.base10_parse::<usize>()?,
+            None => i,
+        };
+
+        if let Some(other) = seen.insert(id, member) {
+            return Err(Error::new(member.field.span(), format!("Field id `{}` is already used by `{}`", id, other.path.join("."))));
+        }
+
+        ids.push((i, LitInt::new(&format!("{}usize", id), Span::call_site())));
+    }
+
+    Ok(ids)
+}
+
+/// The number of bits of an enum tag in a bit-packing middleware, enough for the largest tag,
+/// or for any value of the tag type when unknown tags are accepted.
+fn tag_bits(container: &ContainerAttrs, tags: &[Option<LitInt>]) -> Result<u32> {
+    if container.open {
+        return Ok(container.tag_type.unwrap_or(TagType::Usize).bits());
+    }
+
+    let mut max = 0u64;
+
+    for tag in tags.iter().flatten() {
+        max = max.max(tag.base10_parse()?);
+    }
+
+    Ok((u64::BITS - max.leading_zeros()).max(1))
+}
+
+/// The shapes of float fields `quantize` supports.
+enum Quantized<'a> {
+    Scalar,
+    Array(&'a Type, &'a Expr),
+    Vec(&'a Type),
+}
+
+impl<'a> Quantized<'a> {
+    fn of(ty: &'a Type) -> Option<Self> {
+        let float = |ty: &Type| matches!(ty, Type::Path(path) if path.qself.is_none() && (path.path.is_ident("f32") || path.path.is_ident("f64")));
+
+        match ty {
+            ty if float(ty) => Some(Quantized::Scalar),
+            Type::Array(array) if float(&array.elem) => Some(Quantized::Array(&array.elem, &array.len)),
+            Type::Path(path) if path.qself.is_none() => {
+                let segment = path.path.segments.last()?;
+
+                let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+                    return None;
+                };
+
+                match arguments.args.first() {
+                    Some(GenericArgument::Type(elem)) if segment.ident == "Vec" && arguments.args.len() == 1 && float(elem) => Some(Quantized::Vec(elem)),
+                    _ => None,
+                }
+            },
+            _ => None,
+        }
+    }
+}
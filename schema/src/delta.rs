@@ -0,0 +1,140 @@
+//! Generation of the `encode_delta` and `apply_delta` bodies shared by `DeltaSchema` and
+//! `AsyncDeltaSchema`.
+//!
+//! A delta starts with a change mask holding one bit per encoded field in declaration order, stored
+//! like the flags of a `Bitmap`, followed by the fields whose bit is set. A field has changed when
+//! it isn't equal to the baseline's. A `#[npsd(delta)]` field is written as its own delta against
+//! the baseline's field, any other field is written whole, the way `Schema` writes it.
+
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::{spanned::Spanned, Data, Error, Lifetime, Result};
+
+use crate::bitmap::Storage;
+use crate::body::{next_ident, Body, Member};
+use crate::located;
+
+/// The encoded fields of a delta struct and their bits in the change mask.
+pub(crate) struct Delta<'a> {
+    body: &'a Body<'a>,
+    members: Vec<(Member<'a>, u32)>,
+    storage: Storage,
+}
+
+impl<'a> Delta<'a> {
+    pub(crate) fn new(body: &'a Body<'a>, data: &'a Data) -> Result<Self> {
+        let Data::Struct(data) = data else {
+            return Err(Error::new(body.ident.span(), "DeltaSchema can only be derived for structs"));
+        };
+
+        let members: Vec<_> = Body::members(&data.fields, &body.ident.to_string(), false)?
+            .into_iter()
+            .filter(|member| !member.attrs.skip)
+            .zip(0..)
+            .collect();
+
+        let storage = Storage::new(members.len() as u32);
+
+        Ok(Delta { body, members, storage })
+    }
+
+    /// The delta trait of nested `#[npsd(delta)]` fields.
+    fn delta_payload(&self, lifetime: &Lifetime) -> TokenStream {
+        let context = self.body.context;
+
+        if self.body.asynchronous {
+            quote! { npsd::AsyncDeltaPayload::<#lifetime, #context> }
+        } else {
+            quote! { npsd::DeltaPayload::<#lifetime, #context> }
+        }
+    }
+
+    /// Writes the change mask of `self` against `baseline`, then the changed fields.
+    pub(crate) fn encode(&self, lifetime: &Lifetime) -> TokenStream {
+        let next = next_ident();
+        let has_version = self.body.has_version();
+        let delta_payload = self.delta_payload(lifetime);
+        let storage = self.storage.ty();
+        let empty = self.storage.empty();
+
+        let changes = self.members.iter().map(|(member, bit)| {
+            let binding = &member.binding;
+            let set = self.storage.set(*bit);
+            let changed = quote! { self.#binding != baseline.#binding };
+
+            let changed = match member.attrs.version_check(&has_version) {
+                Some(check) => quote! { #check && #changed },
+                None => changed,
+            };
+
+            quote_spanned! { member.field.span() =>
+                if #changed {
+                    #set
+                }
+            }
+        });
+
+        let fields = self.members.iter().map(|(member, bit)| {
+            let binding = &member.binding;
+            let test = self.storage.test(*bit);
+
+            let field = match (member.attrs.delta, self.body.asynchronous) {
+                (true, false) => quote! { #delta_payload::encode_delta(&self.#binding, &baseline.#binding, ctx, #next)?; },
+                (true, true) => quote! { #delta_payload::poll_encode_delta(&self.#binding, &baseline.#binding, ctx, #next).await?; },
+                (false, _) => self.body.encode_member(&next, member),
+            };
+
+            quote! {
+                if #test {
+                    #field
+                }
+            }
+        });
+
+        let write = self.body.write(&next, &quote! { &__payload_bits });
+
+        quote! {
+            let mut __payload_bits: #storage = #empty;
+            #( #changes )*
+            #write;
+            #( #fields )*
+            Ok(())
+        }
+    }
+
+    /// Reads a change mask and replaces the changed fields of `self`.
+    pub(crate) fn apply(&self, lifetime: &Lifetime) -> TokenStream {
+        let next = next_ident();
+        let delta_payload = self.delta_payload(lifetime);
+        let storage = self.storage.ty();
+        let read = self.body.read(&next, Some(&storage));
+        let read_located = located(&[self.body.ident.to_string()]);
+
+        let fields = self.members.iter().map(|(member, bit)| {
+            let binding = &member.binding;
+            let test = self.storage.test(*bit);
+            let located = located(&member.path);
+
+            let field = match (member.attrs.delta, self.body.asynchronous) {
+                (true, false) => quote! { #delta_payload::apply_delta(&mut self.#binding, ctx, #next) #located; },
+                (true, true) => quote! { #delta_payload::poll_apply_delta(&mut self.#binding, ctx, #next).await #located; },
+                (false, _) => {
+                    let value = self.body.decode_member(&next, member, true);
+                    quote! { self.#binding = #value; }
+                },
+            };
+
+            quote_spanned! { member.field.span() =>
+                if #test {
+                    #field
+                }
+            }
+        });
+
+        quote! {
+            let __payload_bits: #storage = #read #read_located;
+            #( #fields )*
+            Ok(())
+        }
+    }
+}
@@ -12,23 +12,100 @@
 //! Generates implementations for payload processing traits such as `IntoPayload`, `FromPayload`, and `Payload` for public use.
 //!
 //! ### `#[derive(Bitmap)]`
-//! Generates implementations for payload processing traits for bitmap structures with up to 8 fields.
+//! Generates implementations for payload processing traits for bitmap structures. Fields are packed from
+//! the lowest bit up into the smallest unsigned integer that fits them, or a byte array beyond 128 bits.
 //!
 //! ### `#[derive(AsyncSchema)]`
 //! Generates asynchronous implementations for payload processing traits such as `AsyncIntoPayload`, `AsyncFromPayload`, and `AsyncPayload` for public use.
 //!
 //! ### `#[derive(AsyncBitmap)]`
-//! Generates asynchronous implementations for payload processing traits for bitmap structures with up to 8 fields.
+//! Generates asynchronous implementations for payload processing traits for bitmap structures, with the
+//! layout of `Bitmap`.
+//!
+//! ### `#[derive(DeltaSchema)]`
+//! Generates an implementation of `DeltaPayload` for a struct, which writes a change mask and the fields
+//! that differ from a baseline, and applies such a delta to the baseline.
+//!
+//! ### `#[derive(AsyncDeltaSchema)]`
+//! Generates an implementation of `AsyncDeltaPayload`, with the layout of `DeltaSchema`.
+//!
+//! ### `#[derive(BitField)]`
+//! Generates an implementation of `BitField` for an enum without fields, mapping variants to their tags.
+//!
+//! ### `#[derive(Diff)]`
+//! Generates an implementation of `Diff`, which finds the changes between two values as the ops of a
+//! `Patch` and applies them. Structs are diffed field by field, enums are replaced as a whole.
+//!
+//! ### `#[service]`
+//! Generates an RPC client stub and server dispatcher for a trait, see `npsd::rpc`.
+//!
+//! ## Attributes
+//!
+//! `Schema` and `AsyncSchema` accept `#[npsd(...)]` attributes:
+//!
+//! - `#[npsd(tag_type = u8|u16|u32|usize)]` on an enum picks the integer type of the variant tag.
+//!   It defaults to the `#[repr]` of the enum, or a `usize` varint.
+//! - `#[npsd(tag = N)]` on a variant sets its tag. Variants without one use their explicit
+//!   discriminant, or the previous tag plus one. Duplicate tags are a compile error.
+//! - `#[npsd(tagged)]` on a struct or enum writes the fields as a count followed by an id, a length
+//!   and the encoded bytes per field, so fields can be added and removed between versions. Unknown
+//!   ids are skipped, missing fields decode as their `#[npsd(default)]`, or fail with
+//!   `Error::MissingField`. The id is the position of the field unless set with `#[npsd(id = N)]`.
+//!   Fields are encoded into a scratch buffer, a `NextScoped` when the middleware packs bits, and
+//!   decoded in place between `from_scope` and `from_scope_end`, so limits, tracing and bit packing
+//!   of the middleware apply to them, and with `Next` lengths inside a field can't go past its bytes.
+//! - `#[npsd(since = N)]` and `#[npsd(until = N)]` on a field keep it on the wire only while
+//!   `since <= ctx.version() < until`, otherwise it decodes as its default. The context must
+//!   implement `npsd::HasVersion`.
+//! - `#[npsd(open)]` on an enum length prefixes every variant body, so a peer can skip variants
+//!   and trailing fields it doesn't know. Unknown tags decode into the variant marked
+//!   `#[npsd(other)]`, which holds the tag and the raw body, e.g. `Unknown(u8, Vec<u8>)`, and
+//!   encodes them back unchanged. Without it unknown tags fail with `Error::UnknownVariant`.
+//!   Like tagged fields, variant bodies are decoded in place.
+//! - `#[npsd(transparent)]` on a struct with a single encoded field encodes it as that field,
+//!   decode errors are reported without the struct's path segment.
+//! - `#[npsd(skip)]` on a field leaves it off the wire, it decodes as `Default::default()`, or as
+//!   the result of the function given with `#[npsd(default = "path")]`.
+//! - `#[npsd(with = "module")]` on a field encodes it with `module::into_payload(&value, ctx, next)`
+//!   and `module::from_payload(ctx, next)`, or the `poll_` versions for `AsyncSchema`.
+//! - `#[npsd(validate = "path")]` on a field calls `path(&value)` after decoding, an `Err` with a
+//!   `Display`able reason fails the decode with `Error::Validation`.
+//! - `#[npsd(range = a..b)]` or `#[npsd(range = a..=b)]` on an integer field writes its offset from
+//!   `a`, through `into_bits` with just enough bits for the range, which is the smallest unsigned
+//!   integer holding it unless the middleware packs bits like `npsd::NextBits` does. Values outside
+//!   the range fail with `Error::OutOfRange`.
+//! - `#[npsd(quantize(min = a, max = b, bits = n))]` on an `f32` or `f64` field, or an array or
+//!   `Vec` of them, writes each float as one of `2^n` evenly spaced levels across `a..=b`, through
+//!   `into_bits` like `range`. Values are clamped into the bounds, see `npsd::Quantizer` for the
+//!   precision. Levels above the highest of `n` bits fail to decode with `Error::OutOfRange`.
+//!
+//! Enum tags are written through `into_bits` too, with enough bits for the largest tag, or for any
+//! value of the `tag_type` for `#[npsd(open)]` enums.
+//!
+//! `DeltaSchema` and `AsyncDeltaSchema` accept the field attributes of `Schema`, and:
+//!
+//! - `#[npsd(delta)]` on a field implementing `DeltaPayload` writes it as its own delta against the
+//!   baseline's field instead of whole.
+//!
+//! `Bitmap` and `AsyncBitmap` accept `#[npsd(skip)]`, `#[npsd(default = "path")]` and:
+//!
+//! - `#[npsd(bits = N)]` on a field implementing `BitField` packs it into `N` bits, `1..=128`, next
+//!   to the `bool` flags. Encoding a value that doesn't fit fails with `Error::OutOfRange`.
 
 #[doc(hidden)]
-use syn::{parse_macro_input, TypeParam, parse_quote, punctuated::Punctuated, spanned::Spanned, token::Plus, Data, DataEnum, DeriveInput, Fields, FieldsNamed, FieldsUnnamed, GenericParam, Generics, Ident, Index, Lifetime, LifetimeParam, TypeParamBound};
+use syn::{parse_macro_input, FnArg, ItemTrait, Pat, ReturnType, TraitItem, TypeParam, parse_quote, punctuated::Punctuated, spanned::Spanned, token::Plus, Data, DataEnum, DeriveInput, GenericParam, Generics, Ident, Lifetime, LifetimeParam, TypeParamBound};
 #[doc(hidden)]
-use quote::{quote, quote_spanned};
+use quote::{format_ident, quote, quote_spanned};
 #[doc(hidden)]
 use proc_macro::TokenStream;
 #[doc(hidden)]
 use proc_macro2::Span;
 
+mod attr;
+mod bitmap;
+mod body;
+mod delta;
+
 const DEFAULT_LIFETIME: &'static str = "'__payload";
 const DEFAULT_SCOPE_LIFETIME: &'static str = "'__payload_scope";
 const DEFAULT_CONTEXT: &'static str = "__PayloadCtx";
@@ -60,6 +137,22 @@ fn has_bound(bounds: &Punctuated<TypeParamBound, Plus>, bound_to_check: &str) ->
     })
 }
 
+#[doc(hidden)]
+fn located(path: &[String]) -> proc_macro2::TokenStream {
+    quote! {
+        .map_err(|e| e.at(next.position(), &[#( #path ),*]))?
+    }
+}
+
+#[doc(hidden)]
+fn has_version_bound(internal: bool) -> TypeParamBound {
+    if internal {
+        parse_quote!(crate::HasVersion)
+    } else {
+        parse_quote!(npsd::HasVersion)
+    }
+}
+
 #[doc(hidden)]
 fn schema_into_impl(generics: &mut Generics, internal: bool, context: &Ident) {
     for param in generics.params.iter_mut() {
@@ -204,20 +297,20 @@ pub fn payload_info_intenal_impl(input: TokenStream) -> TokenStream {
     gen.into()
 }
 
-#[proc_macro_derive(Schema)]
+#[proc_macro_derive(Schema, attributes(npsd))]
 pub fn schema_public_impl(input: TokenStream) -> TokenStream {
     schema_impl(input, false)
 }
 
 #[doc(hidden)]
-#[proc_macro_derive(SchemaInternal)]
+#[proc_macro_derive(SchemaInternal, attributes(npsd))]
 pub fn schema_internal_impl(input: TokenStream) -> TokenStream {
     schema_impl(input, true)
 }
 
 #[doc(hidden)]
 fn schema_impl(input: TokenStream, internal: bool) -> TokenStream {
-    let DeriveInput { ident, data, generics, .. } = parse_macro_input!(input);
+    let DeriveInput { ident, data, generics, attrs, .. } = parse_macro_input!(input);
     let (_, ty_generics, where_clause) = generics.split_for_impl();
 
     let (lifetime_exist, lifetime) = resolve_lifetime(&generics, DEFAULT_LIFETIME);
@@ -226,8 +319,15 @@ fn schema_impl(input: TokenStream, internal: bool) -> TokenStream {
     let mw = Ident::new(DEFAULT_MIDDLEWARE, Span::call_site());
     let mut context_generics = generics.clone();
 
-    let context_param: GenericParam = syn::parse_quote!(#context);
-    context_generics.params.push(context_param);
+    let mut context_param: TypeParam = syn::parse_quote!(#context);
+
+    match attr::versioned(&data) {
+        Ok(true) => context_param.bounds.push(has_version_bound(internal)),
+        Ok(false) => {},
+        Err(error) => return error.to_compile_error().into(),
+    }
+
+    context_generics.params.push(GenericParam::Type(context_param));
 
     let mut into_generics = context_generics.clone();
     let mut from_generics = context_generics.clone();
@@ -248,205 +348,20 @@ fn schema_impl(input: TokenStream, internal: bool) -> TokenStream {
     schema_payload_impl(&mut payload_generics, internal, &lifetime, &context);
     let (payload_impl, _, _) = payload_generics.split_for_impl();
 
-    let sender_block = match data.clone() {
-        Data::Struct(data_struct) => {
-            let fields = match data_struct.fields {
-                Fields::Named(FieldsNamed { named, .. }) => {
-                    named.iter().map(|f| {
-                        let name = &f.ident;
-                        let span = f.span();
-
-                        quote_spanned! { span =>
-                            next.into_payload(&self.#name, ctx)?;
-                        }
-                    }).collect::<Vec<_>>()
-                },
-
-                Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-                    unnamed.iter().enumerate().map(|(i, _)| {
-                        let index = Index::from(i);
-                        let span = index.span();
-
-                        quote_spanned! { span =>
-                            next.into_payload(&self.#index, ctx)?;
-                        }
-                    }).collect::<Vec<_>>()
-                },
-
-                Fields::Unit => Vec::new(),
-            };
-
-            quote! { #( #fields )* }
-        },
-        Data::Enum(DataEnum { variants, .. }) => {
-            let variant_cases = variants.iter().enumerate().map(|(index, variant)| {
-                let variant_ident = &variant.ident;
-                let variant_span = variant.span(); 
-
-                match &variant.fields {
-                    Fields::Named(FieldsNamed { named, .. }) => {
-                        let (field_patterns, field_serializations): (Vec<_>, Vec<_>) = named.iter()
-                            .map(|f| {
-                                let name = f.ident.as_ref().unwrap();
-                                let span = name.span();
-                                let pattern = quote_spanned! { span => #name };
-                                let serialization = quote_spanned! { span => next.into_payload(&#name, ctx)?; };
-                                (pattern, serialization)
-                            }).unzip();
-
-                        quote_spanned! { variant_span => 
-                            #ident::#variant_ident { #(#field_patterns,)* } => {
-                                next.into_payload(&#index, ctx)?;
-                                #( #field_serializations )*
-                            }
-                        }
-                    },
-                    Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-                        let (field_patterns, field_serializations): (Vec<_>, Vec<_>) = unnamed.iter().enumerate()
-                            .map(|(i, _)| {
-                                let field_name = Ident::new(&format!("__self_{}", i), Span::call_site());
-                                let pattern = quote! { #field_name };
-                                let serialization = quote! { next.into_payload(&#field_name, ctx)?; };
-                                (pattern, serialization)
-                            }).unzip();
-                    
-                        quote_spanned! { variant_span => 
-                            #ident::#variant_ident( #( #field_patterns, )* ) => {
-                                next.into_payload(&#index, ctx)?;
-                                #( #field_serializations )*
-                            }
-                        }
-                    },
-                    Fields::Unit => {
-                        quote_spanned! { variant_span => 
-                            #ident::#variant_ident => {
-                                next.into_payload(&#index, ctx)?;
-                            }
-                        }
-                    },
-                }
-            });
-
-            quote! {
-                match self {
-                    #( #variant_cases, )*
-                }
-            }
-        },
-        Data::Union(_) => {
-            return quote! {
-                compile_error!("Union types are not supported by this macro.");
-            }.into();
+    let (tag_type, tags) = match &data {
+        Data::Enum(DataEnum { variants, .. }) => match attr::variant_tags(&attrs, variants) {
+            Ok(tags) => tags,
+            Err(error) => return error.to_compile_error().into(),
         },
+        _ => (attr::TagType::Usize, Vec::new()),
     };
+    let tag_ty = tag_type.ty();
 
-    let receiver_block = match data.clone() {
-        Data::Struct(data_struct) => {
-            match data_struct.fields {
-                Fields::Named(FieldsNamed { named, .. }) => {
-                    let fields = named.iter().map(|f| {
-                        let field = &f.ident;
-                        let ty = &f.ty;
-                        let span = f.span();
-
-                        quote_spanned! { span =>
-                            #field: next.from_payload::<#context, #ty>(ctx)? // as #ty
-                        }
-                    }).collect::<Vec<_>>();
-
-                    quote! {
-                        Ok(#ident {
-                            #( #fields ),*
-                        })
-                    }
-                },
-                Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-                    let fields = unnamed.iter().enumerate().map(|(_, f)| {
-                        let ty = &f.ty;
-
-                        quote! {
-                            next.from_payload::<#context, #ty>(ctx)? // as #ty
-                        }
-                    }).collect::<Vec<_>>();
-
-                    quote! {
-                        Ok(#ident (
-                            #( #fields ),*
-                        ))
-                    }
-                },
-                Fields::Unit => {
-                    quote! {
-                        Ok(#ident)
-                    }
-                },
-            }
-        },
-        Data::Enum(DataEnum { variants, .. }) => {
-            let match_variants = variants.iter().enumerate().map(|(index, variant)| {
-                let variant_ident = &variant.ident;
-                
-                match &variant.fields {
-                    Fields::Named(FieldsNamed { named, .. }) => {
-                        let deserializations = named.iter().map(|f| {
-                            let name = &f.ident;
-                            let ty = &f.ty;
-        
-                            quote! {
-                                #name: next.from_payload::<#context, #ty>(ctx)? // as #ty
-                            }
-                        });
-                        
-                        quote! {
-                            #index => Ok(#ident::#variant_ident { #(#deserializations),* })
-                        }
-                    },
-                    Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-                        let deserializations = unnamed.iter().map(|f| {
-                            let ty = &f.ty;
-        
-                            quote! {
-                                next.from_payload::<#context, #ty>(ctx)? // as #ty
-                            }
-                        });
-                        
-                        quote! {
-                            #index => Ok(#ident::#variant_ident( #(#deserializations),* ))
-                        }
-                    },
-                    Fields::Unit => {
-                        quote! {
-                            #index => Ok(#ident::#variant_ident)
-                        }
-                    },
-                }
-            }).collect::<Vec<_>>();
-        
-            if internal {
-                quote! {
-                    let variant_index: usize = next.from_payload(ctx)?;
-            
-                    match variant_index {
-                        #(#match_variants,)*
-                        _ => Err(Error::UnknownVariant("Index out of bounds for enum".to_string())),
-                    }
-                }
-            } else {
-                quote! {
-                    let variant_index: usize = next.from_payload(ctx)?;
-            
-                    match variant_index {
-                        #(#match_variants,)*
-                        _ => Err(npsd::Error::UnknownVariant("Index out of bounds for enum".to_string())),
-                    }
-                }
-            }
-        },
-        Data::Union(_) => {
-            return quote! {
-                compile_error!("Union types are not supported by this macro.");
-            }.into();
-        },
+    let body = body::Body { ident: &ident, context: &context, asynchronous: false, internal };
+
+    let (sender_block, receiver_block) = match body.sender(&attrs, &data, &tags).and_then(|sender| Ok((sender, body.receiver(&attrs, &data, &tag_ty, &tags)?))) {
+        Ok(blocks) => blocks,
+        Err(error) => return error.to_compile_error().into(),
     };
 
     let gen = if internal {
@@ -488,13 +403,13 @@ fn schema_impl(input: TokenStream, internal: bool) -> TokenStream {
     gen.into()
 }
 
-#[proc_macro_derive(Bitmap)]
+#[proc_macro_derive(Bitmap, attributes(npsd))]
 pub fn bitmap_derive(input: TokenStream) -> TokenStream {
     bitmap_impl(input, false)
 }
 
 #[doc(hidden)]
-#[proc_macro_derive(BitmapInternal)]
+#[proc_macro_derive(BitmapInternal, attributes(npsd))]
 pub fn bitmap_internal_derive(input: TokenStream) -> TokenStream {
     bitmap_impl(input, true)
 }
@@ -512,26 +427,21 @@ fn bitmap_impl(input: TokenStream, internal: bool) -> TokenStream {
         } 
     };
 
-    let field_count = match fields {
-        Fields::Named(ref named_fields) => named_fields.named.len(),
-        Fields::Unnamed(ref unnamed_fields) => unnamed_fields.unnamed.len(),
-        Fields::Unit => 0,
+    let layout = match bitmap::Layout::new(&ident, fields) {
+        Ok(layout) => layout,
+        Err(error) => return error.to_compile_error().into(),
     };
 
-    if field_count > 8 {
-        return quote! {
-            compile_error!("Bitmap can only be derived for structs with no more than 8 fields");
-        }.into();
-    }
-
     let lifetime = Lifetime::new(DEFAULT_LIFETIME, Span::call_site());
     let scope = Lifetime::new(DEFAULT_SCOPE_LIFETIME, Span::call_site());
 
     let context = Ident::new(DEFAULT_CONTEXT, Span::call_site());
     let mw = Ident::new(DEFAULT_MIDDLEWARE, Span::call_site());
 
-    let into_payload_impl = generate_into_payload_impl(&ident, &fields, &scope, &context, &mw, internal);
-    let from_payload_impl = generate_from_payload_impl(&ident, &fields, &lifetime, &context, &mw, internal);
+    let body = body::Body { ident: &ident, context: &context, asynchronous: false, internal };
+
+    let into_payload_impl = generate_into_payload_impl(&ident, &layout, &body, &scope, &context, &mw, internal);
+    let from_payload_impl = generate_from_payload_impl(&ident, &layout, &body, &lifetime, &context, &mw, internal);
     let payload_impl = generate_payload_impl(&ident, &lifetime,&context, internal);
 
     let expanded = quote! {
@@ -544,42 +454,15 @@ fn bitmap_impl(input: TokenStream, internal: bool) -> TokenStream {
 }
 
 #[doc(hidden)]
-fn generate_into_payload_impl(name: &Ident, fields: &Fields, scope: &Lifetime, context: &Ident, mw: &Ident, internal: bool) -> proc_macro2::TokenStream {
-    let field_conversions = match fields {
-        Fields::Named(FieldsNamed { named, .. }) => {
-            named.iter().enumerate().map(|(i, f)| {
-                let field_name = &f.ident;
-                let bit_position = i as u8;
-
-                quote! {
-                    if self.#field_name {
-                        byte |= 1 << #bit_position;
-                    }
-                }
-            }).collect::<Vec<_>>()
-        },
-        Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-            unnamed.iter().enumerate().map(|(i, _)| {
-                let field_name = Index::from(i);
-                let bit_position = i as u8;
-
-                quote! {
-                    if self.#field_name {
-                        byte |= 1 << #bit_position;
-                    }
-                }
-            }).collect::<Vec<_>>()
-        },
-        Fields::Unit => vec![],
-    };
+fn generate_into_payload_impl(name: &Ident, layout: &bitmap::Layout, body: &body::Body, scope: &Lifetime, context: &Ident, mw: &Ident, internal: bool) -> proc_macro2::TokenStream {
+    let next = Ident::new("next", Span::call_site());
+    let encode = layout.encode(body, &next);
 
     if internal {
         quote! {
             impl<#context> IntoPayload<#context> for #name {
-                fn into_payload<#scope, #mw: npsd::Middleware<#scope>>(&self, ctx: &mut #context, next: &mut #mw) -> Result<(), Error> {
-                    let mut byte: u8 = 0;
-                    #(#field_conversions)*
-                    next.into_payload(&byte, ctx)
+                fn into_payload<#scope, #mw: Middleware<#scope>>(&self, ctx: &mut #context, next: &mut #mw) -> Result<(), Error> {
+                    #encode
                 }
             }
         }
@@ -587,9 +470,7 @@ fn generate_into_payload_impl(name: &Ident, fields: &Fields, scope: &Lifetime, c
         quote! {
             impl<#context> npsd::IntoPayload<#context> for #name {
                 fn into_payload<#scope, #mw: npsd::Middleware<#scope>>(&self, ctx: &mut #context, next: &mut #mw) -> Result<(), npsd::Error> {
-                    let mut byte: u8 = 0;
-                    #(#field_conversions)*
-                    next.into_payload(&byte, ctx)
+                    #encode
                 }
             }
         }
@@ -597,40 +478,15 @@ fn generate_into_payload_impl(name: &Ident, fields: &Fields, scope: &Lifetime, c
 }
 
 #[doc(hidden)]
-fn generate_from_payload_impl(name: &Ident, fields: &Fields, lifetime: &Lifetime, context: &Ident, mw: &Ident, internal: bool) -> proc_macro2::TokenStream {
-    let field_assignments = match fields {
-        Fields::Named(FieldsNamed { named, .. }) => {
-            named.iter().enumerate().map(|(i, f)| {
-                let field_name = &f.ident;
-                let bit_position = i as u8;
-
-                quote! {
-                    #field_name: (byte & (1 << #bit_position)) != 0
-                }
-            }).collect::<Vec<_>>()
-        },
-        Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-            unnamed.iter().enumerate().map(|(i, _)| {
-                let field_name = Index::from(i);
-                let bit_position = i as u8;
-
-                quote! {
-                    #field_name: (byte & (1 << #bit_position)) != 0
-                }
-            }).collect::<Vec<_>>()
-        },
-        Fields::Unit => vec![],
-    };
+fn generate_from_payload_impl(name: &Ident, layout: &bitmap::Layout, body: &body::Body, lifetime: &Lifetime, context: &Ident, mw: &Ident, internal: bool) -> proc_macro2::TokenStream {
+    let next = Ident::new("next", Span::call_site());
+    let decode = layout.decode(body, &next, name);
 
     if internal {
         quote! {
             impl<#lifetime, #context> FromPayload<#lifetime, #context> for #name {
                 fn from_payload<#mw: Middleware<#lifetime>>(ctx: &mut #context, next: &mut #mw) -> Result<Self, Error> {
-                    let byte: u8 = next.from_payload(ctx)?;
-
-                    Ok(#name {
-                        #(#field_assignments),*
-                    })
+                    #decode
                 }
             }
         }
@@ -638,11 +494,7 @@ fn generate_from_payload_impl(name: &Ident, fields: &Fields, lifetime: &Lifetime
         quote! {
             impl<#lifetime, #context> npsd::FromPayload<#lifetime, #context> for #name {
                 fn from_payload<#mw: npsd::Middleware<#lifetime>>(ctx: &mut #context, next: &mut #mw) -> Result<Self, npsd::Error> {
-                    let byte: u8 = next.from_payload(ctx)?;
-
-                    Ok(#name {
-                        #(#field_assignments),*
-                    })
+                    #decode
                 }
             }
         }
@@ -662,20 +514,20 @@ fn generate_payload_impl(name: &Ident, lifetime: &Lifetime, context: &Ident, int
     }
 }
 
-#[proc_macro_derive(AsyncSchema)]
+#[proc_macro_derive(AsyncSchema, attributes(npsd))]
 pub fn async_schema_public_impl(input: TokenStream) -> TokenStream {
     async_schema_impl(input, false)
 }
 
 #[doc(hidden)]
-#[proc_macro_derive(AsyncSchemaInternal)]
+#[proc_macro_derive(AsyncSchemaInternal, attributes(npsd))]
 pub fn async_schema_internal_impl(input: TokenStream) -> TokenStream {
     async_schema_impl(input, true)
 }
 
 #[doc(hidden)]
 fn async_schema_impl(input: TokenStream, internal: bool) -> TokenStream {
-    let DeriveInput { ident, data, generics, .. } = parse_macro_input!(input);
+    let DeriveInput { ident, data, generics, attrs, .. } = parse_macro_input!(input);
     let (_, ty_generics, where_clause) = generics.split_for_impl();
 
     let (lifetime_exist, lifetime) = resolve_lifetime(&generics, DEFAULT_LIFETIME);
@@ -692,6 +544,12 @@ fn async_schema_impl(input: TokenStream, internal: bool) -> TokenStream {
     context_param.bounds.push(send_bound);
     context_param.bounds.push(sync_bound);
 
+    match attr::versioned(&data) {
+        Ok(true) => context_param.bounds.push(has_version_bound(internal)),
+        Ok(false) => {},
+        Err(error) => return error.to_compile_error().into(),
+    }
+
     context_generics.params.push(GenericParam::Type(context_param));
 
     let mut into_generics = context_generics.clone();
@@ -713,205 +571,20 @@ fn async_schema_impl(input: TokenStream, internal: bool) -> TokenStream {
     async_schema_payload_impl(&mut payload_generics, internal, &lifetime, &context);
     let (payload_impl, _, _) = payload_generics.split_for_impl();
 
-    let sender_block = match data.clone() {
-        Data::Struct(data_struct) => {
-            let fields = match data_struct.fields {
-                Fields::Named(FieldsNamed { named, .. }) => {
-                    named.iter().map(|f| {
-                        let name = &f.ident;
-                        let span = f.span();
-
-                        quote_spanned! { span =>
-                            next.poll_into_payload(&self.#name, ctx).await?;
-                        }
-                    }).collect::<Vec<_>>()
-                },
-
-                Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-                    unnamed.iter().enumerate().map(|(i, _)| {
-                        let index = Index::from(i);
-                        let span = index.span();
-
-                        quote_spanned! { span =>
-                            next.poll_into_payload(&self.#index, ctx).await?;
-                        }
-                    }).collect::<Vec<_>>()
-                },
-
-                Fields::Unit => Vec::new(),
-            };
-
-            quote! { #( #fields )* }
-        },
-        Data::Enum(DataEnum { variants, .. }) => {
-            let variant_cases = variants.iter().enumerate().map(|(index, variant)| {
-                let variant_ident = &variant.ident;
-                let variant_span = variant.span(); 
-
-                match &variant.fields {
-                    Fields::Named(FieldsNamed { named, .. }) => {
-                        let (field_patterns, field_serializations): (Vec<_>, Vec<_>) = named.iter()
-                            .map(|f| {
-                                let name = f.ident.as_ref().unwrap();
-                                let span = name.span();
-                                let pattern = quote_spanned! { span => #name };
-                                let serialization = quote_spanned! { span => next.poll_into_payload(&#name, ctx).await?; };
-                                (pattern, serialization)
-                            }).unzip();
-
-                        quote_spanned! { variant_span => 
-                            #ident::#variant_ident { #(#field_patterns,)* } => {
-                                next.poll_into_payload(&#index, ctx).await?;
-                                #( #field_serializations )*
-                            }
-                        }
-                    },
-                    Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-                        let (field_patterns, field_serializations): (Vec<_>, Vec<_>) = unnamed.iter().enumerate()
-                            .map(|(i, _)| {
-                                let field_name = Ident::new(&format!("__self_{}", i), Span::call_site());
-                                let pattern = quote! { #field_name };
-                                let serialization = quote! { next.poll_into_payload(&#field_name, ctx).await?; };
-                                (pattern, serialization)
-                            }).unzip();
-                    
-                        quote_spanned! { variant_span => 
-                            #ident::#variant_ident( #( #field_patterns, )* ) => {
-                                next.poll_into_payload(&#index, ctx).await?;
-                                #( #field_serializations )*
-                            }
-                        }
-                    },
-                    Fields::Unit => {
-                        quote_spanned! { variant_span => 
-                            #ident::#variant_ident => {
-                                next.poll_into_payload(&#index, ctx).await?;
-                            }
-                        }
-                    },
-                }
-            });
-
-            quote! {
-                match self {
-                    #( #variant_cases, )*
-                }
-            }
-        },
-        Data::Union(_) => {
-            return quote! {
-                compile_error!("Union types are not supported by this macro.");
-            }.into();
+    let (tag_type, tags) = match &data {
+        Data::Enum(DataEnum { variants, .. }) => match attr::variant_tags(&attrs, variants) {
+            Ok(tags) => tags,
+            Err(error) => return error.to_compile_error().into(),
         },
+        _ => (attr::TagType::Usize, Vec::new()),
     };
+    let tag_ty = tag_type.ty();
 
-    let receiver_block = match data.clone() {
-        Data::Struct(data_struct) => {
-            match data_struct.fields {
-                Fields::Named(FieldsNamed { named, .. }) => {
-                    let fields = named.iter().map(|f| {
-                        let field = &f.ident;
-                        let ty = &f.ty;
-                        let span = f.span();
-
-                        quote_spanned! { span =>
-                            #field: next.poll_from_payload::<#context, #ty>(ctx).await? // as #ty
-                        }
-                    }).collect::<Vec<_>>();
-
-                    quote! {
-                        Ok(#ident {
-                            #( #fields ),*
-                        })
-                    }
-                },
-                Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-                    let fields = unnamed.iter().enumerate().map(|(_, f)| {
-                        let ty = &f.ty;
-
-                        quote! {
-                            next.poll_from_payload::<#context, #ty>(ctx).await? // as #ty
-                        }
-                    }).collect::<Vec<_>>();
-
-                    quote! {
-                        Ok(#ident (
-                            #( #fields ),*
-                        ))
-                    }
-                },
-                Fields::Unit => {
-                    quote! {
-                        Ok(#ident)
-                    }
-                },
-            }
-        },
-        Data::Enum(DataEnum { variants, .. }) => {
-            let match_variants = variants.iter().enumerate().map(|(index, variant)| {
-                let variant_ident = &variant.ident;
-                
-                match &variant.fields {
-                    Fields::Named(FieldsNamed { named, .. }) => {
-                        let deserializations = named.iter().map(|f| {
-                            let name = &f.ident;
-                            let ty = &f.ty;
-        
-                            quote! {
-                                #name: next.poll_from_payload::<#context, #ty>(ctx).await? // as #ty
-                            }
-                        });
-                        
-                        quote! {
-                            #index => Ok(#ident::#variant_ident { #(#deserializations),* })
-                        }
-                    },
-                    Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-                        let deserializations = unnamed.iter().map(|f| {
-                            let ty = &f.ty;
-        
-                            quote! {
-                                next.poll_from_payload::<#context, #ty>(ctx).await? // as #ty
-                            }
-                        });
-                        
-                        quote! {
-                            #index => Ok(#ident::#variant_ident( #(#deserializations),* ))
-                        }
-                    },
-                    Fields::Unit => {
-                        quote! {
-                            #index => Ok(#ident::#variant_ident)
-                        }
-                    },
-                }
-            }).collect::<Vec<_>>();
-        
-            if internal {
-                quote! {
-                    let variant_index: usize = next.poll_from_payload(ctx).await?;
-            
-                    match variant_index {
-                        #(#match_variants,)*
-                        _ => Err(Error::UnknownVariant("Index out of bounds for enum".to_string())),
-                    }
-                }
-            } else {
-                quote! {
-                    let variant_index: usize = next.poll_from_payload(ctx).await?;
-            
-                    match variant_index {
-                        #(#match_variants,)*
-                        _ => Err(npsd::Error::UnknownVariant("Index out of bounds for enum".to_string())),
-                    }
-                }
-            }
-        },
-        Data::Union(_) => {
-            return quote! {
-                compile_error!("Union types are not supported by this macro.");
-            }.into();
-        },
+    let body = body::Body { ident: &ident, context: &context, asynchronous: true, internal };
+
+    let (sender_block, receiver_block) = match body.sender(&attrs, &data, &tags).and_then(|sender| Ok((sender, body.receiver(&attrs, &data, &tag_ty, &tags)?))) {
+        Ok(blocks) => blocks,
+        Err(error) => return error.to_compile_error().into(),
     };
 
     let gen = if internal {
@@ -954,13 +627,13 @@ fn async_schema_impl(input: TokenStream, internal: bool) -> TokenStream {
 }
 
 
-#[proc_macro_derive(AsyncBitmap)]
+#[proc_macro_derive(AsyncBitmap, attributes(npsd))]
 pub fn async_bitmap_derive(input: TokenStream) -> TokenStream {
     async_bitmap_impl(input, false)
 }
 
 #[doc(hidden)]
-#[proc_macro_derive(AsyncBitmapInternal)]
+#[proc_macro_derive(AsyncBitmapInternal, attributes(npsd))]
 pub fn async_bitmap_internal_derive(input: TokenStream) -> TokenStream {
     async_bitmap_impl(input, true)
 }
@@ -978,27 +651,22 @@ fn async_bitmap_impl(input: TokenStream, internal: bool) -> TokenStream {
         } 
     };
 
-    let field_count = match fields {
-        Fields::Named(ref named_fields) => named_fields.named.len(),
-        Fields::Unnamed(ref unnamed_fields) => unnamed_fields.unnamed.len(),
-        Fields::Unit => 0,
+    let layout = match bitmap::Layout::new(&ident, fields) {
+        Ok(layout) => layout,
+        Err(error) => return error.to_compile_error().into(),
     };
 
-    if field_count > 8 {
-        return quote! {
-            compile_error!("Bitmap can only be derived for structs with no more than 8 fields");
-        }.into();
-    }
-
     let lifetime = Lifetime::new(DEFAULT_LIFETIME, Span::call_site());
     let scope = Lifetime::new(DEFAULT_SCOPE_LIFETIME, Span::call_site());
 
     let context = Ident::new(DEFAULT_CONTEXT, Span::call_site());
     let mw = Ident::new(DEFAULT_MIDDLEWARE, Span::call_site());
 
-    let into_payload_impl = async_generate_into_payload_impl(&ident, &fields, &scope, &context, &mw, internal);
-    let from_payload_impl = async_generate_from_payload_impl(&ident, &fields, &lifetime, &context, &mw, internal);
-    let payload_impl = async_generate_payload_impl(&ident, &lifetime, &context, internal);
+    let body = body::Body { ident: &ident, context: &context, asynchronous: true, internal };
+
+    let into_payload_impl = async_generate_into_payload_impl(&ident, &layout, &body, &scope, &context, &mw, internal);
+    let from_payload_impl = async_generate_from_payload_impl(&ident, &layout, &body, &lifetime, &context, &mw, internal);
+    let payload_impl = async_generate_payload_impl(&ident, &lifetime,&context, internal);
 
     let expanded = quote! {
         #into_payload_impl
@@ -1010,42 +678,15 @@ fn async_bitmap_impl(input: TokenStream, internal: bool) -> TokenStream {
 }
 
 #[doc(hidden)]
-fn async_generate_into_payload_impl(name: &Ident, fields: &Fields, scope: &Lifetime, context: &Ident, mw: &Ident, internal: bool) -> proc_macro2::TokenStream {
-    let field_conversions = match fields {
-        Fields::Named(FieldsNamed { named, .. }) => {
-            named.iter().enumerate().map(|(i, f)| {
-                let field_name = &f.ident;
-                let bit_position = i as u8;
-
-                quote! {
-                    if self.#field_name {
-                        byte |= 1 << #bit_position;
-                    }
-                }
-            }).collect::<Vec<_>>()
-        },
-        Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-            unnamed.iter().enumerate().map(|(i, _)| {
-                let field_name = Index::from(i);
-                let bit_position = i as u8;
-
-                quote! {
-                    if self.#field_name {
-                        byte |= 1 << #bit_position;
-                    }
-                }
-            }).collect::<Vec<_>>()
-        },
-        Fields::Unit => vec![],
-    };
+fn async_generate_into_payload_impl(name: &Ident, layout: &bitmap::Layout, body: &body::Body, scope: &Lifetime, context: &Ident, mw: &Ident, internal: bool) -> proc_macro2::TokenStream {
+    let next = Ident::new("next", Span::call_site());
+    let encode = layout.encode(body, &next);
 
     if internal {
         quote! {
             impl<#context: Send + Sync> AsyncIntoPayload<#context> for #name {
                 async fn poll_into_payload<#scope, #mw: AsyncMiddleware<#scope>>(&self, ctx: &mut #context, next: &mut #mw) -> Result<(), Error> {
-                    let mut byte: u8 = 0;
-                    #(#field_conversions)*
-                    next.poll_into_payload(&byte, ctx).await
+                    #encode
                 }
             }
         }
@@ -1053,9 +694,7 @@ fn async_generate_into_payload_impl(name: &Ident, fields: &Fields, scope: &Lifet
         quote! {
             impl<#context: Send + Sync> npsd::AsyncIntoPayload<#context> for #name {
                 async fn poll_into_payload<#scope, #mw: npsd::AsyncMiddleware<#scope>>(&self, ctx: &mut #context, next: &mut #mw) -> Result<(), npsd::Error> {
-                    let mut byte: u8 = 0;
-                    #(#field_conversions)*
-                    next.poll_into_payload(&byte, ctx).await
+                    #encode
                 }
             }
         }
@@ -1063,40 +702,15 @@ fn async_generate_into_payload_impl(name: &Ident, fields: &Fields, scope: &Lifet
 }
 
 #[doc(hidden)]
-fn async_generate_from_payload_impl(name: &Ident, fields: &Fields, lifetime: &Lifetime, context: &Ident, mw: &Ident, internal: bool) -> proc_macro2::TokenStream {
-    let field_assignments = match fields {
-        Fields::Named(FieldsNamed { named, .. }) => {
-            named.iter().enumerate().map(|(i, f)| {
-                let field_name = &f.ident;
-                let bit_position = i as u8;
-
-                quote! {
-                    #field_name: (byte & (1 << #bit_position)) != 0
-                }
-            }).collect::<Vec<_>>()
-        },
-        Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-            unnamed.iter().enumerate().map(|(i, _)| {
-                let field_name = Index::from(i);
-                let bit_position = i as u8;
-
-                quote! {
-                    #field_name: (byte & (1 << #bit_position)) != 0
-                }
-            }).collect::<Vec<_>>()
-        },
-        Fields::Unit => vec![],
-    };
+fn async_generate_from_payload_impl(name: &Ident, layout: &bitmap::Layout, body: &body::Body, lifetime: &Lifetime, context: &Ident, mw: &Ident, internal: bool) -> proc_macro2::TokenStream {
+    let next = Ident::new("next", Span::call_site());
+    let decode = layout.decode(body, &next, name);
 
     if internal {
         quote! {
             impl<#lifetime, #context: Send + Sync> AsyncFromPayload<#lifetime, #context> for #name {
                 async fn poll_from_payload<#mw: AsyncMiddleware<#lifetime>>(ctx: &mut #context, next: &mut #mw) -> Result<Self, Error> {
-                    let byte: u8 = next.poll_from_payload(ctx).await?;
-
-                    Ok(#name {
-                        #(#field_assignments),*
-                    })
+                    #decode
                 }
             }
         }
@@ -1104,11 +718,7 @@ fn async_generate_from_payload_impl(name: &Ident, fields: &Fields, lifetime: &Li
         quote! {
             impl<#lifetime, #context: Send + Sync> npsd::AsyncFromPayload<#lifetime, #context> for #name {
                 async fn poll_from_payload<#mw: npsd::AsyncMiddleware<#lifetime>>(ctx: &mut #context, next: &mut #mw) -> Result<Self, npsd::Error> {
-                    let byte: u8 = next.poll_from_payload(ctx).await?;
-
-                    Ok(#name {
-                        #(#field_assignments),*
-                    })
+                    #decode
                 }
             }
         }
@@ -1126,4 +736,377 @@ fn async_generate_payload_impl(name: &Ident, lifetime: &Lifetime, context: &Iden
             impl<#lifetime, #context: Send + Sync> npsd::AsyncPayload<#lifetime, #context> for #name {}
         }
     }
-}
\ No newline at end of file
+}
+
+/// Implements `npsd::DeltaPayload` for a struct, encoding it as the fields that changed since a
+/// baseline. Fields must implement `PartialEq`, `#[npsd(delta)]` fields `npsd::DeltaPayload`.
+#[proc_macro_derive(DeltaSchema, attributes(npsd))]
+pub fn delta_schema_derive(input: TokenStream) -> TokenStream {
+    delta_schema_impl(input, false)
+}
+
+/// Implements `npsd::AsyncDeltaPayload` for a struct, see `DeltaSchema`.
+#[proc_macro_derive(AsyncDeltaSchema, attributes(npsd))]
+pub fn async_delta_schema_derive(input: TokenStream) -> TokenStream {
+    delta_schema_impl(input, true)
+}
+
+#[doc(hidden)]
+fn delta_schema_impl(input: TokenStream, asynchronous: bool) -> TokenStream {
+    let DeriveInput { ident, data, generics, .. } = parse_macro_input!(input);
+    let (_, ty_generics, where_clause) = generics.split_for_impl();
+
+    let (lifetime_exist, lifetime) = resolve_lifetime(&generics, DEFAULT_LIFETIME);
+    let context = Ident::new(DEFAULT_CONTEXT, Span::call_site());
+    let scope = Lifetime::new(DEFAULT_SCOPE_LIFETIME, Span::call_site());
+    let mw = Ident::new(DEFAULT_MIDDLEWARE, Span::call_site());
+    let mut delta_generics = generics.clone();
+
+    let mut context_param: TypeParam = syn::parse_quote!(#context);
+
+    if asynchronous {
+        context_param.bounds.push(syn::parse_quote!(Send));
+        context_param.bounds.push(syn::parse_quote!(Sync));
+    }
+
+    match attr::versioned(&data) {
+        Ok(true) => context_param.bounds.push(has_version_bound(false)),
+        Ok(false) => {},
+        Err(error) => return error.to_compile_error().into(),
+    }
+
+    delta_generics.params.push(GenericParam::Type(context_param));
+
+    if !lifetime_exist {
+        delta_generics.params.insert(0, GenericParam::Lifetime(LifetimeParam::new(lifetime.clone())));
+    }
+
+    if asynchronous {
+        async_schema_payload_impl(&mut delta_generics, false, &lifetime, &context);
+    } else {
+        schema_payload_impl(&mut delta_generics, false, &lifetime, &context);
+    }
+
+    for param in delta_generics.params.iter_mut() {
+        if let GenericParam::Type(type_param) = param {
+            if type_param.ident != DEFAULT_CONTEXT && !has_bound(&type_param.bounds, "PartialEq") {
+                type_param.bounds.push(parse_quote!(PartialEq));
+            }
+        }
+    }
+
+    let (delta_impl, _, _) = delta_generics.split_for_impl();
+
+    let body = body::Body { ident: &ident, context: &context, asynchronous, internal: false };
+
+    let (encode, apply) = match delta::Delta::new(&body, &data) {
+        Ok(delta) => (delta.encode(&lifetime), delta.apply(&lifetime)),
+        Err(error) => return error.to_compile_error().into(),
+    };
+
+    let gen = if asynchronous {
+        quote! {
+            impl #delta_impl npsd::AsyncDeltaPayload<#lifetime, #context> for #ident #ty_generics #where_clause {
+                async fn poll_encode_delta<#scope, #mw: npsd::AsyncMiddleware<#scope>>(&self, baseline: &Self, ctx: &mut #context, next: &mut #mw) -> Result<(), npsd::Error> {
+                    #encode
+                }
+
+                async fn poll_apply_delta<#mw: npsd::AsyncMiddleware<#lifetime>>(&mut self, ctx: &mut #context, next: &mut #mw) -> Result<(), npsd::Error> {
+                    #apply
+                }
+            }
+        }
+    } else {
+        quote! {
+            impl #delta_impl npsd::DeltaPayload<#lifetime, #context> for #ident #ty_generics #where_clause {
+                fn encode_delta<#scope, #mw: npsd::Middleware<#scope>>(&self, baseline: &Self, ctx: &mut #context, next: &mut #mw) -> Result<(), npsd::Error> {
+                    #encode
+                }
+
+                fn apply_delta<#mw: npsd::Middleware<#lifetime>>(&mut self, ctx: &mut #context, next: &mut #mw) -> Result<(), npsd::Error> {
+                    #apply
+                }
+            }
+        }
+    };
+
+    gen.into()
+}
+
+/// Implements `npsd::BitField` for an enum without fields, so it can be packed into a `Bitmap`
+/// with `#[npsd(bits = N)]`. Variants map to their tags, see `#[npsd(tag = N)]`.
+#[proc_macro_derive(BitField, attributes(npsd))]
+pub fn bit_field_derive(input: TokenStream) -> TokenStream {
+    let DeriveInput { ident, attrs, data, .. } = parse_macro_input!(input);
+
+    let variants = match data {
+        Data::Enum(DataEnum { variants, .. }) => variants,
+        _ => {
+            return quote! {
+                compile_error!("BitField can only be derived for enums without fields");
+            }.into();
+        }
+    };
+
+    if let Some(variant) = variants.iter().find(|variant| !variant.fields.is_empty()) {
+        return syn::Error::new(variant.span(), "BitField can only be derived for enums without fields").to_compile_error().into();
+    }
+
+    let tags = match attr::variant_tags(&attrs, &variants) {
+        Ok((_, tags)) => tags,
+        Err(error) => return error.to_compile_error().into(),
+    };
+
+    let mut names = Vec::with_capacity(variants.len());
+    let mut values = Vec::with_capacity(variants.len());
+
+    for (variant, tag) in variants.iter().zip(tags) {
+        let value = match tag.map(|tag| tag.base10_parse::<u64>()) {
+            Some(Ok(value)) => value,
+            Some(Err(error)) => return error.to_compile_error().into(),
+            None => return syn::Error::new(variant.span(), "BitField can't be derived for open enums").to_compile_error().into(),
+        };
+
+        names.push(&variant.ident);
+        values.push(syn::LitInt::new(&format!("{}u128", value), Span::call_site()));
+    }
+
+    let expanded = quote! {
+        impl npsd::BitField for #ident {
+            fn to_bits(&self) -> u128 {
+                match self {
+                    #( #ident::#names => #values, )*
+                }
+            }
+
+            fn from_bits(bits: u128) -> Result<Self, npsd::Error> {
+                match bits {
+                    #( #values => Ok(#ident::#names), )*
+                    _ => Err(npsd::Error::UnknownVariant(format!("Unknown tag `{}` for enum", bits))),
+                }
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Implements `npsd::Diff` for a struct or an enum. Structs are diffed field by field, and the
+/// fields that aren't skipped must implement `npsd::Diff` and `PartialEq`. Enums change as a whole,
+/// so they must implement `PartialEq` and the payload traits.
+#[proc_macro_derive(Diff, attributes(npsd))]
+pub fn diff_derive(input: TokenStream) -> TokenStream {
+    let DeriveInput { ident, data, generics, .. } = parse_macro_input!(input);
+    let (_, ty_generics, where_clause) = generics.split_for_impl();
+
+    let (lifetime_exist, lifetime) = resolve_lifetime(&generics, DEFAULT_LIFETIME);
+    let context = Ident::new(DEFAULT_CONTEXT, Span::call_site());
+    let mut diff_generics = generics.clone();
+
+    for param in diff_generics.params.iter_mut() {
+        if let GenericParam::Type(type_param) = param {
+            if !has_bound(&type_param.bounds, "Diff") {
+                type_param.bounds.push(parse_quote!(npsd::Diff<#lifetime, #context>));
+            }
+
+            if !has_bound(&type_param.bounds, "PartialEq") {
+                type_param.bounds.push(parse_quote!(PartialEq));
+            }
+        }
+    }
+
+    diff_generics.params.push(GenericParam::Type(syn::parse_quote!(#context)));
+
+    if !lifetime_exist {
+        diff_generics.params.insert(0, GenericParam::Lifetime(LifetimeParam::new(lifetime.clone())));
+    }
+
+    let (diff_impl, _, _) = diff_generics.split_for_impl();
+
+    let (diff, apply) = match &data {
+        Data::Struct(data) => {
+            let members = match body::Body::members(&data.fields, &ident.to_string(), false) {
+                Ok(members) => members,
+                Err(error) => return error.to_compile_error().into(),
+            };
+
+            let encoded: Vec<_> = members.iter().filter(|member| !member.attrs.skip).collect();
+            let bindings: Vec<_> = encoded.iter().map(|member| &member.binding).collect();
+            let positions: Vec<_> = (0..encoded.len()).collect();
+
+            let diff = quote! {
+                #(
+                    if self.#bindings != new.#bindings {
+                        path.push(npsd::Segment::Field(#positions));
+                        npsd::Diff::<#lifetime, #context>::diff(&self.#bindings, &new.#bindings, path, ops, ctx)?;
+                        path.pop();
+                    }
+                )*
+
+                Ok(())
+            };
+
+            let apply = quote! {
+                match path {
+                    #(
+                        [npsd::Segment::Field(#positions), rest @ ..] => npsd::Diff::<#lifetime, #context>::apply(&mut self.#bindings, rest, op, ctx),
+                    )*
+                    _ => Err(npsd::patch::invalid(path, op)),
+                }
+            };
+
+            (diff, apply)
+        },
+        Data::Enum(_) => (
+            quote! { npsd::patch::diff_value(self, new, path, ops, ctx) },
+            quote! { npsd::patch::apply_value(self, path, op, ctx) },
+        ),
+        Data::Union(_) => return syn::Error::new(ident.span(), "Union types are not supported by this macro.").to_compile_error().into(),
+    };
+
+    let expanded = quote! {
+        impl #diff_impl npsd::Diff<#lifetime, #context> for #ident #ty_generics #where_clause {
+            fn diff(&self, new: &Self, path: &mut Vec<npsd::Segment>, ops: &mut Vec<npsd::Op>, ctx: &mut #context) -> Result<(), npsd::Error> {
+                #diff
+            }
+
+            fn apply(&mut self, path: &#lifetime [npsd::Segment], op: &#lifetime npsd::Op, ctx: &mut #context) -> Result<(), npsd::Error> {
+                #apply
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Generates an RPC client stub and server dispatcher for a trait.
+///
+/// For `trait Inventory`, this generates `InventoryClient<T: npsd::Transport>`, with an `async`
+/// method per trait method returning `Result<R, npsd::Error>`, and `InventoryServer<S: Inventory>`,
+/// implementing `npsd::Service`. Methods take `&self` and owned arguments, and can be `async`.
+/// Methods are identified by the hash of `Trait::method`, so they can be reordered freely.
+///
+/// `npsd::serve` answers the requests of a connection one at a time, in the order they arrive.
+/// A client can have several calls in flight, but a slow method holds up the calls behind it;
+/// serve each connection separately, and hand long work off from the method, to keep others
+/// responsive.
+#[proc_macro_attribute]
+pub fn service(_attr: TokenStream, input: TokenStream) -> TokenStream {
+    let item: ItemTrait = parse_macro_input!(input);
+
+    let vis = &item.vis;
+    let ident = &item.ident;
+    let client = format_ident!("{}Client", ident);
+    let server = format_ident!("{}Server", ident);
+
+    if !item.generics.params.is_empty() {
+        return quote_spanned! { item.generics.span() =>
+            compile_error!("Service traits can't be generic");
+        }.into();
+    }
+
+    let mut client_methods = Vec::new();
+    let mut server_arms = Vec::new();
+
+    for trait_item in &item.items {
+        let method = match trait_item {
+            TraitItem::Fn(method) => method,
+            _ => continue,
+        };
+
+        let sig = &method.sig;
+        let name = &sig.ident;
+
+        if !sig.generics.params.is_empty() {
+            return quote_spanned! { sig.generics.span() =>
+                compile_error!("Service methods can't be generic");
+            }.into();
+        }
+
+        match sig.receiver() {
+            Some(receiver) if receiver.reference.is_some() && receiver.mutability.is_none() => {},
+            _ => {
+                return quote_spanned! { sig.span() =>
+                    compile_error!("Service methods must take `&self`");
+                }.into();
+            },
+        }
+
+        let (names, types): (Vec<_>, Vec<_>) = sig.inputs.iter().filter_map(|arg| match arg {
+            FnArg::Typed(arg) => Some(arg),
+            FnArg::Receiver(_) => None,
+        }).enumerate().map(|(i, arg)| {
+            let name = match &*arg.pat {
+                Pat::Ident(pat) => pat.ident.clone(),
+                _ => format_ident!("arg{}", i),
+            };
+
+            (name, (*arg.ty).clone())
+        }).unzip();
+
+        if names.len() > 8 {
+            return quote_spanned! { sig.inputs.span() =>
+                compile_error!("Service methods can't take more than 8 arguments");
+            }.into();
+        }
+
+        let output = match &sig.output {
+            ReturnType::Default => quote! { () },
+            ReturnType::Type(_, ty) => quote! { #ty },
+        };
+
+        let path = format!("{}::{}", ident, name);
+        let hash = quote! { npsd::PayloadConstHash(#path.as_bytes()) };
+        let call_await = sig.asyncness.map(|_| quote! { .await });
+        let docs = method.attrs.iter().filter(|attr| attr.path().is_ident("doc"));
+
+        client_methods.push(quote! {
+            #(#docs)*
+            pub async fn #name(&self, #( #names: #types ),*) -> Result<#output, npsd::Error> {
+                self.client.call(#hash, &( #( #names, )* )).await
+            }
+        });
+
+        server_arms.push(quote! {
+            if method == #hash {
+                let ( #( #names, )* ): ( #( #types, )* ) = npsd::decode_message(args).await?;
+                let value: #output = self.0.#name( #( #names ),* ) #call_await;
+
+                return npsd::encode_message(&value).await;
+            }
+        });
+    }
+
+    let client_doc = format!("The client stub of [`{}`], generated by `#[npsd::service]`.", ident);
+    let server_doc = format!("The server dispatcher of [`{}`], generated by `#[npsd::service]`.", ident);
+
+    let expanded = quote! {
+        #item
+
+        #[doc = #client_doc]
+        #vis struct #client<T: npsd::Transport> {
+            client: npsd::RpcClient<T>,
+        }
+
+        impl<T: npsd::Transport> #client<T> {
+            pub fn new(transport: T) -> Self {
+                Self { client: npsd::RpcClient::new(transport) }
+            }
+
+            #(#client_methods)*
+        }
+
+        #[doc = #server_doc]
+        #vis struct #server<S>(pub S);
+
+        impl<S: #ident + Send + Sync> npsd::Service for #server<S> {
+            async fn call(&self, method: u64, args: &[u8]) -> Result<Vec<u8>, npsd::Error> {
+                #(#server_arms)*
+
+                Err(npsd::Error::UnknownMethod(method))
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
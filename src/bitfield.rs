@@ -0,0 +1,49 @@
+use crate::Error;
+
+/// The `BitField` trait converts a value to and from the bits it occupies in a packed bitmap.
+///
+/// It is used for the `#[npsd(bits = N)]` fields of `#[derive(Bitmap)]` structs. It is implemented
+/// for `bool` and the unsigned integers, and can be derived with `#[derive(BitField)]` for enums
+/// without fields, which map to their tags.
+///
+/// ### Methods
+/// - `fn to_bits(&self) -> u128`:
+///     - Returns the value as an unsigned integer, the bitmap checks that it fits into its `N` bits.
+/// - `fn from_bits(bits: u128) -> Result<Self, Error>`:
+///     - Converts the bits read from a bitmap back into a value.
+pub trait BitField: Sized {
+    fn to_bits(&self) -> u128;
+    fn from_bits(bits: u128) -> Result<Self, Error>;
+}
+
+impl BitField for bool {
+    #[inline(always)]
+    fn to_bits(&self) -> u128 {
+        *self as u128
+    }
+
+    #[inline(always)]
+    fn from_bits(bits: u128) -> Result<Self, Error> {
+        Ok(bits != 0)
+    }
+}
+
+macro_rules! bitfield_unsigned {
+    ($($type:ty),*) => {
+        $(
+            impl BitField for $type {
+                #[inline(always)]
+                fn to_bits(&self) -> u128 {
+                    *self as u128
+                }
+
+                #[inline(always)]
+                fn from_bits(bits: u128) -> Result<Self, Error> {
+                    <$type>::try_from(bits).map_err(|_| Error::OutOfRange(format!("`{}` doesn't fit into `{}`", bits, stringify!($type))))
+                }
+            }
+        )*
+    };
+}
+
+bitfield_unsigned!(u8, u16, u32, u64, u128, usize);
@@ -0,0 +1,44 @@
+#[cfg(feature = "sync")]
+use crate::Middleware;
+
+#[cfg(feature = "async")]
+use crate::AsyncMiddleware;
+
+#[cfg(feature = "async")]
+use core::future::Future;
+
+use crate::Error;
+
+/// The `DeltaPayload` trait encodes a value as its changes from a baseline of the same type, and
+/// applies such changes to the baseline.
+///
+/// It is derived for structs with `#[derive(DeltaSchema)]`. A delta starts with a change mask with
+/// a bit per field that isn't skipped, stored like the flags of a `Bitmap`, followed by the changed
+/// fields in declaration order. A field is written as its own delta when it has `#[npsd(delta)]`,
+/// and whole otherwise, which is what collections and maps fall back to. Both sides must agree on
+/// the baseline, a delta applied to any other value produces garbage rather than an error.
+///
+/// ### Methods
+/// - `fn encode_delta<'b, M: Middleware<'b>>(&self, baseline: &Self, ctx: &mut C, next: &mut M) -> Result<(), Error>`:
+///     - Writes the fields of `self` that differ from `baseline`.
+/// - `fn apply_delta<M: Middleware<'a>>(&mut self, ctx: &mut C, next: &mut M) -> Result<(), Error>`:
+///     - Reads a delta written against `self` and updates `self` to the encoded value.
+#[cfg(feature = "sync")]
+pub trait DeltaPayload<'a, C> {
+    fn encode_delta<'b, M: Middleware<'b>>(&self, baseline: &Self, ctx: &mut C, next: &mut M) -> Result<(), Error>;
+    fn apply_delta<M: Middleware<'a>>(&mut self, ctx: &mut C, next: &mut M) -> Result<(), Error>;
+}
+
+/// The `AsyncDeltaPayload` trait is the asynchronous version of `DeltaPayload`, derived with
+/// `#[derive(AsyncDeltaSchema)]`.
+///
+/// ### Methods
+/// - `fn poll_encode_delta<'b, M: AsyncMiddleware<'b>>(&self, baseline: &Self, ctx: &mut C, next: &mut M) -> impl Future<Output = Result<(), Error>>`:
+///     - Polls the writing of the fields of `self` that differ from `baseline`.
+/// - `fn poll_apply_delta<M: AsyncMiddleware<'a>>(&mut self, ctx: &mut C, next: &mut M) -> impl Future<Output = Result<(), Error>>`:
+///     - Polls the reading of a delta written against `self`, updating `self` to the encoded value.
+#[cfg(feature = "async")]
+pub trait AsyncDeltaPayload<'a, C: Send + Sync>: Send + Sync {
+    fn poll_encode_delta<'b, M: AsyncMiddleware<'b>>(&self, baseline: &Self, ctx: &mut C, next: &mut M) -> impl Future<Output = Result<(), Error>>;
+    fn poll_apply_delta<M: AsyncMiddleware<'a>>(&mut self, ctx: &mut C, next: &mut M) -> impl Future<Output = Result<(), Error>>;
+}
@@ -66,4 +66,122 @@ pub enum Error {
 
     #[error("External error: {0}")]
     External(String),
+
+    #[error("I/O error: `{0}`")]
+    Io(String),
+
+    #[error("Unsupported operation: `{0}`")]
+    Unsupported(String),
+
+    #[error("Frame too large: `{size}` bytes, maximum is `{max}`")]
+    FrameTooLarge {
+        size: usize,
+        max: usize,
+    },
+
+    #[error("Limit exceeded: {limit} of `{found}`, maximum is `{max}`")]
+    LimitExceeded {
+        limit: String,
+        found: usize,
+        max: usize,
+    },
+
+    #[error("{error} at byte `{offset}` in `{}`", .path.join("."))]
+    Decode {
+        offset: usize,
+        path: Vec<String>,
+        error: Box<Error>,
+    },
+
+    #[error("Compression error: `{0}`")]
+    Compression(String),
+
+    #[error("Authentication failed: packet was tampered with or sealed with another key")]
+    AuthenticationFailed,
+
+    #[error("Checksum mismatch: expected `{expected:#x}`, found `{found:#x}`")]
+    ChecksumMismatch {
+        expected: u64,
+        found: u64,
+    },
+
+    #[error("Invalid fragment: `{0}`")]
+    Fragment(String),
+
+    #[error("Type mismatch: expected `{ty}` with hash `{expected:#018x}`, found hash `{found:#018x}`")]
+    TypeMismatch {
+        ty: String,
+        expected: u64,
+        found: u64,
+    },
+
+    #[error("Unknown type: no handler for hash `{0:#018x}`")]
+    UnknownType(u64),
+
+    #[error("Unknown method: no method with hash `{0:#018x}`")]
+    UnknownMethod(u64),
+
+    #[error("Validation failed: `{0}`")]
+    Validation(String),
+
+    #[error("Missing field: `{0}`")]
+    MissingField(String),
+
+    #[error("Out of range: {0}")]
+    OutOfRange(String),
+
+    #[error("Invalid patch: {0}")]
+    InvalidPatch(String),
+}
+
+impl Error {
+    /// Attaches a location to a decode error.
+    ///
+    /// The first call records `offset` and wraps the error in `Error::Decode`. Later calls, made
+    /// while the error travels up through the enclosing types, only prepend their segments to the
+    /// path, so the path reads from the outermost type down to the failing field, e.g.
+    /// `Zoo.animals.Animal::Cat.name`. This is what the derive macros call for every field.
+    pub fn at(self, offset: usize, path: &[&str]) -> Self {
+        match self {
+            Error::Decode { offset, path: mut inner, error } => {
+                inner.splice(0..0, path.iter().map(|segment| segment.to_string()));
+                Error::Decode { offset, path: inner, error }
+            },
+            error => Error::Decode {
+                offset,
+                path: path.iter().map(|segment| segment.to_string()).collect(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Returns the byte offset of a located decode error.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            Error::Decode { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// Returns the type and field path of a located decode error.
+    pub fn path(&self) -> Option<&[String]> {
+        match self {
+            Error::Decode { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying error, without its location.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::Decode { error, .. } => error.kind(),
+            error => error,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error.to_string())
+    }
 }
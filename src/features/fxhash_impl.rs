@@ -30,6 +30,7 @@ impl<'a, C, K: FromPayload<'a, C>, V: FromPayload<'a, C>> FromPayload<'a, C> for
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut map = FxHashMap::default();
         let count: usize = next.from_payload(ctx)?;
+        next.reserve::<(K, V)>(count)?;
 
         for _ in 0..count {
             let key: K = next.from_payload(ctx)?;
@@ -67,6 +68,7 @@ impl<'a, C: Send + Sync, K: AsyncFromPayload<'a, C>, V: AsyncFromPayload<'a, C>>
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut map = FxHashMap::default();
         let count: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<(K, V)>(count).await?;
 
         for _ in 0..count {
             let key: K = next.poll_from_payload(ctx).await?;
@@ -108,6 +110,7 @@ impl<'a, C, K: FromPayload<'a, C>> FromPayload<'a, C> for FxHashSet<K>
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut set = FxHashSet::default();
         let count: usize = next.from_payload(ctx)?;
+        next.reserve::<K>(count)?;
 
         for _ in 0..count {
             let key: K = next.from_payload(ctx)?;
@@ -148,6 +151,7 @@ impl<'a, C: Send + Sync, K: AsyncFromPayload<'a, C>> AsyncFromPayload<'a, C> for
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut set = FxHashSet::default();
         let count: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<K>(count).await?;
 
         for _ in 0..count {
             let key: K = next.poll_from_payload(ctx).await?;
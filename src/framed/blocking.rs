@@ -0,0 +1,135 @@
+use std::io::{Read, Write};
+
+use crate::{Error, FromPayload, IntoPayload};
+
+use super::{decode_frame, encode_frame, frame_header, DEFAULT_MAX_FRAME_SIZE};
+
+/// Reads length-delimited frames from any `std::io::Read`.
+///
+/// The length prefix is read one byte at a time, so wrap unbuffered readers such as
+/// `TcpStream` in a `BufReader`. The frame body is read into a buffer that is reused between
+/// frames and grows with the data, so a bogus length can't trigger a large up-front allocation.
+#[derive(Debug)]
+pub struct FramedReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    max_frame_size: usize,
+}
+
+impl<R: Read> FramedReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_max_frame_size(inner, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(inner: R, max_frame_size: usize) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            max_frame_size,
+        }
+    }
+
+    /// Reads one complete frame and decodes it into `T`.
+    pub fn read<C, T: for<'a> FromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        self.buf.clear();
+
+        let len = loop {
+            let mut byte = [0u8; 1];
+            self.inner.read_exact(&mut byte)?;
+            self.buf.push(byte[0]);
+
+            if let Some((_, len)) = frame_header(&self.buf)? {
+                break len;
+            }
+        };
+
+        if len > self.max_frame_size {
+            return Err(Error::FrameTooLarge { size: len, max: self.max_frame_size });
+        }
+
+        self.buf.clear();
+
+        let found = (&mut self.inner).take(len as u64).read_to_end(&mut self.buf)?;
+
+        if found != len {
+            return Err(Error::InvalidLength { expected: len, found });
+        }
+
+        decode_frame(&self.buf, ctx)
+    }
+
+    #[inline(always)]
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Writes length-delimited frames into any `std::io::Write`.
+///
+/// Each frame is encoded in memory first, since its length has to be known before the prefix
+/// can be written, and is then handed to the writer with a single `write_all`.
+#[derive(Debug)]
+pub struct FramedWriter<W> {
+    inner: W,
+    max_frame_size: usize,
+}
+
+impl<W: Write> FramedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_max_frame_size(inner, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(inner: W, max_frame_size: usize) -> Self {
+        Self {
+            inner,
+            max_frame_size,
+        }
+    }
+
+    /// Encodes `value` and writes it as one frame.
+    pub fn write<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        let frame = encode_frame(value, ctx, self.max_frame_size)?;
+
+        Ok(self.inner.write_all(frame.as_slice())?)
+    }
+
+    #[inline(always)]
+    pub fn flush(&mut self) -> Result<(), Error> {
+        Ok(self.inner.flush()?)
+    }
+
+    #[inline(always)]
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
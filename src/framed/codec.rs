@@ -0,0 +1,92 @@
+use std::marker::PhantomData;
+
+use bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{Error, FromPayload, IntoPayload};
+
+use super::{decode_frame, encode_frame, frame_header, DEFAULT_MAX_FRAME_SIZE};
+
+/// A `tokio_util` codec for length-delimited `Payload` frames. Requires the `codec` feature to be enabled.
+///
+/// `Decoder::decode` returns `Ok(None)` until the length prefix and the whole body are buffered,
+/// and only then runs `FromPayload` on the body. Since the codec traits take no context argument,
+/// the codec owns the context `C` used for both directions.
+#[derive(Clone, Debug)]
+pub struct PayloadCodec<T, C> {
+    ctx: C,
+    max_frame_size: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T, C> PayloadCodec<T, C> {
+    pub fn new(ctx: C) -> Self {
+        Self::with_max_frame_size(ctx, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(ctx: C, max_frame_size: usize) -> Self {
+        Self {
+            ctx,
+            max_frame_size,
+            marker: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    #[inline(always)]
+    pub fn context(&self) -> &C {
+        &self.ctx
+    }
+
+    #[inline(always)]
+    pub fn context_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+}
+
+impl<T, C: Default> Default for PayloadCodec<T, C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
+
+impl<T: IntoPayload<C>, C> Encoder<T> for PayloadCodec<T, C> {
+    type Error = Error;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Error> {
+        let frame = encode_frame(&item, &mut self.ctx, self.max_frame_size)?;
+        dst.extend_from_slice(frame.as_slice());
+
+        Ok(())
+    }
+}
+
+impl<T: for<'a> FromPayload<'a, C>, C> Decoder for PayloadCodec<T, C> {
+    type Item = T;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, Error> {
+        let (header, len) = match frame_header(src)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        if len > self.max_frame_size {
+            return Err(Error::FrameTooLarge { size: len, max: self.max_frame_size });
+        }
+
+        if src.len() < header + len {
+            src.reserve(header + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(header);
+        let body = src.split_to(len);
+
+        decode_frame(&body, &mut self.ctx).map(Some)
+    }
+}
@@ -0,0 +1,156 @@
+use std::marker::PhantomData;
+
+use crate::{Error, FromPayload, Middleware, Next};
+
+use super::{needed, DEFAULT_MAX_FRAME_SIZE};
+
+/// Tries to decode one `T` from the start of `src`.
+///
+/// Returns `Ok(None)` when `src` ends before the value is complete, and otherwise the value
+/// together with the number of bytes it consumed. Any other error means the data is corrupt.
+/// `ctx` may have been touched by an attempt that ran out of data.
+pub fn try_decode<C, T: for<'a> FromPayload<'a, C>>(src: &[u8], ctx: &mut C) -> Result<Option<(T, usize)>, Error> {
+    match decode_partial(src, ctx)? {
+        Decoded::Complete(value, consumed) => Ok(Some((value, consumed))),
+        Decoded::Incomplete(_) => Ok(None),
+    }
+}
+
+enum Decoded<T> {
+    Complete(T, usize),
+    /// The input ran out, and needs to reach this length before the decode can get further.
+    Incomplete(usize),
+}
+
+fn decode_partial<C, T: for<'a> FromPayload<'a, C>>(src: &[u8], ctx: &mut C) -> Result<Decoded<T>, Error> {
+    if src.is_empty() {
+        return Ok(Decoded::Incomplete(1));
+    }
+
+    let mut next = Next::from(src);
+
+    match next.from_payload::<C, T>(ctx) {
+        Ok(value) => Ok(Decoded::Complete(value, next.position())),
+        Err(error) => match needed(&error, next.position(), src.len()) {
+            Some(needed) => Ok(Decoded::Incomplete(needed)),
+            None => Err(error),
+        },
+    }
+}
+
+/// A resumable, sans-IO decoder for unframed messages.
+///
+/// Bytes are handed over with `feed` as they arrive, and `poll_decode` yields the next complete
+/// message, or `Ok(None)` while more data is needed. Several back-to-back messages can be pulled
+/// from a single `feed`. An incomplete message is only decoded again once enough bytes have been
+/// fed to get past the read it stopped at, so a large string or byte buffer arriving in small
+/// pieces isn't decoded over and over. A message that needs more than `max_message_size` bytes
+/// fails with `Error::FrameTooLarge`, so a corrupt length can't stall the decoder forever.
+///
+/// Any other error means the buffered data is corrupt; call `clear` before reusing the decoder.
+#[derive(Clone, Debug)]
+pub struct Decoder<T, C = ()> {
+    ctx: C,
+    buf: Vec<u8>,
+    pos: usize,
+    consumed: usize,
+    needed: usize,
+    max_message_size: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T, C> Decoder<T, C> {
+    pub fn new(ctx: C) -> Self {
+        Self::with_max_message_size(ctx, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_message_size(ctx: C, max_message_size: usize) -> Self {
+        Self {
+            ctx,
+            buf: Vec::new(),
+            pos: 0,
+            consumed: 0,
+            needed: 0,
+            max_message_size,
+            marker: PhantomData,
+        }
+    }
+
+    /// Appends received bytes to the internal buffer.
+    pub fn feed(&mut self, data: &[u8]) {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Returns the number of bytes consumed by the most recently decoded message.
+    #[inline(always)]
+    pub fn last_consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Returns the bytes that have been fed but not yet decoded.
+    #[inline(always)]
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Drops all buffered bytes, e.g. after a corrupt message.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.pos = 0;
+        self.needed = 0;
+    }
+
+    #[inline(always)]
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size
+    }
+
+    #[inline(always)]
+    pub fn context(&self) -> &C {
+        &self.ctx
+    }
+
+    #[inline(always)]
+    pub fn context_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+}
+
+impl<T: for<'a> FromPayload<'a, C>, C> Decoder<T, C> {
+    /// Decodes the next complete message from the buffered bytes.
+    pub fn poll_decode(&mut self) -> Result<Option<T>, Error> {
+        if self.buf.len() - self.pos < self.needed {
+            return Ok(None);
+        }
+
+        match decode_partial(&self.buf[self.pos..], &mut self.ctx)? {
+            Decoded::Complete(value, consumed) => {
+                self.pos += consumed;
+                self.consumed = consumed;
+                self.needed = 0;
+
+                Ok(Some(value))
+            },
+            Decoded::Incomplete(needed) => {
+                if needed > self.max_message_size {
+                    return Err(Error::FrameTooLarge { size: needed, max: self.max_message_size });
+                }
+
+                self.needed = needed;
+
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl<T, C: Default> Default for Decoder<T, C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
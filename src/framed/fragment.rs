@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::{Error, FromPayload, IntoPayload, Middleware, Next};
+
+use super::{decode_frame, DEFAULT_MAX_FRAME_SIZE};
+
+/// The length of the header at the start of every fragment: a `u32` message id followed by a
+/// `u16` fragment index and a `u16` fragment count, all big-endian.
+pub const FRAGMENT_HEADER_LEN: usize = 8;
+
+/// The default time after which an incomplete message is dropped, 5 seconds.
+pub const DEFAULT_REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default maximum number of messages that are reassembled at the same time.
+pub const DEFAULT_MAX_PENDING: usize = 64;
+
+/// The header of one fragment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FragmentHeader {
+    pub message_id: u32,
+    pub index: u16,
+    pub count: u16,
+}
+
+impl FragmentHeader {
+    /// Splits `fragment` into its header and its part of the message.
+    pub fn parse(fragment: &[u8]) -> Result<(Self, &[u8]), Error> {
+        if fragment.len() < FRAGMENT_HEADER_LEN {
+            return Err(Error::InvalidLength { expected: FRAGMENT_HEADER_LEN, found: fragment.len() });
+        }
+
+        let (header, data) = fragment.split_at(FRAGMENT_HEADER_LEN);
+
+        let header = Self {
+            message_id: u32::from_be_bytes([header[0], header[1], header[2], header[3]]),
+            index: u16::from_be_bytes([header[4], header[5]]),
+            count: u16::from_be_bytes([header[6], header[7]]),
+        };
+
+        if header.count == 0 || header.index >= header.count {
+            return Err(Error::Fragment(format!("Fragment `{}` of `{}` in message `{}`", header.index, header.count, header.message_id)));
+        }
+
+        Ok((header, data))
+    }
+
+    fn write(&self, fragment: &mut Vec<u8>) {
+        fragment.extend_from_slice(&self.message_id.to_be_bytes());
+        fragment.extend_from_slice(&self.index.to_be_bytes());
+        fragment.extend_from_slice(&self.count.to_be_bytes());
+    }
+}
+
+/// Splits encoded messages into fragments that fit in `mtu` bytes, header included.
+///
+/// Every message gets the next message id, wrapping around after `u32::MAX`. A message that
+/// would need more than `u16::MAX` fragments fails with `Error::FrameTooLarge`.
+#[derive(Clone, Debug)]
+pub struct Fragmenter {
+    mtu: usize,
+    message_id: u32,
+}
+
+impl Fragmenter {
+    /// Panics if `mtu` can't hold the header and at least one byte of data.
+    pub fn new(mtu: usize) -> Self {
+        Self::with_message_id(mtu, 0)
+    }
+
+    /// Like `new`, but starts numbering messages from `message_id`.
+    pub fn with_message_id(mtu: usize, message_id: u32) -> Self {
+        assert!(mtu > FRAGMENT_HEADER_LEN, "MTU must be larger than the fragment header");
+
+        Self {
+            mtu,
+            message_id,
+        }
+    }
+
+    #[inline(always)]
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Splits an encoded message into fragments.
+    pub fn fragment(&mut self, body: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let chunk = self.mtu - FRAGMENT_HEADER_LEN;
+        let max = chunk * u16::MAX as usize;
+
+        if body.len() > max {
+            return Err(Error::FrameTooLarge { size: body.len(), max });
+        }
+
+        let message_id = self.message_id;
+        self.message_id = self.message_id.wrapping_add(1);
+
+        let count = body.len().div_ceil(chunk).max(1) as u16;
+        let mut fragments = Vec::with_capacity(count as usize);
+
+        for index in 0..count {
+            let start = index as usize * chunk;
+            let data = &body[start..body.len().min(start + chunk)];
+
+            let mut fragment = Vec::with_capacity(FRAGMENT_HEADER_LEN + data.len());
+            FragmentHeader { message_id, index, count }.write(&mut fragment);
+            fragment.extend_from_slice(data);
+
+            fragments.push(fragment);
+        }
+
+        Ok(fragments)
+    }
+
+    /// Encodes `value` and splits it into fragments.
+    pub fn encode<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<Vec<Vec<u8>>, Error> {
+        let mut next = Next::default();
+        next.into_payload(value, ctx)?;
+
+        self.fragment(next.as_slice())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Pending {
+    parts: BTreeMap<u16, Vec<u8>>,
+    count: u16,
+    size: usize,
+    started: Instant,
+}
+
+/// Collects fragments produced by `Fragmenter` back into complete messages.
+///
+/// Fragments may arrive in any order, and duplicates are ignored, including those of messages
+/// that were completed within the last `timeout`. Messages that are still incomplete after
+/// `timeout` are dropped. To bound memory, at most `max_pending` messages are reassembled at
+/// once, the oldest being dropped to make room, and a message whose fragments add up to more
+/// than `max_message_size` bytes, or that claims more fragments than it could have bytes, fails
+/// with `Error::FrameTooLarge`.
+#[derive(Clone, Debug)]
+pub struct Reassembler {
+    pending: HashMap<u32, Pending>,
+    completed: HashMap<u32, Instant>,
+    timeout: Duration,
+    max_pending: usize,
+    max_message_size: usize,
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+            completed: HashMap::new(),
+            timeout: DEFAULT_REASSEMBLY_TIMEOUT,
+            max_pending: DEFAULT_MAX_PENDING,
+            max_message_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Returns the number of incomplete messages.
+    #[inline(always)]
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Adds a received fragment, returning the message once all of its fragments are in.
+    pub fn push(&mut self, fragment: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let now = Instant::now();
+        self.expire_at(now);
+
+        let (header, data) = FragmentHeader::parse(fragment)?;
+
+        if self.completed.contains_key(&header.message_id) {
+            return Ok(None);
+        }
+
+        // Every fragment but the one of an empty message carries at least a byte.
+        if header.count as usize > self.max_message_size.max(1) {
+            return Err(Error::FrameTooLarge { size: header.count as usize, max: self.max_message_size });
+        }
+
+        if !self.pending.contains_key(&header.message_id) {
+            self.make_room();
+        }
+
+        let pending = self.pending.entry(header.message_id).or_insert_with(|| Pending {
+            parts: BTreeMap::new(),
+            count: header.count,
+            size: 0,
+            started: now,
+        });
+
+        if pending.count != header.count {
+            return Err(Error::Fragment(format!("Fragment count `{}` doesn't match `{}` in message `{}`", header.count, pending.count, header.message_id)));
+        }
+
+        if pending.parts.contains_key(&header.index) {
+            return Ok(None);
+        }
+
+        let size = pending.size + data.len();
+
+        if size > self.max_message_size {
+            self.pending.remove(&header.message_id);
+            return Err(Error::FrameTooLarge { size, max: self.max_message_size });
+        }
+
+        pending.parts.insert(header.index, data.to_vec());
+        pending.size = size;
+
+        if pending.parts.len() < pending.count as usize {
+            return Ok(None);
+        }
+
+        let pending = match self.pending.remove(&header.message_id) {
+            Some(pending) => pending,
+            None => return Ok(None),
+        };
+
+        self.completed.insert(header.message_id, now);
+
+        let mut body = Vec::with_capacity(pending.size);
+
+        for part in pending.parts.into_values() {
+            body.extend_from_slice(&part);
+        }
+
+        Ok(Some(body))
+    }
+
+    /// Adds a received fragment, decoding the message once all of its fragments are in.
+    pub fn decode<C, T: for<'a> FromPayload<'a, C>>(&mut self, fragment: &[u8], ctx: &mut C) -> Result<Option<T>, Error> {
+        match self.push(fragment)? {
+            Some(body) => decode_frame(&body, ctx).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Drops incomplete messages older than `timeout`, returning how many were dropped.
+    pub fn expire(&mut self) -> usize {
+        self.expire_at(Instant::now())
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.completed.clear();
+    }
+
+    fn expire_at(&mut self, now: Instant) -> usize {
+        let timeout = self.timeout;
+        let before = self.pending.len();
+
+        self.pending.retain(|_, pending| now.duration_since(pending.started) < timeout);
+        self.completed.retain(|_, completed| now.duration_since(*completed) < timeout);
+
+        before - self.pending.len()
+    }
+
+    fn make_room(&mut self) {
+        while self.pending.len() >= self.max_pending.max(1) {
+            let oldest = self.pending.iter().min_by_key(|(_, pending)| pending.started).map(|(id, _)| *id);
+
+            match oldest {
+                Some(id) => self.pending.remove(&id),
+                None => break,
+            };
+        }
+    }
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,96 @@
+//! Length-delimited framing for `Payload` messages.
+//!
+//! Every frame is the body length, encoded with the crate's `usize` varint, followed by the body
+//! as produced by `Next`. A frame is only decoded once all of its bytes have arrived, and frames
+//! whose declared length exceeds the configured maximum are rejected with `Error::FrameTooLarge`
+//! before any of the body is read.
+//!
+//! For event loops and non-blocking sockets, `Decoder` decodes unframed messages incrementally
+//! from whatever bytes have arrived so far.
+//!
+//! For datagram transports such as UDP, `Fragmenter` splits messages into fragments that fit in
+//! the MTU, and `Reassembler` puts them back together.
+
+use crate::{Error, FromPayload, IntoPayload, Middleware, Next};
+
+pub mod blocking;
+pub mod decoder;
+pub mod fragment;
+
+#[cfg(feature = "codec")]
+pub mod codec;
+
+pub use blocking::*;
+pub use decoder::*;
+pub use fragment::*;
+
+#[cfg(feature = "codec")]
+pub use codec::*;
+
+/// The default maximum size of a frame body, 8 MiB.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+/// Parses the length prefix at the start of `src`.
+///
+/// Returns `Ok(None)` while the prefix is still incomplete, otherwise the length of the prefix
+/// and the length of the body that follows it.
+pub fn frame_header(src: &[u8]) -> Result<Option<(usize, usize)>, Error> {
+    let mut next = Next::from(src);
+
+    match next.from_payload::<(), usize>(&mut ()) {
+        Ok(len) => Ok(Some((next.position(), len))),
+        Err(error) if needed(&error, next.position(), src.len()).is_some() => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns the length the input needs to reach for a decode that failed with `error` to get past
+/// the failing read, or `None` when `error` doesn't come from the input ending early.
+///
+/// Only an `Error::InvalidLength` whose `found` reaches exactly to the end of the `len` bytes of
+/// input counts, measured from the offset of the error, or from `position` when it isn't
+/// located. Any other error, including a length that overruns a part or a shorter buffer inside
+/// the payload, means the data is corrupt.
+pub(crate) fn needed(error: &Error, position: usize, len: usize) -> Option<usize> {
+    let position = error.offset().unwrap_or(position);
+
+    match error.kind() {
+        Error::InvalidLength { expected, found } if position.checked_add(*found) == Some(len) => {
+            Some(position.saturating_add(*expected))
+        },
+        _ => None,
+    }
+}
+
+/// Encodes `value` into a complete frame, length prefix included.
+pub fn encode_frame<C, T: IntoPayload<C>>(value: &T, ctx: &mut C, max_frame_size: usize) -> Result<Next<'static>, Error> {
+    let mut body = Next::default();
+    body.into_payload(value, ctx)?;
+
+    let size = body.as_slice().len();
+
+    if size > max_frame_size {
+        return Err(Error::FrameTooLarge { size, max: max_frame_size });
+    }
+
+    let mut frame = Next::with_mtu(size + 10);
+    frame.into_payload(&size, &mut ())?;
+    frame.write(body.as_slice())?;
+
+    Ok(frame)
+}
+
+/// Decodes a value from the body of a frame, without the length prefix.
+///
+/// The value must take up the whole body, bytes left over after it fail with
+/// `Error::InvalidLength`, with the bytes the value took as `expected`.
+pub fn decode_frame<C, T: for<'a> FromPayload<'a, C>>(body: &[u8], ctx: &mut C) -> Result<T, Error> {
+    let mut next = Next::from(body);
+    let value = next.from_payload(ctx)?;
+
+    if next.position() != body.len() {
+        return Err(Error::InvalidLength { expected: next.position(), found: body.len() });
+    }
+
+    Ok(value)
+}
@@ -164,6 +164,9 @@
 
 /// The `Middleware` trait defines methods for converting types to and from payloads of bytes.
 ///
+/// ### Associated Constants
+/// - `const PACKS_BITS: bool`: Whether `into_bits` packs values into their bits, as `NextBits` does. Wrappers like `Layered` only hand values to the inner `into_bits` when it does, `false` by default.
+///
 /// ### Methods
 ///
 /// - `fn into_payload<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error>`:
@@ -176,6 +179,28 @@
 ///     - Reads raw data from the handler. This method reads a specified number of bytes from the handler, splits the handler's data accordingly, and returns a slice of the read data.
 /// - `fn read_mut<'a, T>(&'a mut self, nbytes: usize) -> Result<&'a mut [T], Error>`:
 ///     - Reads raw data from the handler. This method reads a specified number of bytes from the handler, splits the handler's data accordingly, and returns a mutable slice of the read data.
+/// - `fn read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error>`:
+///     - Reads exactly `buf.len()` bytes from the handler into `buf`. Unlike `read`, the data is copied out so the handler does not need to keep it alive.
+/// - `fn read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error>`:
+///     - Reads a specified number of bytes from the handler into an owned vector.
+/// - `fn reserve<T>(&mut self, len: usize) -> Result<(), Error>`:
+///     - Called by collection and string impls with the length read from the wire, before anything is allocated for `len` elements of `T`. The default accepts any length.
+/// - `fn into_bits<C, T: IntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, ctx: &mut C) -> Result<(), Error>`:
+///     - Writes a value that fits into `bits` bits, such as a `bool`, an enum tag or a `#[npsd(range)]` field. Bit-packing handlers like `NextBits` write only those bits, the default writes the value's usual payload.
+/// - `fn from_bits<C, T: FromPayload<'a, C> + BitField>(&mut self, bits: u32, ctx: &mut C) -> Result<T, Error>`:
+///     - Reads a value written by `into_bits`.
+/// - `fn into_end(&mut self) -> Result<(), Error>`:
+///     - Called once a top-level value is written, by the handler itself or by a wrapper like `Layered` that drives `into_payload` on its own. `NextBits` pads its last byte here, the default does nothing.
+/// - `fn from_end(&mut self) -> Result<(), Error>`:
+///     - Called once a top-level value is read, see `into_end`.
+/// - `fn into_scope(&mut self) -> Result<(), Error>`:
+///     - Called between the length prefix and the bytes of a part, such as a `#[npsd(tagged)]` field or the body of an `#[npsd(open)]` variant. `NextBits` pads its last byte here, the default does nothing.
+/// - `fn from_scope(&mut self, len: usize) -> Result<(), Error>`:
+///     - Called before a part of `len` bytes is decoded in place. Handlers that track their position, like `Next`, fail reads and lengths past the end of the part, the default does nothing.
+/// - `fn from_scope_end(&mut self) -> Result<(), Error>`:
+///     - Called once the part is decoded, skipping what is left of it, such as trailing fields added by a newer peer.
+/// - `fn position(&self) -> usize`:
+///     - Returns the number of bytes processed so far, used to locate decode errors. Handlers that don't track it return 0.
 /// - `fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error>`:
 ///     - Pushes a boxed value into the handler, returning a reference to the stored value.
 /// - `fn push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error>`:
@@ -186,6 +211,8 @@
 ///     - Pushes a boxed array of values into the handler, returning a mutable reference to the stored array.
 #[cfg(feature = "sync")]
 pub trait Middleware<'a> {
+    const PACKS_BITS: bool = false;
+
     fn into_payload<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error>;
     fn from_payload<C, T: FromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error>;
 
@@ -193,6 +220,66 @@ pub trait Middleware<'a> {
     fn read<T>(&mut self, nbytes: usize) -> Result<&'a [T], Error>;
     fn read_mut<T>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error>;
 
+    #[inline(always)]
+    fn read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error> {
+        buf.copy_from_slice(self.read::<T>(buf.len())?);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error> {
+        Ok(self.read::<T>(nbytes)?.to_vec())
+    }
+
+    #[allow(unused)]
+    #[inline(always)]
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[inline(always)]
+    fn into_bits<C, T: IntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, ctx: &mut C) -> Result<(), Error> {
+        self.into_payload(value, ctx)
+    }
+
+    #[allow(unused)]
+    #[inline(always)]
+    fn from_bits<C, T: FromPayload<'a, C> + BitField>(&mut self, bits: u32, ctx: &mut C) -> Result<T, Error> {
+        self.from_payload(ctx)
+    }
+
+    #[inline(always)]
+    fn into_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn from_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn into_scope(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[inline(always)]
+    fn from_scope(&mut self, len: usize) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn from_scope_end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        0
+    }
+
     #[allow(unused)]
     fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
         return Err(Error::Stack(format!("References disabled.")));
@@ -216,6 +303,9 @@ pub trait Middleware<'a> {
 
 /// The `AsyncMiddleware` trait defines asynchronous methods for converting types to and from payloads of bytes.
 ///
+/// ### Associated Constants
+/// - `const PACKS_BITS: bool`: Whether `poll_into_bits` packs values into their bits, see `Middleware::PACKS_BITS`.
+///
 /// ### Methods
 /// - `fn poll_into_payload<'a, C, T: AsyncIntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> impl Future<Output = Result<(), Error>>`:
 ///     - Polls the conversion of a value into a payload of bytes asynchronously.
@@ -227,6 +317,28 @@ pub trait Middleware<'a> {
 ///     - Polls the asynchronous reading of raw data from the handler.
 /// - `fn poll_read_mut<'a, T>(&'a mut self, nbytes: usize) -> impl Future<Output = Result<&'a mut [T], Error>>`:
 ///     - Polls the asynchronous reading of raw data from the handler, returning a mutable slice of the read data.
+/// - `fn poll_read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> impl Future<Output = Result<(), Error>>`:
+///     - Polls the asynchronous reading of exactly `buf.len()` bytes from the handler into `buf`.
+/// - `fn poll_read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> impl Future<Output = Result<Vec<T>, Error>>`:
+///     - Polls the asynchronous reading of raw data from the handler into an owned vector.
+/// - `fn poll_reserve<T>(&mut self, len: usize) -> impl Future<Output = Result<(), Error>>`:
+///     - Called by collection and string impls with the length read from the wire, before anything is allocated for `len` elements of `T`.
+/// - `fn poll_into_bits<C, T: AsyncIntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, ctx: &mut C) -> impl Future<Output = Result<(), Error>>`:
+///     - Polls the writing of a value that fits into `bits` bits, see `Middleware::into_bits`.
+/// - `fn poll_from_bits<C, T: AsyncFromPayload<'a, C> + BitField>(&mut self, bits: u32, ctx: &mut C) -> impl Future<Output = Result<T, Error>>`:
+///     - Polls the reading of a value written by `poll_into_bits`.
+/// - `fn poll_into_end(&mut self) -> impl Future<Output = Result<(), Error>>`:
+///     - Polls the end of a top-level value being written, see `Middleware::into_end`.
+/// - `fn poll_from_end(&mut self) -> impl Future<Output = Result<(), Error>>`:
+///     - Polls the end of a top-level value being read, see `Middleware::into_end`.
+/// - `fn poll_into_scope(&mut self) -> impl Future<Output = Result<(), Error>>`:
+///     - Polls the start of a part being written, see `Middleware::into_scope`.
+/// - `fn poll_from_scope(&mut self, len: usize) -> impl Future<Output = Result<(), Error>>`:
+///     - Polls the start of a part of `len` bytes being read, see `Middleware::from_scope`.
+/// - `fn poll_from_scope_end(&mut self) -> impl Future<Output = Result<(), Error>>`:
+///     - Polls the end of a part being read, see `Middleware::from_scope_end`.
+/// - `fn position(&self) -> usize`:
+///     - Returns the number of bytes processed so far, used to locate decode errors. Handlers that don't track it return 0.
 /// - `fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> impl Future<Output = Result<&'a T, Error>>`:
 ///     - Polls the asynchronous pushing of a boxed value into the handler, returning a reference to the stored value.
 /// - `fn poll_push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> impl Future<Output = Result<&'a mut T, Error>>`:
@@ -237,6 +349,8 @@ pub trait Middleware<'a> {
 ///     - Polls the asynchronous pushing of a boxed array of values into the handler, returning a mutable reference to the stored array.
 #[cfg(feature = "async")]
 pub trait AsyncMiddleware<'a>: Send + Sync {
+    const PACKS_BITS: bool = false;
+
     fn poll_into_payload<C: Send + Sync, T: AsyncIntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> impl Future<Output = Result<(), Error>>;
     fn poll_from_payload<C: Send + Sync, T: AsyncFromPayload<'a, C>>(&mut self, ctx: &mut C) -> impl Future<Output = Result<T, Error>>;
 
@@ -244,6 +358,76 @@ pub trait AsyncMiddleware<'a>: Send + Sync {
     fn poll_read<T: 'a>(&mut self, nbytes: usize) -> impl Future<Output = Result<&'a [T], Error>>;
     fn poll_read_mut<T: 'a>(&mut self, nbytes: usize) -> impl Future<Output = Result<&'a mut [T], Error>>;
 
+    fn poll_read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            buf.copy_from_slice(self.poll_read::<T>(buf.len()).await?);
+            Ok(())
+        }
+    }
+
+    fn poll_read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> impl Future<Output = Result<Vec<T>, Error>> {
+        async move {
+            Ok(self.poll_read::<T>(nbytes).await?.to_vec())
+        }
+    }
+
+    #[allow(unused)]
+    fn poll_reserve<T>(&mut self, len: usize) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            Ok(())
+        }
+    }
+
+    #[allow(unused)]
+    fn poll_into_bits<C: Send + Sync, T: AsyncIntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, ctx: &mut C) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            self.poll_into_payload(value, ctx).await
+        }
+    }
+
+    #[allow(unused)]
+    fn poll_from_bits<C: Send + Sync, T: AsyncFromPayload<'a, C> + BitField>(&mut self, bits: u32, ctx: &mut C) -> impl Future<Output = Result<T, Error>> {
+        async move {
+            self.poll_from_payload(ctx).await
+        }
+    }
+
+    fn poll_into_end(&mut self) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            Ok(())
+        }
+    }
+
+    fn poll_from_end(&mut self) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            Ok(())
+        }
+    }
+
+    fn poll_into_scope(&mut self) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            Ok(())
+        }
+    }
+
+    #[allow(unused)]
+    fn poll_from_scope(&mut self, len: usize) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            Ok(())
+        }
+    }
+
+    fn poll_from_scope_end(&mut self) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            Ok(())
+        }
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        0
+    }
+
     #[allow(unused)]
     fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> impl Future<Output = Result<&'a T, Error>> {
         async move {
@@ -394,11 +578,36 @@ pub trait PayloadInfo {
     const SIZE: Option<usize> = None;
 }
 
+/// The `HasVersion` trait exposes the negotiated protocol version of a context.
+///
+/// Derived types with `#[npsd(since = N)]` or `#[npsd(until = N)]` fields require it from their
+/// context. Such a field is on the wire when `since <= version() < until`, otherwise it is left out
+/// and decodes as its default.
+///
+/// ### Methods
+/// - `fn version(&self) -> u32`:
+///     - Returns the protocol version both peers agreed on.
+pub trait HasVersion {
+    fn version(&self) -> u32;
+}
+
+impl HasVersion for u32 {
+    #[inline(always)]
+    fn version(&self) -> u32 {
+        *self
+    }
+}
+
 pub mod middleware;
 pub mod error;
+pub mod bitfield;
+pub mod quantize;
 pub mod info;
 pub mod features;
 
+#[cfg(any(feature = "sync", feature = "async"))]
+pub mod delta;
+
 #[cfg(feature = "crossbeam")]
 pub mod stack;
 
@@ -408,6 +617,18 @@ pub use stack::*;
 #[cfg(feature = "sync")]
 pub mod payload;
 
+#[cfg(feature = "sync")]
+pub mod patch;
+
+#[cfg(feature = "sync")]
+pub mod framed;
+
+#[cfg(all(feature = "info", any(feature = "sync", feature = "async")))]
+pub mod registry;
+
+#[cfg(feature = "rpc")]
+pub mod rpc;
+
 #[cfg(feature = "async")]
 pub mod poll_payload;
 
@@ -421,4 +642,21 @@ pub use xxhash_rust::xxh3::xxh3_64 as PayloadHash;
 
 pub use error::*;
 pub use middleware::*;
-pub use npsd_schema::*;
\ No newline at end of file
+pub use bitfield::*;
+pub use quantize::*;
+
+#[cfg(any(feature = "sync", feature = "async"))]
+pub use delta::*;
+
+#[cfg(feature = "sync")]
+pub use framed::*;
+
+#[cfg(feature = "sync")]
+pub use patch::{Diff, Op, Patch, Segment};
+
+#[cfg(all(feature = "info", any(feature = "sync", feature = "async")))]
+pub use registry::*;
+
+#[cfg(feature = "rpc")]
+pub use rpc::*;
+pub use npsd_schema::*;
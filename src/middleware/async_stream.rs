@@ -0,0 +1,278 @@
+use std::{marker::PhantomData, mem};
+
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{AsyncFromPayload, AsyncIntoPayload, AsyncMiddleware};
+
+#[cfg(feature = "crossbeam")]
+use crate::{AnyBox, Stack};
+
+use crate::Error;
+
+use super::Scopes;
+
+/// An `AsyncMiddleware` that decodes payloads directly from any `tokio::io::AsyncRead`.
+/// Requires the `tokio` feature to be enabled.
+///
+/// `poll_read_exact` and `poll_read_owned` await exactly the requested number of bytes from
+/// the reader, so a message can be decoded from a `TcpStream` without buffering it first.
+/// Like `NextReader`, borrowed types fail with `Error::Unsupported`.
+#[derive(Debug)]
+pub struct AsyncNextReader<'a, R> {
+    inner: R,
+    nbytes: usize,
+    scopes: Scopes,
+    #[cfg(feature = "crossbeam")]
+    stack: Stack<'a>,
+    marker: PhantomData<&'a ()>,
+}
+
+impl<'a, R: AsyncRead + Unpin + Send + Sync> AsyncNextReader<'a, R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            nbytes: 0,
+            scopes: Scopes::default(),
+            #[cfg(feature = "crossbeam")]
+            stack: Stack::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the number of bytes read from the underlying reader.
+    #[inline(always)]
+    pub fn position(&self) -> usize {
+        self.nbytes
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<'a, R: AsyncRead + Unpin + Send + Sync> AsyncMiddleware<'a> for AsyncNextReader<'a, R> {
+    #[inline(always)]
+    fn poll_into_payload<C: Send + Sync, T: AsyncIntoPayload<C>>(
+        &mut self,
+        value: &T,
+        ctx: &mut C
+    ) -> impl core::future::Future<Output = Result<(), Error>> {
+        value.poll_into_payload(ctx, self)
+    }
+
+    #[inline(always)]
+    fn poll_from_payload<C: Send + Sync, T: AsyncFromPayload<'a, C>> (
+        &mut self,
+        ctx: &mut C,
+    ) -> impl core::future::Future<Output = Result<T, Error>> {
+        T::poll_from_payload(ctx, self)
+    }
+
+    async fn poll_write<T>(&mut self, _data: &[T]) -> Result<(), Error> {
+        Err(Error::Unsupported("AsyncNextReader can't be used for encoding".to_string()))
+    }
+
+    async fn poll_read<T: 'a>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        Err(Error::Unsupported(format!("Borrowed read of {} bytes from a stream, decode into an owned type instead", nbytes)))
+    }
+
+    async fn poll_read_mut<T: 'a>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        Err(Error::Unsupported(format!("Borrowed read of {} bytes from a stream, decode into an owned type instead", nbytes)))
+    }
+
+    #[inline(always)]
+    async fn poll_reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.reserve::<T>(self.nbytes, len)
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.enter(self.nbytes, len, None)
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope_end(&mut self) -> Result<(), Error> {
+        let left = self.scopes.leave(self.nbytes);
+        let found = io::copy(&mut (&mut self.inner).take(left as u64), &mut io::sink()).await? as usize;
+        self.nbytes += found;
+
+        if found != left {
+            return Err(Error::InvalidLength { expected: left, found });
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.nbytes
+    }
+
+    async fn poll_read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error> {
+        debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+        self.scopes.read(self.nbytes, buf.len())?;
+
+        let slice = unsafe {
+            ::std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len())
+        };
+
+        self.inner.read_exact(slice).await?;
+        self.nbytes += slice.len();
+
+        Ok(())
+    }
+
+    async fn poll_read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error> {
+        debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+        self.scopes.read(self.nbytes, nbytes)?;
+
+        // The length comes from the wire, so let the vector grow with the data
+        // instead of trusting it for the initial allocation.
+        let mut vec = Vec::new();
+        let found = (&mut self.inner).take(nbytes as u64).read_to_end(&mut vec).await?;
+        self.nbytes += found;
+
+        if found != nbytes {
+            return Err(Error::InvalidLength { expected: nbytes, found });
+        }
+
+        let mut vec = mem::ManuallyDrop::new(vec);
+
+        Ok(unsafe {
+            Vec::from_raw_parts(vec.as_mut_ptr() as *mut T, vec.len(), vec.capacity())
+        })
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[inline(always)]
+    async fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        Ok(self.stack.push(value))
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[inline(always)]
+    async fn poll_push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        Ok(self.stack.push_mut(value))
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[inline(always)]
+    async fn poll_push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        Ok(self.stack.push_array(values))
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[inline(always)]
+    async fn poll_push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        Ok(self.stack.push_array_mut(values))
+    }
+}
+
+/// An `AsyncMiddleware` that encodes payloads directly into any `tokio::io::AsyncWrite`.
+/// Requires the `tokio` feature to be enabled.
+///
+/// `poll_write` awaits `write_all` on the writer, and the writer is flushed once the
+/// outermost `poll_into_payload` returns, so every message is on the wire when
+/// `poll_into_packet` completes.
+#[derive(Debug)]
+pub struct AsyncNextWriter<W> {
+    inner: W,
+    nbytes: usize,
+    depth: usize,
+}
+
+impl<W: AsyncWrite + Unpin + Send + Sync> AsyncNextWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            nbytes: 0,
+            depth: 0,
+        }
+    }
+
+    /// Returns the number of bytes written to the underlying writer.
+    #[inline(always)]
+    pub fn position(&self) -> usize {
+        self.nbytes
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<'a, W: AsyncWrite + Unpin + Send + Sync> AsyncMiddleware<'a> for AsyncNextWriter<W> {
+    async fn poll_into_payload<C: Send + Sync, T: AsyncIntoPayload<C>>(
+        &mut self,
+        value: &T,
+        ctx: &mut C
+    ) -> Result<(), Error> {
+        self.depth += 1;
+        let result = value.poll_into_payload(ctx, self).await;
+        self.depth -= 1;
+
+        result?;
+
+        if self.depth == 0 {
+            self.inner.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn poll_from_payload<C: Send + Sync, T: AsyncFromPayload<'a, C>> (
+        &mut self,
+        ctx: &mut C,
+    ) -> impl core::future::Future<Output = Result<T, Error>> {
+        T::poll_from_payload(ctx, self)
+    }
+
+    async fn poll_write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+
+        let slice = unsafe {
+            ::std::slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data))
+        };
+
+        self.inner.write_all(slice).await?;
+        self.nbytes += slice.len();
+
+        Ok(())
+    }
+
+    async fn poll_read<T: 'a>(&mut self, _nbytes: usize) -> Result<&'a [T], Error> {
+        Err(Error::Unsupported("AsyncNextWriter can't be used for decoding".to_string()))
+    }
+
+    async fn poll_read_mut<T: 'a>(&mut self, _nbytes: usize) -> Result<&'a mut [T], Error> {
+        Err(Error::Unsupported("AsyncNextWriter can't be used for decoding".to_string()))
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.nbytes
+    }
+}
@@ -0,0 +1,528 @@
+use core::{mem, slice};
+
+#[cfg(feature = "sync")]
+use crate::{FromPayload, IntoPayload, Middleware};
+
+#[cfg(feature = "async")]
+use crate::{AsyncFromPayload, AsyncIntoPayload, AsyncMiddleware};
+
+use crate::{AnyBox, BitField, Error};
+
+/// The most bytes a single `into_bits` / `from_bits` call can complete, 128 bits plus 7 pending ones.
+const MAX_BITS_BYTES: usize = 17;
+
+/// A `Middleware` and `AsyncMiddleware` wrapper that packs values at bit granularity.
+///
+/// Values written with `into_bits`, which covers `bool`, the tags of `Option`, `Result` and derived
+/// enums, and `#[npsd(range = a..b)]` fields, take only their bits, so eight `bool`s in a row take
+/// a single byte, across fields and nested structs. Everything else is written as whole bytes,
+/// which start at whatever bit the previous value ended on. The last byte of every top-level value
+/// is padded with zero bits, so messages stay byte aligned and can be framed as usual.
+///
+/// Bits fill each byte from the lowest bit up. The inner middleware only sees complete bytes,
+/// and as with `Layered`, its own `into_payload` / `from_payload` are bypassed. `Layered` and
+/// `NextTyped` hand bits and the end of every top-level value on to a `NextBits` they wrap, so
+/// `NextLimits<NextBits<M>>` writes the same bytes as `NextBits<M>`. Length prefixed parts, like
+/// `#[npsd(tagged)]` fields, start and end on a byte boundary. Borrowing reads (`&str`, `&[u8]`)
+/// that don't start on a byte boundary copy the bytes onto the inner middleware's stack, which
+/// requires the `crossbeam` feature.
+#[derive(Debug)]
+pub struct NextBits<M> {
+    inner: M,
+    depth: usize,
+    /// Bits written but not yet complete, or read but not yet consumed, from the lowest bit.
+    pending: u8,
+    /// The number of bits in `pending`.
+    len: u32,
+}
+
+impl<M> NextBits<M> {
+    pub fn new(inner: M) -> Self {
+        Self {
+            inner,
+            depth: 0,
+            pending: 0,
+            len: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &M {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut M {
+        &mut self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> M {
+        self.inner
+    }
+
+    /// Appends the low `bits` bits of `value`, returning the bytes they complete.
+    fn pack(&mut self, mut value: u128, mut bits: u32) -> ([u8; MAX_BITS_BYTES], usize) {
+        let mut bytes = [0u8; MAX_BITS_BYTES];
+        let mut complete = 0;
+
+        while bits > 0 {
+            let take = bits.min(8 - self.len);
+
+            self.pending |= ((value & mask(take)) as u8) << self.len;
+            self.len += take;
+            value >>= take;
+            bits -= take;
+
+            if self.len == 8 {
+                bytes[complete] = self.pending;
+                complete += 1;
+
+                self.pending = 0;
+                self.len = 0;
+            }
+        }
+
+        (bytes, complete)
+    }
+
+    /// The number of bytes to read before `bits` bits can be unpacked.
+    fn needed(&self, bits: u32) -> usize {
+        bits.saturating_sub(self.len).div_ceil(8) as usize
+    }
+
+    /// Takes `bits` bits, from the pending ones and then from `bytes`, which holds `needed(bits)` bytes.
+    fn unpack(&mut self, mut bits: u32, bytes: &[u8]) -> u128 {
+        let mut bytes = bytes.iter();
+        let mut value = 0u128;
+        let mut shift = 0;
+
+        while bits > 0 {
+            if self.len == 0 {
+                self.pending = bytes.next().copied().unwrap_or_default();
+                self.len = 8;
+            }
+
+            let take = bits.min(self.len);
+
+            value |= (self.pending as u128 & mask(take)) << shift;
+            self.pending = self.pending.checked_shr(take).unwrap_or_default();
+            self.len -= take;
+            shift += take;
+            bits -= take;
+        }
+
+        value
+    }
+
+    /// Moves whole bytes by the pending bits, turning bytes to write into the bytes completed by
+    /// them, or bytes read into the bytes they hold. Only called while `len` isn't 0.
+    fn shift(&mut self, data: &mut [u8]) {
+        for byte in data {
+            let carry = self.pending;
+
+            self.pending = *byte >> (8 - self.len);
+            *byte = carry | (*byte << self.len);
+        }
+    }
+
+    /// Returns the bits written but not yet complete, padded to a byte.
+    pub(super) fn pending(&self) -> Option<u8> {
+        (self.len > 0).then_some(self.pending)
+    }
+
+    /// Takes the pending bits of a finished message, padding them to a byte when encoding.
+    fn align(&mut self) -> Option<u8> {
+        let pending = (self.len > 0).then_some(self.pending);
+
+        self.pending = 0;
+        self.len = 0;
+
+        pending
+    }
+}
+
+#[inline(always)]
+fn mask(bits: u32) -> u128 {
+    u128::MAX.checked_shr(128 - bits).unwrap_or_default()
+}
+
+#[inline(always)]
+fn check(value: u128, bits: u32) -> Result<u128, Error> {
+    if bits > 128 || value & !mask(bits) != 0 {
+        return Err(Error::OutOfRange(format!("`{}` doesn't fit into {} bits", value, bits)));
+    }
+
+    Ok(value)
+}
+
+#[inline(always)]
+fn as_bytes_mut<T>(data: &mut [T]) -> &mut [u8] {
+    debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+
+    unsafe {
+        slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, mem::size_of_val(data))
+    }
+}
+
+#[inline(always)]
+fn to_bytes<T>(data: &[T]) -> Vec<u8> {
+    debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+
+    unsafe {
+        slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data))
+    }.to_vec()
+}
+
+#[inline(always)]
+fn cast<T>(data: &[u8]) -> &[T] {
+    debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+
+    unsafe {
+        slice::from_raw_parts(data.as_ptr() as *const T, data.len())
+    }
+}
+
+#[inline(always)]
+fn cast_mut<T>(data: &mut [u8]) -> &mut [T] {
+    debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+
+    unsafe {
+        slice::from_raw_parts_mut(data.as_mut_ptr() as *mut T, data.len())
+    }
+}
+
+#[inline(always)]
+fn cast_vec<T: Clone>(data: Vec<u8>) -> Vec<T> {
+    cast::<T>(&data).to_vec()
+}
+
+#[cfg(feature = "sync")]
+impl<'a, M: Middleware<'a>> Middleware<'a> for NextBits<M> {
+    const PACKS_BITS: bool = true;
+
+    fn into_payload<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        self.depth += 1;
+        let result = value.into_payload(ctx, self);
+        self.depth -= 1;
+
+        if self.depth == 0 {
+            return match result {
+                Ok(()) => self.into_end(),
+                Err(error) => {
+                    self.align();
+                    Err(error)
+                },
+            };
+        }
+
+        result
+    }
+
+    fn from_payload<C, T: FromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        self.depth += 1;
+        let result = T::from_payload(ctx, self);
+        self.depth -= 1;
+
+        if self.depth == 0 {
+            let end = self.from_end();
+            return result.and_then(|value| end.map(|()| value));
+        }
+
+        result
+    }
+
+    fn into_bits<C, T: IntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, _ctx: &mut C) -> Result<(), Error> {
+        let (bytes, complete) = self.pack(check(value.to_bits(), bits)?, bits);
+        self.inner.write(&bytes[..complete])
+    }
+
+    fn from_bits<C, T: FromPayload<'a, C> + BitField>(&mut self, bits: u32, _ctx: &mut C) -> Result<T, Error> {
+        check(0, bits)?;
+
+        let mut bytes = [0u8; MAX_BITS_BYTES];
+        let needed = self.needed(bits);
+        self.inner.read_exact(&mut bytes[..needed])?;
+
+        T::from_bits(self.unpack(bits, &bytes[..needed]))
+    }
+
+    fn into_end(&mut self) -> Result<(), Error> {
+        if let Some(pending) = self.align() {
+            self.inner.write(&[pending])?;
+        }
+
+        self.inner.into_end()
+    }
+
+    fn from_end(&mut self) -> Result<(), Error> {
+        self.align();
+        self.inner.from_end()
+    }
+
+    fn into_scope(&mut self) -> Result<(), Error> {
+        if let Some(pending) = self.align() {
+            self.inner.write(&[pending])?;
+        }
+
+        self.inner.into_scope()
+    }
+
+    fn from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.align();
+        self.inner.from_scope(len)
+    }
+
+    fn from_scope_end(&mut self) -> Result<(), Error> {
+        self.align();
+        self.inner.from_scope_end()
+    }
+
+    fn write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        if self.len == 0 {
+            return self.inner.write(data);
+        }
+
+        let mut bytes = to_bytes(data);
+        self.shift(&mut bytes);
+        self.inner.write(&bytes)
+    }
+
+    fn read<T>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        if self.len == 0 {
+            return self.inner.read(nbytes);
+        }
+
+        let mut bytes = self.inner.read_owned::<u8>(nbytes)?;
+        self.shift(&mut bytes);
+
+        Ok(cast(self.inner.push_array(bytes.into_boxed_slice())?))
+    }
+
+    fn read_mut<T>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        if self.len == 0 {
+            return self.inner.read_mut(nbytes);
+        }
+
+        let mut bytes = self.inner.read_owned::<u8>(nbytes)?;
+        self.shift(&mut bytes);
+
+        Ok(cast_mut(self.inner.push_array_mut(bytes.into_boxed_slice())?))
+    }
+
+    fn read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error> {
+        self.inner.read_exact(buf)?;
+
+        if self.len != 0 {
+            self.shift(as_bytes_mut(buf));
+        }
+
+        Ok(())
+    }
+
+    fn read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error> {
+        if self.len == 0 {
+            return self.inner.read_owned(nbytes);
+        }
+
+        let mut bytes = self.inner.read_owned::<u8>(nbytes)?;
+        self.shift(&mut bytes);
+
+        Ok(cast_vec(bytes))
+    }
+
+    #[inline(always)]
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.inner.reserve::<T>(len)
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.inner.position()
+    }
+
+    #[inline(always)]
+    fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        self.inner.push(value)
+    }
+
+    #[inline(always)]
+    fn push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        self.inner.push_mut(value)
+    }
+
+    #[inline(always)]
+    fn push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        self.inner.push_array(values)
+    }
+
+    #[inline(always)]
+    fn push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        self.inner.push_array_mut(values)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a, M: AsyncMiddleware<'a>> AsyncMiddleware<'a> for NextBits<M> {
+    const PACKS_BITS: bool = true;
+
+    async fn poll_into_payload<C: Send + Sync, T: AsyncIntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        self.depth += 1;
+        let result = value.poll_into_payload(ctx, self).await;
+        self.depth -= 1;
+
+        if self.depth == 0 {
+            return match result {
+                Ok(()) => self.poll_into_end().await,
+                Err(error) => {
+                    self.align();
+                    Err(error)
+                },
+            };
+        }
+
+        result
+    }
+
+    async fn poll_from_payload<C: Send + Sync, T: AsyncFromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        self.depth += 1;
+        let result = T::poll_from_payload(ctx, self).await;
+        self.depth -= 1;
+
+        if self.depth == 0 {
+            let end = self.poll_from_end().await;
+            return result.and_then(|value| end.map(|()| value));
+        }
+
+        result
+    }
+
+    async fn poll_into_bits<C: Send + Sync, T: AsyncIntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, _ctx: &mut C) -> Result<(), Error> {
+        let (bytes, complete) = self.pack(check(value.to_bits(), bits)?, bits);
+        self.inner.poll_write(&bytes[..complete]).await
+    }
+
+    async fn poll_from_bits<C: Send + Sync, T: AsyncFromPayload<'a, C> + BitField>(&mut self, bits: u32, _ctx: &mut C) -> Result<T, Error> {
+        check(0, bits)?;
+
+        let mut bytes = [0u8; MAX_BITS_BYTES];
+        let needed = self.needed(bits);
+        self.inner.poll_read_exact(&mut bytes[..needed]).await?;
+
+        T::from_bits(self.unpack(bits, &bytes[..needed]))
+    }
+
+    async fn poll_into_end(&mut self) -> Result<(), Error> {
+        if let Some(pending) = self.align() {
+            self.inner.poll_write(&[pending]).await?;
+        }
+
+        self.inner.poll_into_end().await
+    }
+
+    async fn poll_from_end(&mut self) -> Result<(), Error> {
+        self.align();
+        self.inner.poll_from_end().await
+    }
+
+    async fn poll_into_scope(&mut self) -> Result<(), Error> {
+        if let Some(pending) = self.align() {
+            self.inner.poll_write(&[pending]).await?;
+        }
+
+        self.inner.poll_into_scope().await
+    }
+
+    async fn poll_from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.align();
+        self.inner.poll_from_scope(len).await
+    }
+
+    async fn poll_from_scope_end(&mut self) -> Result<(), Error> {
+        self.align();
+        self.inner.poll_from_scope_end().await
+    }
+
+    async fn poll_write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        if self.len == 0 {
+            return self.inner.poll_write(data).await;
+        }
+
+        let mut bytes = to_bytes(data);
+        self.shift(&mut bytes);
+        self.inner.poll_write(&bytes).await
+    }
+
+    async fn poll_read<T: 'a>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        if self.len == 0 {
+            return self.inner.poll_read(nbytes).await;
+        }
+
+        let mut bytes = self.inner.poll_read_owned::<u8>(nbytes).await?;
+        self.shift(&mut bytes);
+
+        Ok(cast(self.inner.poll_push_array(bytes.into_boxed_slice()).await?))
+    }
+
+    async fn poll_read_mut<T: 'a>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        if self.len == 0 {
+            return self.inner.poll_read_mut(nbytes).await;
+        }
+
+        let mut bytes = self.inner.poll_read_owned::<u8>(nbytes).await?;
+        self.shift(&mut bytes);
+
+        Ok(cast_mut(self.inner.poll_push_array_mut(bytes.into_boxed_slice()).await?))
+    }
+
+    async fn poll_read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error> {
+        self.inner.poll_read_exact(buf).await?;
+
+        if self.len != 0 {
+            self.shift(as_bytes_mut(buf));
+        }
+
+        Ok(())
+    }
+
+    async fn poll_read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error> {
+        if self.len == 0 {
+            return self.inner.poll_read_owned(nbytes).await;
+        }
+
+        let mut bytes = self.inner.poll_read_owned::<u8>(nbytes).await?;
+        self.shift(&mut bytes);
+
+        Ok(cast_vec(bytes))
+    }
+
+    #[inline(always)]
+    async fn poll_reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.inner.poll_reserve::<T>(len).await
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.inner.position()
+    }
+
+    #[inline(always)]
+    async fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        self.inner.poll_push(value).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        self.inner.poll_push_mut(value).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        self.inner.poll_push_array(values).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        self.inner.poll_push_array_mut(values).await
+    }
+}
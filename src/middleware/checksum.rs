@@ -0,0 +1,153 @@
+use core::fmt;
+
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{Error, PayloadHash};
+
+use super::{MiddlewareLayer, PacketFilter};
+
+/// A `MiddlewareLayer` that hashes every byte written and read with xxh3-64.
+///
+/// The digests cover everything since the layer was created or last reset, so the
+/// `written_digest` of a sender can be compared with the `read_digest` of its receiver.
+#[derive(Clone, Default)]
+pub struct ChecksumLayer {
+    written: Xxh3,
+    read: Xxh3,
+}
+
+impl ChecksumLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the xxh3-64 hash of the bytes written so far.
+    #[inline(always)]
+    pub fn written_digest(&self) -> u64 {
+        self.written.digest()
+    }
+
+    /// Returns the xxh3-64 hash of the bytes read so far.
+    #[inline(always)]
+    pub fn read_digest(&self) -> u64 {
+        self.read.digest()
+    }
+
+    pub fn reset(&mut self) {
+        self.written.reset();
+        self.read.reset();
+    }
+}
+
+impl fmt::Debug for ChecksumLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChecksumLayer")
+            .field("written_digest", &self.written_digest())
+            .field("read_digest", &self.read_digest())
+            .finish()
+    }
+}
+
+impl MiddlewareLayer for ChecksumLayer {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.written.update(data);
+        Ok(())
+    }
+
+    fn read(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.read.update(data);
+        Ok(())
+    }
+}
+
+/// The checksum appended by `Checksum`.
+///
+/// - `Xxh3`: xxh3-64, stored as 8 big-endian bytes.
+/// - `Crc32c`: CRC-32C (Castagnoli), stored as 4 big-endian bytes. Requires the `crc32c` feature.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum ChecksumAlgorithm {
+    #[default]
+    Xxh3,
+
+    #[cfg(feature = "crc32c")]
+    Crc32c,
+}
+
+impl ChecksumAlgorithm {
+    /// Returns the length of the trailer, in bytes.
+    pub const fn trailer_len(&self) -> usize {
+        match self {
+            ChecksumAlgorithm::Xxh3 => 8,
+
+            #[cfg(feature = "crc32c")]
+            ChecksumAlgorithm::Crc32c => 4,
+        }
+    }
+
+    pub fn checksum(&self, data: &[u8]) -> u64 {
+        match self {
+            ChecksumAlgorithm::Xxh3 => PayloadHash(data),
+
+            #[cfg(feature = "crc32c")]
+            ChecksumAlgorithm::Crc32c => crc32c::crc32c(data) as u64,
+        }
+    }
+}
+
+/// A `PacketFilter` that appends a checksum of the body, to be used with `NextPacket`.
+///
+/// The trailer is checked before the body is handed to `FromPayload`, so a packet corrupted in
+/// transit fails with `Error::ChecksumMismatch` instead of decoding into a wrong value. This
+/// guards against accidental corruption only, use `Sealer` when packets may be forged.
+///
+/// Both ends must agree on the algorithm, since the trailer doesn't say which one was used.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Checksum {
+    algorithm: ChecksumAlgorithm,
+}
+
+impl Checksum {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        Self { algorithm }
+    }
+
+    #[inline(always)]
+    pub fn algorithm(&self) -> ChecksumAlgorithm {
+        self.algorithm
+    }
+}
+
+impl PacketFilter for Checksum {
+    fn encode(&mut self, body: &[u8]) -> Result<Vec<u8>, Error> {
+        let len = self.algorithm.trailer_len();
+        let checksum = self.algorithm.checksum(body).to_be_bytes();
+
+        let mut packet = Vec::with_capacity(body.len() + len);
+        packet.extend_from_slice(body);
+        packet.extend_from_slice(&checksum[checksum.len() - len..]);
+
+        Ok(packet)
+    }
+
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        let len = self.algorithm.trailer_len();
+
+        if packet.len() < len {
+            return Err(Error::InvalidLength { expected: len, found: packet.len() });
+        }
+
+        let (body, trailer) = packet.split_at(packet.len() - len);
+
+        let mut expected = [0u8; 8];
+        expected[8 - len..].copy_from_slice(trailer);
+
+        let expected = u64::from_be_bytes(expected);
+        let found = self.algorithm.checksum(body);
+
+        if expected != found {
+            return Err(Error::ChecksumMismatch { expected, found });
+        }
+
+        Ok(body.to_vec())
+    }
+}
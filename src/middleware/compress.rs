@@ -0,0 +1,218 @@
+#[cfg(any(feature = "zstd", feature = "deflate"))]
+use std::io::Read;
+
+use crate::Error;
+
+use super::PacketFilter;
+
+/// The default size below which messages are sent uncompressed, in bytes.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 256;
+
+/// The default maximum size of a decompressed message, 8 MiB.
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 8 * 1024 * 1024;
+
+/// A compression algorithm, stored as the first byte of every compressed packet.
+///
+/// - `None`: the body follows as is.
+/// - `Lz4`: LZ4 block format, prefixed with the uncompressed size. Requires the `lz4` feature.
+/// - `Zstd`: a Zstandard frame. Requires the `zstd` feature.
+/// - `Deflate`: a raw Deflate stream. Requires the `deflate` feature.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Compression {
+    None = 0,
+
+    #[cfg(feature = "lz4")]
+    Lz4 = 1,
+
+    #[cfg(feature = "zstd")]
+    Zstd = 2,
+
+    #[cfg(feature = "deflate")]
+    Deflate = 3,
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Error> {
+        match value {
+            0 => Ok(Compression::None),
+
+            #[cfg(feature = "lz4")]
+            1 => Ok(Compression::Lz4),
+
+            #[cfg(feature = "zstd")]
+            2 => Ok(Compression::Zstd),
+
+            #[cfg(feature = "deflate")]
+            3 => Ok(Compression::Deflate),
+
+            #[allow(unreachable_patterns)]
+            1..=3 => Err(Error::Compression(format!("Algorithm `{}` is not enabled", value))),
+            _ => Err(Error::Compression(format!("Unknown algorithm `{}`", value))),
+        }
+    }
+}
+
+/// A `PacketFilter` that compresses messages, to be used with `NextPacket`.
+///
+/// Bodies shorter than `threshold`, or that don't get any smaller, are sent with the
+/// `Compression::None` header instead. Decompression accepts any enabled algorithm, whatever
+/// `algorithm` is set to, and fails with `Error::Compression` once the output would go over
+/// `max_size` bytes.
+///
+/// - `level`: the compression level for `Zstd` (`1..=22`) and `Deflate` (`0..=9`), ignored by `Lz4`.
+#[derive(Clone, Debug)]
+pub struct Compressor {
+    algorithm: Compression,
+    threshold: usize,
+    level: i32,
+    max_size: usize,
+}
+
+impl Compressor {
+    pub fn new(algorithm: Compression) -> Self {
+        Self {
+            algorithm,
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            level: 3,
+            max_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_level(mut self, level: i32) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    #[inline(always)]
+    pub fn algorithm(&self) -> Compression {
+        self.algorithm
+    }
+
+    #[inline(always)]
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    #[inline(always)]
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    #[inline(always)]
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    fn compress(&self, algorithm: Compression, body: &[u8], packet: &mut Vec<u8>) -> Result<(), Error> {
+        match algorithm {
+            Compression::None => packet.extend_from_slice(body),
+
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => packet.extend_from_slice(&lz4_flex::compress_prepend_size(body)),
+
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                zstd::stream::copy_encode(body, &mut *packet, self.level)?;
+            },
+
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => {
+                let level = flate2::Compression::new(self.level.clamp(0, 9) as u32);
+                flate2::read::DeflateEncoder::new(body, level).read_to_end(packet)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    fn decompress(&self, algorithm: Compression, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let body = match algorithm {
+            Compression::None => data.to_vec(),
+
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let size = data.get(..4).map(|size| u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize).unwrap_or(0);
+                self.check(size)?;
+
+                lz4_flex::decompress_size_prepended(data).map_err(|e| Error::Compression(e.to_string()))?
+            },
+
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let decoder = zstd::stream::read::Decoder::new(data).map_err(|e| Error::Compression(e.to_string()))?;
+                self.read_bounded(decoder)?
+            },
+
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => self.read_bounded(flate2::read::DeflateDecoder::new(data))?,
+        };
+
+        self.check(body.len())?;
+
+        Ok(body)
+    }
+
+    fn check(&self, size: usize) -> Result<(), Error> {
+        if size > self.max_size {
+            return Err(Error::Compression(format!("Decompressed size exceeds `{}` bytes", self.max_size)));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(any(feature = "zstd", feature = "deflate"))]
+    fn read_bounded<R: Read>(&self, reader: R) -> Result<Vec<u8>, Error> {
+        let mut body = Vec::new();
+        reader.take(self.max_size as u64 + 1).read_to_end(&mut body).map_err(|e| Error::Compression(e.to_string()))?;
+
+        Ok(body)
+    }
+}
+
+impl Default for Compressor {
+    fn default() -> Self {
+        Self::new(Compression::None)
+    }
+}
+
+impl PacketFilter for Compressor {
+    fn encode(&mut self, body: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut packet = Vec::with_capacity(body.len() + 1);
+
+        if self.algorithm != Compression::None && body.len() >= self.threshold {
+            packet.push(self.algorithm as u8);
+            self.compress(self.algorithm, body, &mut packet)?;
+
+            if packet.len() <= body.len() {
+                return Ok(packet);
+            }
+
+            packet.clear();
+        }
+
+        packet.push(Compression::None as u8);
+        packet.extend_from_slice(body);
+
+        Ok(packet)
+    }
+
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        match packet.split_first() {
+            Some((&algorithm, data)) => self.decompress(Compression::try_from(algorithm)?, data),
+            None => Err(Error::InvalidLength { expected: 1, found: 0 }),
+        }
+    }
+}
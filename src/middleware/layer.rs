@@ -0,0 +1,670 @@
+#[cfg(any(feature = "sync", feature = "async"))]
+use core::{mem, slice};
+
+#[cfg(feature = "sync")]
+use crate::{FromPayload, IntoPayload, Middleware};
+
+#[cfg(feature = "async")]
+use crate::{AsyncFromPayload, AsyncIntoPayload, AsyncMiddleware};
+
+#[cfg(all(feature = "info", any(feature = "sync", feature = "async")))]
+use crate::PayloadInfo;
+
+#[cfg(any(feature = "sync", feature = "async"))]
+use crate::{AnyBox, BitField};
+
+use crate::Error;
+
+use super::{TraceDirection, TraceEvent};
+
+/// A set of hooks that `Layered` runs around an inner middleware.
+///
+/// Every hook has a default that does nothing, so a layer only implements the ones it cares about.
+/// The same layer works for both `Middleware` and `AsyncMiddleware`.
+///
+/// ### Methods
+/// - `fn enter(&mut self, event: &TraceEvent) -> Result<(), Error>`:
+///     - Called before a value is encoded or decoded. Returning an error aborts it.
+/// - `fn leave(&mut self, event: &TraceEvent, nbytes: usize, error: Option<&Error>)`:
+///     - Called once a value that was entered is done, with the number of bytes it took and its error, if any.
+/// - `fn write(&mut self, data: &[u8]) -> Result<(), Error>`:
+///     - Called with the bytes about to be written to the inner middleware.
+/// - `fn read(&mut self, data: &[u8]) -> Result<(), Error>`:
+///     - Called with the bytes just read from the inner middleware.
+/// - `fn reserve<T>(&mut self, len: usize) -> Result<(), Error>`:
+///     - Called before collections and strings allocate `len` elements of `T`.
+pub trait MiddlewareLayer: Send + Sync {
+    #[allow(unused)]
+    #[inline(always)]
+    fn enter(&mut self, event: &TraceEvent) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[inline(always)]
+    fn leave(&mut self, event: &TraceEvent, nbytes: usize, error: Option<&Error>) {}
+
+    #[allow(unused)]
+    #[inline(always)]
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[inline(always)]
+    fn read(&mut self, data: &[u8]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[allow(unused)]
+    #[inline(always)]
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl MiddlewareLayer for () {}
+
+impl<L: MiddlewareLayer> MiddlewareLayer for &mut L {
+    #[inline(always)]
+    fn enter(&mut self, event: &TraceEvent) -> Result<(), Error> {
+        (**self).enter(event)
+    }
+
+    #[inline(always)]
+    fn leave(&mut self, event: &TraceEvent, nbytes: usize, error: Option<&Error>) {
+        (**self).leave(event, nbytes, error)
+    }
+
+    #[inline(always)]
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        (**self).write(data)
+    }
+
+    #[inline(always)]
+    fn read(&mut self, data: &[u8]) -> Result<(), Error> {
+        (**self).read(data)
+    }
+
+    #[inline(always)]
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        (**self).reserve::<T>(len)
+    }
+}
+
+/// Two layers run one after the other, the outer one first.
+///
+/// `enter`, `write` and `reserve` run outer to inner, `leave` and `read` inner to outer.
+#[derive(Clone, Default, Debug)]
+pub struct Chain<O, I>(pub O, pub I);
+
+impl<O: MiddlewareLayer, I: MiddlewareLayer> MiddlewareLayer for Chain<O, I> {
+    fn enter(&mut self, event: &TraceEvent) -> Result<(), Error> {
+        self.0.enter(event)?;
+
+        if let Err(error) = self.1.enter(event) {
+            self.0.leave(event, 0, Some(&error));
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    fn leave(&mut self, event: &TraceEvent, nbytes: usize, error: Option<&Error>) {
+        self.1.leave(event, nbytes, error);
+        self.0.leave(event, nbytes, error);
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.0.write(data)?;
+        self.1.write(data)
+    }
+
+    fn read(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.1.read(data)?;
+        self.0.read(data)
+    }
+
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.0.reserve::<T>(len)?;
+        self.1.reserve::<T>(len)
+    }
+}
+
+/// A `Middleware` and `AsyncMiddleware` that runs a `MiddlewareLayer` around an inner middleware.
+///
+/// Reads, writes and references go to the inner middleware, while `into_payload` and
+/// `from_payload` are driven by `Layered` itself so that the layer sees every nested value.
+/// Since the inner middleware's own `into_payload` / `from_payload` are bypassed, stack
+/// several layers with `Chain` or `MiddlewareBuilder` rather than nesting `Layered`.
+///
+/// When the inner middleware packs bits, like `NextBits`, `into_bits` / `from_bits` are handed
+/// to it, and so is the end of every top-level value. The layer still enters and leaves those
+/// values, but doesn't see their bits.
+///
+/// Offsets reported to the layer count the bytes written or read through this `Layered`.
+#[derive(Debug)]
+pub struct Layered<L, M> {
+    layer: L,
+    inner: M,
+    #[cfg(any(feature = "sync", feature = "async"))]
+    depth: usize,
+    #[cfg(any(feature = "sync", feature = "async"))]
+    written: usize,
+    #[cfg(any(feature = "sync", feature = "async"))]
+    read: usize,
+}
+
+impl<L, M> Layered<L, M> {
+    pub fn new(inner: M, layer: impl Into<L>) -> Self {
+        Self {
+            layer: layer.into(),
+            inner,
+            #[cfg(any(feature = "sync", feature = "async"))]
+            depth: 0,
+            #[cfg(any(feature = "sync", feature = "async"))]
+            written: 0,
+            #[cfg(any(feature = "sync", feature = "async"))]
+            read: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn layer(&self) -> &L {
+        &self.layer
+    }
+
+    #[inline(always)]
+    pub fn layer_mut(&mut self) -> &mut L {
+        &mut self.layer
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &M {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut M {
+        &mut self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> M {
+        self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_parts(self) -> (L, M) {
+        (self.layer, self.inner)
+    }
+}
+
+#[cfg(any(feature = "sync", feature = "async"))]
+impl<L: MiddlewareLayer, M> Layered<L, M> {
+    fn enter(&mut self, direction: TraceDirection, ty: &'static str) -> Result<TraceEvent, Error> {
+        let offset = match direction {
+            TraceDirection::Encode => self.written,
+            TraceDirection::Decode => self.read,
+        };
+
+        let event = TraceEvent { direction, ty, depth: self.depth, offset };
+        self.layer.enter(&event)?;
+        self.depth += 1;
+
+        Ok(event)
+    }
+
+    fn leave(&mut self, event: &TraceEvent, error: Option<&Error>) {
+        let offset = match event.direction {
+            TraceDirection::Encode => self.written,
+            TraceDirection::Decode => self.read,
+        };
+
+        self.depth -= 1;
+        self.layer.leave(event, offset - event.offset, error);
+    }
+
+    fn on_write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        let bytes = as_bytes(data);
+
+        self.layer.write(bytes)?;
+        self.written += bytes.len();
+
+        Ok(())
+    }
+
+    fn on_read<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        let bytes = as_bytes(data);
+
+        self.read += bytes.len();
+        self.layer.read(bytes)
+    }
+}
+
+#[cfg(any(feature = "sync", feature = "async"))]
+#[inline(always)]
+fn as_bytes<T>(data: &[T]) -> &[u8] {
+    debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+
+    unsafe {
+        slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data))
+    }
+}
+
+#[cfg(all(feature = "info", any(feature = "sync", feature = "async")))]
+#[inline(always)]
+fn type_name<T: PayloadInfo + ?Sized>() -> &'static str {
+    T::TYPE
+}
+
+#[cfg(all(not(feature = "info"), any(feature = "sync", feature = "async")))]
+#[inline(always)]
+fn type_name<T: ?Sized>() -> &'static str {
+    core::any::type_name::<T>()
+}
+
+#[cfg(feature = "sync")]
+impl<'a, L: MiddlewareLayer, M: Middleware<'a>> Middleware<'a> for Layered<L, M> {
+    const PACKS_BITS: bool = M::PACKS_BITS;
+
+    fn into_payload<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        let event = self.enter(TraceDirection::Encode, type_name::<T>())?;
+        let result = value.into_payload(ctx, self);
+        self.leave(&event, result.as_ref().err());
+
+        if event.depth == 0 {
+            result?;
+            return self.inner.into_end();
+        }
+
+        result
+    }
+
+    fn from_payload<C, T: FromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        let event = self.enter(TraceDirection::Decode, type_name::<T>())?;
+        let result = T::from_payload(ctx, self);
+        self.leave(&event, result.as_ref().err());
+
+        if event.depth == 0 {
+            let end = self.inner.from_end();
+            return result.and_then(|value| end.map(|()| value));
+        }
+
+        result
+    }
+
+    fn write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        self.on_write(data)?;
+        self.inner.write(data)
+    }
+
+    fn read<T>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        let data = self.inner.read(nbytes)?;
+        self.on_read(data)?;
+
+        Ok(data)
+    }
+
+    fn read_mut<T>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        let data = self.inner.read_mut(nbytes)?;
+        self.on_read(data)?;
+
+        Ok(data)
+    }
+
+    fn read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error> {
+        self.inner.read_exact(buf)?;
+        self.on_read(buf)
+    }
+
+    fn read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error> {
+        let data = self.inner.read_owned(nbytes)?;
+        self.on_read(&data)?;
+
+        Ok(data)
+    }
+
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.layer.reserve::<T>(len)?;
+        self.inner.reserve::<T>(len)
+    }
+
+    fn into_bits<C, T: IntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, ctx: &mut C) -> Result<(), Error> {
+        if !M::PACKS_BITS {
+            return self.into_payload(value, ctx);
+        }
+
+        let event = self.enter(TraceDirection::Encode, type_name::<T>())?;
+        let result = self.inner.into_bits(value, bits, ctx);
+        self.leave(&event, result.as_ref().err());
+
+        result
+    }
+
+    fn from_bits<C, T: FromPayload<'a, C> + BitField>(&mut self, bits: u32, ctx: &mut C) -> Result<T, Error> {
+        if !M::PACKS_BITS {
+            return self.from_payload(ctx);
+        }
+
+        let event = self.enter(TraceDirection::Decode, type_name::<T>())?;
+        let result = self.inner.from_bits(bits, ctx);
+        self.leave(&event, result.as_ref().err());
+
+        result
+    }
+
+    #[inline(always)]
+    fn into_end(&mut self) -> Result<(), Error> {
+        self.inner.into_end()
+    }
+
+    #[inline(always)]
+    fn from_end(&mut self) -> Result<(), Error> {
+        self.inner.from_end()
+    }
+
+    #[inline(always)]
+    fn into_scope(&mut self) -> Result<(), Error> {
+        self.inner.into_scope()
+    }
+
+    #[inline(always)]
+    fn from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.inner.from_scope(len)
+    }
+
+    #[inline(always)]
+    fn from_scope_end(&mut self) -> Result<(), Error> {
+        self.inner.from_scope_end()
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.inner.position()
+    }
+
+    #[inline(always)]
+    fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        self.inner.push(value)
+    }
+
+    #[inline(always)]
+    fn push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        self.inner.push_mut(value)
+    }
+
+    #[inline(always)]
+    fn push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        self.inner.push_array(values)
+    }
+
+    #[inline(always)]
+    fn push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        self.inner.push_array_mut(values)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a, L: MiddlewareLayer, M: AsyncMiddleware<'a>> AsyncMiddleware<'a> for Layered<L, M> {
+    const PACKS_BITS: bool = M::PACKS_BITS;
+
+    async fn poll_into_payload<C: Send + Sync, T: AsyncIntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        let event = self.enter(TraceDirection::Encode, type_name::<T>())?;
+        let result = value.poll_into_payload(ctx, self).await;
+        self.leave(&event, result.as_ref().err());
+
+        if event.depth == 0 {
+            result?;
+            return self.inner.poll_into_end().await;
+        }
+
+        result
+    }
+
+    async fn poll_from_payload<C: Send + Sync, T: AsyncFromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        let event = self.enter(TraceDirection::Decode, type_name::<T>())?;
+        let result = T::poll_from_payload(ctx, self).await;
+        self.leave(&event, result.as_ref().err());
+
+        if event.depth == 0 {
+            let end = self.inner.poll_from_end().await;
+            return result.and_then(|value| end.map(|()| value));
+        }
+
+        result
+    }
+
+    async fn poll_write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        self.on_write(data)?;
+        self.inner.poll_write(data).await
+    }
+
+    async fn poll_read<T: 'a>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        let data = self.inner.poll_read(nbytes).await?;
+        self.on_read(data)?;
+
+        Ok(data)
+    }
+
+    async fn poll_read_mut<T: 'a>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        let data = self.inner.poll_read_mut(nbytes).await?;
+        self.on_read(data)?;
+
+        Ok(data)
+    }
+
+    async fn poll_read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error> {
+        self.inner.poll_read_exact(buf).await?;
+        self.on_read(buf)
+    }
+
+    async fn poll_read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error> {
+        let data = self.inner.poll_read_owned(nbytes).await?;
+        self.on_read(&data)?;
+
+        Ok(data)
+    }
+
+    async fn poll_reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.layer.reserve::<T>(len)?;
+        self.inner.poll_reserve::<T>(len).await
+    }
+
+    async fn poll_into_bits<C: Send + Sync, T: AsyncIntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, ctx: &mut C) -> Result<(), Error> {
+        if !M::PACKS_BITS {
+            return self.poll_into_payload(value, ctx).await;
+        }
+
+        let event = self.enter(TraceDirection::Encode, type_name::<T>())?;
+        let result = self.inner.poll_into_bits(value, bits, ctx).await;
+        self.leave(&event, result.as_ref().err());
+
+        result
+    }
+
+    async fn poll_from_bits<C: Send + Sync, T: AsyncFromPayload<'a, C> + BitField>(&mut self, bits: u32, ctx: &mut C) -> Result<T, Error> {
+        if !M::PACKS_BITS {
+            return self.poll_from_payload(ctx).await;
+        }
+
+        let event = self.enter(TraceDirection::Decode, type_name::<T>())?;
+        let result = self.inner.poll_from_bits(bits, ctx).await;
+        self.leave(&event, result.as_ref().err());
+
+        result
+    }
+
+    #[inline(always)]
+    async fn poll_into_end(&mut self) -> Result<(), Error> {
+        self.inner.poll_into_end().await
+    }
+
+    #[inline(always)]
+    async fn poll_from_end(&mut self) -> Result<(), Error> {
+        self.inner.poll_from_end().await
+    }
+
+    #[inline(always)]
+    async fn poll_into_scope(&mut self) -> Result<(), Error> {
+        self.inner.poll_into_scope().await
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.inner.poll_from_scope(len).await
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope_end(&mut self) -> Result<(), Error> {
+        self.inner.poll_from_scope_end().await
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.inner.position()
+    }
+
+    #[inline(always)]
+    async fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        self.inner.poll_push(value).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        self.inner.poll_push_mut(value).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        self.inner.poll_push_array(values).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        self.inner.poll_push_array_mut(values).await
+    }
+}
+
+/// Stacks layers over a base middleware.
+///
+/// The first layer added is the outermost one, see `Chain` for the order hooks run in.
+///
+/// ```
+/// use npsd::{Limits, LimitsLayer, MetricsLayer, MiddlewareBuilder, Next, Payload};
+///
+/// let mut metrics = MetricsLayer::default();
+/// let mut next = MiddlewareBuilder::new(Next::default())
+///     .layer(LimitsLayer::new(Limits::default()))
+///     .layer(&mut metrics)
+///     .build();
+///
+/// "Hello".to_string().into_packet(&mut (), &mut next).unwrap();
+/// drop(next);
+///
+/// assert_eq!(metrics.bytes_written(), 6);
+/// ```
+#[derive(Debug)]
+pub struct MiddlewareBuilder<L, M> {
+    layer: L,
+    inner: M,
+}
+
+impl<M> MiddlewareBuilder<(), M> {
+    pub fn new(inner: M) -> Self {
+        Self {
+            layer: (),
+            inner,
+        }
+    }
+}
+
+impl<L: MiddlewareLayer, M> MiddlewareBuilder<L, M> {
+    /// Adds a layer below the ones added so far.
+    pub fn layer<N: MiddlewareLayer>(self, layer: N) -> MiddlewareBuilder<Chain<L, N>, M> {
+        MiddlewareBuilder {
+            layer: Chain(self.layer, layer),
+            inner: self.inner,
+        }
+    }
+
+    pub fn build(self) -> Layered<L, M> {
+        Layered::new(self.inner, self.layer)
+    }
+}
+
+/// Counts values and bytes going through a `Layered` middleware.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct MetricsLayer {
+    encoded: usize,
+    decoded: usize,
+    errors: usize,
+    bytes_written: usize,
+    bytes_read: usize,
+    max_depth: usize,
+}
+
+impl MetricsLayer {
+    /// Returns the number of values encoded, nested values included.
+    #[inline(always)]
+    pub fn encoded(&self) -> usize {
+        self.encoded
+    }
+
+    /// Returns the number of values decoded, nested values included.
+    #[inline(always)]
+    pub fn decoded(&self) -> usize {
+        self.decoded
+    }
+
+    /// Returns the number of values that failed. A failing nested value also fails the values around it.
+    #[inline(always)]
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    #[inline(always)]
+    pub fn bytes_written(&self) -> usize {
+        self.bytes_written
+    }
+
+    #[inline(always)]
+    pub fn bytes_read(&self) -> usize {
+        self.bytes_read
+    }
+
+    /// Returns the deepest nesting seen, `1` for a value without nested values.
+    #[inline(always)]
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+}
+
+impl MiddlewareLayer for MetricsLayer {
+    fn enter(&mut self, event: &TraceEvent) -> Result<(), Error> {
+        match event.direction {
+            TraceDirection::Encode => self.encoded += 1,
+            TraceDirection::Decode => self.decoded += 1,
+        }
+
+        self.max_depth = self.max_depth.max(event.depth + 1);
+
+        Ok(())
+    }
+
+    fn leave(&mut self, _event: &TraceEvent, _nbytes: usize, error: Option<&Error>) {
+        if error.is_some() {
+            self.errors += 1;
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.bytes_written += data.len();
+        Ok(())
+    }
+
+    fn read(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.bytes_read += data.len();
+        Ok(())
+    }
+}
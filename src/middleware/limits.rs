@@ -0,0 +1,146 @@
+use core::mem;
+
+use crate::Error;
+
+use super::{Layered, MiddlewareLayer, TraceDirection, TraceEvent};
+
+/// Bounds applied while decoding untrusted input.
+///
+/// Lengths read from the wire are checked against these limits before anything is allocated,
+/// so a few malicious bytes can't make the decoder reserve gigabytes of memory.
+///
+/// - `max_len`: maximum number of elements in a collection (`Vec<T>`, `VecDeque<T>`, `HashMap<K, V>`, ...).
+/// - `max_bytes`: maximum length of a single string or byte slice.
+/// - `max_alloc`: maximum number of bytes reserved by collections and strings over one message.
+/// - `max_depth`: maximum nesting of `into_payload` / `from_payload` calls, primitives included.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Limits {
+    pub max_len: usize,
+    pub max_bytes: usize,
+    pub max_alloc: usize,
+    pub max_depth: usize,
+}
+
+impl Limits {
+    /// Limits that accept everything, to be narrowed down field by field.
+    pub const fn unlimited() -> Self {
+        Self {
+            max_len: usize::MAX,
+            max_bytes: usize::MAX,
+            max_alloc: usize::MAX,
+            max_depth: usize::MAX,
+        }
+    }
+
+    fn check(limit: &str, found: usize, max: usize) -> Result<(), Error> {
+        if found > max {
+            return Err(Error::LimitExceeded { limit: limit.to_string(), found, max });
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_len: 1 << 20,
+            max_bytes: 16 * 1024 * 1024,
+            max_alloc: 64 * 1024 * 1024,
+            max_depth: 128,
+        }
+    }
+}
+
+/// A `MiddlewareLayer` that enforces `Limits`.
+///
+/// Every nested value passes through the depth check, and the allocation budget is reset each
+/// time a new top-level value is decoded.
+///
+/// - `enter`: fails with `Error::LimitExceeded` when nested deeper than `max_depth`.
+/// - `reserve`: fails with `Error::LimitExceeded` when `len` is over `max_bytes` (for byte sized `T`)
+///   or `max_len` (for everything else), or when it would take the message over `max_alloc`.
+#[derive(Clone, Default, Debug)]
+pub struct LimitsLayer {
+    limits: Limits,
+    allocated: usize,
+}
+
+impl LimitsLayer {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            allocated: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    #[inline(always)]
+    pub fn limits_mut(&mut self) -> &mut Limits {
+        &mut self.limits
+    }
+
+    /// Returns the number of bytes reserved so far by the current message.
+    #[inline(always)]
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+}
+
+impl From<Limits> for LimitsLayer {
+    fn from(limits: Limits) -> Self {
+        Self::new(limits)
+    }
+}
+
+impl MiddlewareLayer for LimitsLayer {
+    fn enter(&mut self, event: &TraceEvent) -> Result<(), Error> {
+        if event.direction == TraceDirection::Decode && event.depth == 0 {
+            self.allocated = 0;
+        }
+
+        Limits::check("nesting depth", event.depth + 1, self.limits.max_depth)
+    }
+
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        if mem::size_of::<T>() == 1 {
+            Limits::check("byte length", len, self.limits.max_bytes)?;
+        } else {
+            Limits::check("collection length", len, self.limits.max_len)?;
+        }
+
+        let allocated = self.allocated.saturating_add(len.saturating_mul(mem::size_of::<T>()));
+        Limits::check("allocated bytes", allocated, self.limits.max_alloc)?;
+
+        self.allocated = allocated;
+
+        Ok(())
+    }
+}
+
+/// A `Middleware` and `AsyncMiddleware` wrapper that enforces `Limits` on top of another middleware.
+///
+/// This is `Layered` with a single `LimitsLayer`, use `MiddlewareBuilder` to combine it with other layers.
+pub type NextLimits<M> = Layered<LimitsLayer, M>;
+
+impl<M> Layered<LimitsLayer, M> {
+    #[inline(always)]
+    pub fn limits(&self) -> &Limits {
+        self.layer().limits()
+    }
+
+    #[inline(always)]
+    pub fn limits_mut(&mut self) -> &mut Limits {
+        self.layer_mut().limits_mut()
+    }
+
+    /// Returns the number of bytes reserved so far by the current message.
+    #[inline(always)]
+    pub fn allocated(&self) -> usize {
+        self.layer().allocated()
+    }
+}
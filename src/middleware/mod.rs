@@ -1,3 +1,51 @@
 pub mod next;
+pub mod limits;
+pub mod layer;
+pub mod trace;
+pub mod checksum;
+pub mod packet;
+pub mod compress;
 
-pub use next::*;
\ No newline at end of file
+#[cfg(any(feature = "sync", feature = "async"))]
+pub mod bits;
+
+#[cfg(any(feature = "sync", feature = "async"))]
+pub mod scoped;
+
+#[cfg(feature = "chacha20poly1305")]
+pub mod seal;
+
+#[cfg(all(feature = "info", any(feature = "sync", feature = "async")))]
+pub mod typed;
+
+#[cfg(feature = "sync")]
+pub mod stream;
+
+#[cfg(feature = "tokio")]
+pub mod async_stream;
+
+pub use next::*;
+pub use limits::*;
+pub use layer::*;
+pub use trace::*;
+pub use checksum::*;
+pub use packet::*;
+pub use compress::*;
+
+#[cfg(any(feature = "sync", feature = "async"))]
+pub use bits::*;
+
+#[cfg(any(feature = "sync", feature = "async"))]
+pub use scoped::*;
+
+#[cfg(feature = "chacha20poly1305")]
+pub use seal::*;
+
+#[cfg(all(feature = "info", any(feature = "sync", feature = "async")))]
+pub use typed::*;
+
+#[cfg(feature = "sync")]
+pub use stream::*;
+
+#[cfg(feature = "tokio")]
+pub use async_stream::*;
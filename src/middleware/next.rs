@@ -12,7 +12,19 @@ use crate::{AsyncFromPayload, AsyncIntoPayload, AsyncMiddleware};
 #[cfg(feature = "crossbeam")]
 use crate::Stack;
 
-use crate::{AnyBox, Error};
+#[cfg(feature = "info")]
+use super::TraceSink;
+
+#[cfg(all(feature = "info", any(feature = "sync", feature = "async")))]
+use super::{TraceDirection, TraceEvent};
+
+#[cfg(all(feature = "crossbeam", any(feature = "sync", feature = "async")))]
+use crate::AnyBox;
+
+use crate::Error;
+
+#[cfg(any(feature = "sync", feature = "async"))]
+use super::Scopes;
 
 /// A no-op implementation of the `Middleware` and `AsyncMiddleware` traits.
 ///
@@ -40,6 +52,8 @@ use crate::{AnyBox, Error};
 ///     - Reads data from the underlying buffer.
 /// - `fn read_mut<'a, T>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error>`:
 ///     - Reads data from the underlying buffer as mut.
+/// - `fn position(&self) -> usize`:
+///     - Returns the current offset into the underlying buffer.
 /// - `fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error>`:
 ///     - Pushes a value onto the stack.
 /// - `fn push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error>`:
@@ -67,14 +81,37 @@ impl<'a> Middleware<'a> for Next<'a> {
 
     #[inline(always)]
     fn read<T>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        self.scopes.read(self.buf.1, nbytes)?;
         self.buf.read(nbytes)
     }
 
     #[inline(always)]
     fn read_mut<T>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        self.scopes.read(self.buf.1, nbytes)?;
         self.buf.read_mut(nbytes)
     }
 
+    #[inline(always)]
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.reserve::<T>(self.buf.1, len)
+    }
+
+    #[inline(always)]
+    fn from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.enter(self.buf.1, len, Some(self.buf.0.len() - self.buf.1))
+    }
+
+    #[inline(always)]
+    fn from_scope_end(&mut self) -> Result<(), Error> {
+        self.buf.1 += self.scopes.leave(self.buf.1);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.buf.1
+    }
+
     #[cfg(feature = "crossbeam")]
     #[inline(always)]
     fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
@@ -120,6 +157,8 @@ impl<'a> Middleware<'a> for Next<'a> {
 ///     - Asynchronously reads data from the underlying buffer.
 /// - `async fn poll_read_mut<T: 'a>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error>`:
 ///     - Asynchronously reads data from the underlying buffer as mut.
+/// - `fn position(&self) -> usize`:
+///     - Returns the current offset into the underlying buffer.
 /// - `async fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error>`:
 ///     - Asynchronously pushes a value onto the stack.
 /// - `async fn poll_push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error>`:
@@ -154,14 +193,37 @@ impl<'a> AsyncMiddleware<'a> for Next<'a> {
 
     #[inline(always)]
     async fn poll_read<T: 'a>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        self.scopes.read(self.buf.1, nbytes)?;
         self.buf.read(nbytes)
     }
 
     #[inline(always)]
     async fn poll_read_mut<T: 'a>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        self.scopes.read(self.buf.1, nbytes)?;
         self.buf.read_mut(nbytes)
     }
 
+    #[inline(always)]
+    async fn poll_reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.reserve::<T>(self.buf.1, len)
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.enter(self.buf.1, len, Some(self.buf.0.len() - self.buf.1))
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope_end(&mut self) -> Result<(), Error> {
+        self.buf.1 += self.scopes.leave(self.buf.1);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.buf.1
+    }
+
     #[cfg(feature = "crossbeam")]
     #[inline(always)]
     async fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
@@ -269,9 +331,13 @@ impl CowRw for (Cow<'_, [u8]>, usize) {
 ///     - Returns a vector containing the serialized data from the underlying buffer.
 /// - `pub fn as_slice(&self) -> &[u8]`:
 ///     - Returns a slice of the underlying buffer.
+/// - `pub fn position(&self) -> usize`:
+///     - Returns the number of bytes consumed from the underlying buffer so far.
 #[derive(Clone, Debug)]
 pub struct Next<'a>{
     buf: (Cow<'a, [u8]>, usize),
+    #[cfg(any(feature = "sync", feature = "async"))]
+    scopes: Scopes,
 
     #[cfg(feature = "crossbeam")]
     stack: Stack<'a>,
@@ -281,6 +347,8 @@ impl<'a> Next<'a> {
     pub fn from_mut(cow: &'a mut Cow<'_, [u8]>) -> Self {
         Self {
             buf: (Cow::Borrowed(&*cow), 0),
+            #[cfg(any(feature = "sync", feature = "async"))]
+            scopes: Scopes::default(),
             #[cfg(feature = "crossbeam")]
             stack: Stack::new(),
         }
@@ -289,6 +357,8 @@ impl<'a> Next<'a> {
     pub fn with_mtu(mtu: usize) -> Self {
         Self {
             buf: (Cow::from(Vec::with_capacity(mtu)), 0),
+            #[cfg(any(feature = "sync", feature = "async"))]
+            scopes: Scopes::default(),
             #[cfg(feature = "crossbeam")]
             stack: Stack::new(),
         }
@@ -303,12 +373,19 @@ impl<'a> Next<'a> {
     pub fn as_slice(&self) -> &[u8] { 
         self.buf.0.as_ref()
     }
+
+    #[inline(always)]
+    pub fn position(&self) -> usize {
+        self.buf.1
+    }
 }
 
 impl<'a> Default for Next<'a> {
     fn default() -> Self {
         Self {
             buf: (Cow::from(Vec::new()), 0),
+            #[cfg(any(feature = "sync", feature = "async"))]
+            scopes: Scopes::default(),
             #[cfg(feature = "crossbeam")]
             stack: Stack::new(),
         }
@@ -319,6 +396,8 @@ impl<'a, T: Into<Cow<'a, [u8]>>> From<T> for Next<'a> {
     fn from(value: T) -> Self {
         Self {
             buf: (value.into(), 0),
+            #[cfg(any(feature = "sync", feature = "async"))]
+            scopes: Scopes::default(),
             #[cfg(feature = "crossbeam")]
             stack: Stack::new(),
         }
@@ -328,12 +407,19 @@ impl<'a, T: Into<Cow<'a, [u8]>>> From<T> for Next<'a> {
 /// The `NextTrace` struct provides middleware functionality for tracing and debugging serialization
 /// and deserialization operations. It maintains a nested context to help identify and report errors
 /// and their locations in the payload structure. Requires the `info` feature to be enabled.
+///
+/// The start and end of every value, with its byte offset, the number of bytes it took and any error,
+/// are reported to a `TraceSink`. By default the events are dropped; use `with_sink` to report them
+/// somewhere, e.g. `with_sink(PrintSink::default())` to print the path of each value to stdout.
 #[cfg(feature = "info")]
 #[derive(Clone, Debug)]
-pub struct NextTrace<'a> {
+pub struct NextTrace<'a, S = ()> {
     buf: (Cow<'a, [u8]>, usize), 
+    #[cfg(any(feature = "sync", feature = "async"))]
+    scopes: Scopes,
     depth: usize, 
     path: LinkedList<&'static str>,
+    sink: S,
     #[cfg(feature = "crossbeam")]
     stack: Stack<'a>
 }
@@ -346,8 +432,11 @@ impl<'a> NextTrace<'a> {
     pub fn from_mut(cow: &'a mut Cow<'_, [u8]>) -> Self {
         Self {
             buf: (Cow::Borrowed(&*cow), 0), 
+            #[cfg(any(feature = "sync", feature = "async"))]
+            scopes: Scopes::default(),
             depth: MAX_NESTED_DEPTH, 
             path: LinkedList::new(),
+            sink: (),
             #[cfg(feature = "crossbeam")]
             stack: Stack::new(),
         }
@@ -356,8 +445,11 @@ impl<'a> NextTrace<'a> {
     pub fn with_mtu(mtu: usize) -> Self {
         Self {
             buf: (Cow::from(Vec::with_capacity(mtu)), 0), 
+            #[cfg(any(feature = "sync", feature = "async"))]
+            scopes: Scopes::default(),
             depth: MAX_NESTED_DEPTH, 
             path: LinkedList::new(),
+            sink: (),
             #[cfg(feature = "crossbeam")]
             stack: Stack::new(),
         }
@@ -366,12 +458,32 @@ impl<'a> NextTrace<'a> {
     pub fn with_depth(depth: usize) -> Self {
         Self {
             buf: (Cow::from(Vec::new()), 0), 
+            #[cfg(any(feature = "sync", feature = "async"))]
+            scopes: Scopes::default(),
             depth, 
             path: LinkedList::new(),
+            sink: (),
             #[cfg(feature = "crossbeam")]
             stack: Stack::new(),
         }
     }
+}
+
+#[cfg(feature = "info")]
+impl<'a, S: TraceSink> NextTrace<'a, S> {
+    /// Replaces the sink that trace events are reported to.
+    pub fn with_sink<U: TraceSink>(self, sink: U) -> NextTrace<'a, U> {
+        NextTrace {
+            buf: self.buf,
+            #[cfg(any(feature = "sync", feature = "async"))]
+            scopes: self.scopes,
+            depth: self.depth,
+            path: self.path,
+            sink,
+            #[cfg(feature = "crossbeam")]
+            stack: self.stack,
+        }
+    }
 
     #[inline(always)]
     pub fn serialized(&self) -> Vec<u8> {
@@ -382,6 +494,44 @@ impl<'a> NextTrace<'a> {
     pub fn as_slice(&self) -> &[u8] { 
         self.buf.0.as_ref()
     }
+
+    #[inline(always)]
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    #[inline(always)]
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    #[inline(always)]
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    #[cfg(any(feature = "sync", feature = "async"))]
+    fn trace_start(&mut self, direction: TraceDirection, ty: &'static str) -> TraceEvent {
+        let offset = match direction {
+            TraceDirection::Encode => self.buf.0.len(),
+            TraceDirection::Decode => self.buf.1,
+        };
+
+        let event = TraceEvent { direction, ty, depth: self.path.len(), offset };
+        self.sink.start(&event);
+
+        event
+    }
+
+    #[cfg(any(feature = "sync", feature = "async"))]
+    fn trace_end(&mut self, event: &TraceEvent, error: Option<&Error>) {
+        let offset = match event.direction {
+            TraceDirection::Encode => self.buf.0.len(),
+            TraceDirection::Decode => self.buf.1,
+        };
+
+        self.sink.end(event, offset - event.offset, error);
+    }
 }
 
 #[cfg(feature = "info")]
@@ -389,8 +539,11 @@ impl<'a, T: Into<Cow<'a, [u8]>>> From<T> for NextTrace<'a> {
     fn from(value: T) -> Self {
         Self {
             buf: (value.into(), 0), 
+            #[cfg(any(feature = "sync", feature = "async"))]
+            scopes: Scopes::default(),
             depth: MAX_NESTED_DEPTH, 
             path: LinkedList::new(),
+            sink: (),
             #[cfg(feature = "crossbeam")]
             stack: Stack::new(),
         }
@@ -402,8 +555,11 @@ impl<'a> Default for NextTrace<'a> {
     fn default() -> Self {
         Self {
             buf: (Cow::from(Vec::new()), 0), 
+            #[cfg(any(feature = "sync", feature = "async"))]
+            scopes: Scopes::default(),
             depth: MAX_NESTED_DEPTH, 
             path: LinkedList::new(),
+            sink: (),
             #[cfg(feature = "crossbeam")]
             stack: Stack::new(),
         }
@@ -412,19 +568,20 @@ impl<'a> Default for NextTrace<'a> {
 
 #[cfg(feature = "info")]
 #[cfg(feature = "sync")]
-impl<'a> Middleware<'a> for NextTrace<'a> {
+impl<'a, S: TraceSink> Middleware<'a> for NextTrace<'a, S> {
     #[inline(always)]
     fn into_payload<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
         if self.path.len() > self.depth {
             return Err(Error::NestedDepthLimit(T::TYPE.to_string()))
         }
 
+        let event = self.trace_start(TraceDirection::Encode, T::TYPE);
         self.path.push_back(T::TYPE);
- 
-        let path: Vec<&str> = self.path.iter().cloned().collect();
-        println!("{}", path.join(" -> "));
 
-        match value.into_payload(ctx, self) {
+        let result = value.into_payload(ctx, self);
+        self.trace_end(&event, result.as_ref().err());
+
+        match result {
             Ok(value) => {
                 self.path.pop_back();
 
@@ -448,12 +605,13 @@ impl<'a> Middleware<'a> for NextTrace<'a> {
             return Err(Error::NestedDepthLimit(T::TYPE.to_string()))
         }
 
+        let event = self.trace_start(TraceDirection::Decode, T::TYPE);
         self.path.push_back(T::TYPE);
 
-        let path: Vec<&str> = self.path.iter().cloned().collect();
-        println!("{}", path.join(" <- "));
+        let result = T::from_payload(ctx, self);
+        self.trace_end(&event, result.as_ref().err());
 
-        match T::from_payload(ctx, self) {
+        match result {
             Ok(value) => {
                 self.path.pop_back();
 
@@ -478,14 +636,37 @@ impl<'a> Middleware<'a> for NextTrace<'a> {
 
     #[inline(always)]
     fn read<T>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        self.scopes.read(self.buf.1, nbytes)?;
         self.buf.read(nbytes)
     }
 
     #[inline(always)]
     fn read_mut<T>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        self.scopes.read(self.buf.1, nbytes)?;
         self.buf.read_mut(nbytes)
     }
 
+    #[inline(always)]
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.reserve::<T>(self.buf.1, len)
+    }
+
+    #[inline(always)]
+    fn from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.enter(self.buf.1, len, Some(self.buf.0.len() - self.buf.1))
+    }
+
+    #[inline(always)]
+    fn from_scope_end(&mut self) -> Result<(), Error> {
+        self.buf.1 += self.scopes.leave(self.buf.1);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.buf.1
+    }
+
     #[cfg(feature = "crossbeam")]
     fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
         Ok(self.stack.push(value))
@@ -509,7 +690,7 @@ impl<'a> Middleware<'a> for NextTrace<'a> {
 
 #[cfg(feature = "info")]
 #[cfg(feature = "async")]
-impl<'a> AsyncMiddleware<'a> for NextTrace<'a> {
+impl<'a, S: TraceSink> AsyncMiddleware<'a> for NextTrace<'a, S> {
     async fn poll_into_payload<C: Send + Sync, T: AsyncIntoPayload<C>>(
         &mut self,
         value: &T,
@@ -519,12 +700,13 @@ impl<'a> AsyncMiddleware<'a> for NextTrace<'a> {
             return Err(Error::NestedDepthLimit(T::TYPE.to_string()));
         }
 
+        let event = self.trace_start(TraceDirection::Encode, T::TYPE);
         self.path.push_back(T::TYPE);
 
-        let path: Vec<&str> = self.path.iter().cloned().collect();
-        println!("{}", path.join(" -> "));
+        let result = value.poll_into_payload(ctx, self).await;
+        self.trace_end(&event, result.as_ref().err());
 
-        match value.poll_into_payload(ctx, self).await {
+        match result {
             Ok(value) => {
                 self.path.pop_back();
                 Ok(value)
@@ -549,12 +731,13 @@ impl<'a> AsyncMiddleware<'a> for NextTrace<'a> {
             return Err(Error::NestedDepthLimit(T::TYPE.to_string()));
         }
 
+        let event = self.trace_start(TraceDirection::Decode, T::TYPE);
         self.path.push_back(T::TYPE);
 
-        let path: Vec<&str> = self.path.iter().cloned().collect();
-        println!("{}", path.join(" <- "));
+        let result = T::poll_from_payload(ctx, self).await;
+        self.trace_end(&event, result.as_ref().err());
 
-        match T::poll_from_payload(ctx, self).await {
+        match result {
             Ok(value) => {
                 self.path.pop_back();
                 Ok(value)
@@ -578,14 +761,37 @@ impl<'a> AsyncMiddleware<'a> for NextTrace<'a> {
 
     #[inline(always)]
     async fn poll_read<T: 'a>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        self.scopes.read(self.buf.1, nbytes)?;
         self.buf.read(nbytes)
     }
 
     #[inline(always)]
     async fn poll_read_mut<T: 'a>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        self.scopes.read(self.buf.1, nbytes)?;
         self.buf.read_mut(nbytes)
     }
 
+    #[inline(always)]
+    async fn poll_reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.reserve::<T>(self.buf.1, len)
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.enter(self.buf.1, len, Some(self.buf.0.len() - self.buf.1))
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope_end(&mut self) -> Result<(), Error> {
+        self.buf.1 += self.scopes.leave(self.buf.1);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.buf.1
+    }
+
     #[cfg(feature = "crossbeam")]
     #[inline(always)]
     async fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
@@ -0,0 +1,269 @@
+#[cfg(feature = "sync")]
+use crate::{FromPayload, IntoPayload, Middleware};
+
+#[cfg(feature = "async")]
+use crate::{AsyncFromPayload, AsyncIntoPayload, AsyncMiddleware};
+
+#[cfg(any(feature = "sync", feature = "async"))]
+use crate::AnyBox;
+
+use crate::Error;
+
+use super::Next;
+
+/// A transformation applied to a whole encoded message, such as compression.
+///
+/// Unlike a `MiddlewareLayer`, a filter sees the complete body at once, so it can rewrite it
+/// freely. A tuple `(A, B)` applies `A` and then `B` when encoding, and undoes them in reverse
+/// order when decoding.
+///
+/// ### Methods
+/// - `fn encode(&mut self, body: &[u8]) -> Result<Vec<u8>, Error>`:
+///     - Turns an encoded body into the packet sent on the wire.
+/// - `fn decode(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error>`:
+///     - Turns a packet received from the wire back into the encoded body.
+pub trait PacketFilter {
+    fn encode(&mut self, body: &[u8]) -> Result<Vec<u8>, Error>;
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+impl PacketFilter for () {
+    #[inline(always)]
+    fn encode(&mut self, body: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(body.to_vec())
+    }
+
+    #[inline(always)]
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(packet.to_vec())
+    }
+}
+
+impl<F: PacketFilter> PacketFilter for &mut F {
+    #[inline(always)]
+    fn encode(&mut self, body: &[u8]) -> Result<Vec<u8>, Error> {
+        (**self).encode(body)
+    }
+
+    #[inline(always)]
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        (**self).decode(packet)
+    }
+}
+
+impl<A: PacketFilter, B: PacketFilter> PacketFilter for (A, B) {
+    fn encode(&mut self, body: &[u8]) -> Result<Vec<u8>, Error> {
+        let body = self.0.encode(body)?;
+        self.1.encode(&body)
+    }
+
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        let packet = self.1.decode(packet)?;
+        self.0.decode(&packet)
+    }
+}
+
+/// A `Middleware` and `AsyncMiddleware` that runs a `PacketFilter` over the whole message.
+///
+/// Values are encoded into an in-memory body, which `seal` passes through the filter to produce
+/// the packet. `open` runs the filter backwards over a received packet before anything is decoded,
+/// so `FromPayload` code only ever sees the restored body.
+///
+/// ```
+/// use npsd::{NextPacket, Payload};
+///
+/// let mut next = NextPacket::new(());
+/// "Hello".to_string().into_packet(&mut (), &mut next).unwrap();
+///
+/// let packet = next.seal().unwrap();
+/// let mut next = NextPacket::open((), &packet).unwrap();
+///
+/// assert_eq!(String::from_packet(&mut (), &mut next).unwrap(), "Hello");
+/// ```
+#[derive(Debug)]
+pub struct NextPacket<'a, F> {
+    filter: F,
+    body: Next<'a>,
+}
+
+impl<'a, F: PacketFilter> NextPacket<'a, F> {
+    /// Creates an empty body to encode into.
+    pub fn new(filter: F) -> Self {
+        Self {
+            filter,
+            body: Next::default(),
+        }
+    }
+
+    /// Restores the body of `packet`, ready to be decoded.
+    pub fn open(mut filter: F, packet: &[u8]) -> Result<Self, Error> {
+        let body = filter.decode(packet)?;
+
+        Ok(Self {
+            filter,
+            body: Next::from(body),
+        })
+    }
+
+    /// Returns the packet for everything encoded so far.
+    pub fn seal(&mut self) -> Result<Vec<u8>, Error> {
+        self.filter.encode(self.body.as_slice())
+    }
+
+    /// Returns the body, before it goes through the filter.
+    #[inline(always)]
+    pub fn body(&self) -> &Next<'a> {
+        &self.body
+    }
+
+    #[inline(always)]
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    #[inline(always)]
+    pub fn filter_mut(&mut self) -> &mut F {
+        &mut self.filter
+    }
+
+    #[inline(always)]
+    pub fn into_filter(self) -> F {
+        self.filter
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<'a, F: PacketFilter> Middleware<'a> for NextPacket<'a, F> {
+    #[inline(always)]
+    fn into_payload<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        value.into_payload(ctx, self)
+    }
+
+    #[inline(always)]
+    fn from_payload<C, T: FromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        T::from_payload(ctx, self)
+    }
+
+    #[inline(always)]
+    fn write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        self.body.write(data)
+    }
+
+    #[inline(always)]
+    fn read<T>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        self.body.read(nbytes)
+    }
+
+    #[inline(always)]
+    fn read_mut<T>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        self.body.read_mut(nbytes)
+    }
+
+    #[inline(always)]
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.body.reserve::<T>(len)
+    }
+
+    #[inline(always)]
+    fn from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.body.from_scope(len)
+    }
+
+    #[inline(always)]
+    fn from_scope_end(&mut self) -> Result<(), Error> {
+        self.body.from_scope_end()
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.body.position()
+    }
+
+    #[inline(always)]
+    fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        Middleware::push(&mut self.body, value)
+    }
+
+    #[inline(always)]
+    fn push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        Middleware::push_mut(&mut self.body, value)
+    }
+
+    #[inline(always)]
+    fn push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        Middleware::push_array(&mut self.body, values)
+    }
+
+    #[inline(always)]
+    fn push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        Middleware::push_array_mut(&mut self.body, values)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a, F: PacketFilter + Send + Sync> AsyncMiddleware<'a> for NextPacket<'a, F> {
+    #[inline(always)]
+    async fn poll_into_payload<C: Send + Sync, T: AsyncIntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        value.poll_into_payload(ctx, self).await
+    }
+
+    #[inline(always)]
+    async fn poll_from_payload<C: Send + Sync, T: AsyncFromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        T::poll_from_payload(ctx, self).await
+    }
+
+    #[inline(always)]
+    async fn poll_write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        self.body.poll_write(data).await
+    }
+
+    #[inline(always)]
+    async fn poll_read<T: 'a>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        self.body.poll_read(nbytes).await
+    }
+
+    #[inline(always)]
+    async fn poll_read_mut<T: 'a>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        self.body.poll_read_mut(nbytes).await
+    }
+
+    #[inline(always)]
+    async fn poll_reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.body.poll_reserve::<T>(len).await
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.body.poll_from_scope(len).await
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope_end(&mut self) -> Result<(), Error> {
+        self.body.poll_from_scope_end().await
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.body.position()
+    }
+
+    #[inline(always)]
+    async fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        self.body.poll_push(value).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        self.body.poll_push_mut(value).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        self.body.poll_push_array(values).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        self.body.poll_push_array_mut(values).await
+    }
+}
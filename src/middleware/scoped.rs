@@ -0,0 +1,255 @@
+use core::mem;
+
+#[cfg(feature = "sync")]
+use crate::{FromPayload, IntoPayload, Middleware};
+
+#[cfg(feature = "async")]
+use crate::{AsyncFromPayload, AsyncIntoPayload, AsyncMiddleware};
+
+use crate::{AnyBox, BitField, Error};
+
+use super::{Next, NextBits};
+
+/// The bounds of the length prefixed parts a reading middleware is decoding, innermost last.
+///
+/// Parts are entered with `from_scope` and left with `from_scope_end`, see `Middleware`. Reads,
+/// reserved lengths and nested parts that go past the end of the innermost part fail with
+/// `Error::OutOfRange`, since the input itself may well go on. Bytes left over once the part is
+/// decoded, such as trailing fields added by a newer peer, are skipped.
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Scopes(Vec<(usize, usize)>);
+
+impl Scopes {
+    /// Enters a part of `len` bytes at `position`, out of the `available` bytes left in the input
+    /// when they are known.
+    pub(crate) fn enter(&mut self, position: usize, len: usize, available: Option<usize>) -> Result<(), Error> {
+        match (self.remaining(position), available) {
+            (Some(remaining), _) if len > remaining => {
+                return Err(Error::OutOfRange(format!("Part of `{}` bytes is over the `{}` left in the enclosing part", len, remaining)));
+            },
+            (None, Some(available)) if len > available => {
+                return Err(Error::InvalidLength { expected: len, found: available });
+            },
+            _ => {},
+        }
+
+        self.0.push((position, position.saturating_add(len)));
+
+        Ok(())
+    }
+
+    /// Leaves the innermost part, returning the number of bytes left in it from `position`, which
+    /// the middleware skips.
+    pub(crate) fn leave(&mut self, position: usize) -> usize {
+        self.0.pop().map_or(0, |(_, end)| end.saturating_sub(position))
+    }
+
+    /// Returns the number of bytes left in the innermost part, if any.
+    #[inline(always)]
+    pub(crate) fn remaining(&self, position: usize) -> Option<usize> {
+        self.0.last().map(|&(_, end)| end.saturating_sub(position))
+    }
+
+    #[inline(always)]
+    pub(crate) fn read(&self, position: usize, nbytes: usize) -> Result<(), Error> {
+        match self.remaining(position) {
+            Some(remaining) if nbytes > remaining => {
+                Err(Error::OutOfRange(format!("Read of `{}` bytes is over the `{}` left in the part", nbytes, remaining)))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Since every element of a sized type takes at least one byte, a length over the bytes left
+    /// in the part can only come from corrupt data, whatever the limits of the middleware.
+    #[inline(always)]
+    pub(crate) fn reserve<T>(&self, position: usize, len: usize) -> Result<(), Error> {
+        match self.remaining(position) {
+            Some(remaining) if mem::size_of::<T>() > 0 && len > remaining => {
+                Err(Error::OutOfRange(format!("Length `{}` is over the `{}` bytes left in the part", len, remaining)))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A `Middleware` and `AsyncMiddleware` that encodes a length prefixed part of a payload, such as
+/// a `#[npsd(tagged)]` field or the body of an `#[npsd(open)]` enum variant, for a middleware that
+/// packs bits.
+///
+/// Values written with `into_bits` are packed like `NextBits` does, from the first bit of the
+/// part, and the last byte is padded by `serialized`, so the outer middleware can decode the part
+/// in place between `from_scope` and `from_scope_end`. Parts for middleware that don't pack bits
+/// are encoded into a plain `Next`.
+#[derive(Debug)]
+pub struct NextScoped<'a> {
+    inner: NextBits<Next<'a>>,
+}
+
+impl<'a> NextScoped<'a> {
+    pub fn new() -> Self {
+        Self {
+            inner: NextBits::new(Next::default()),
+        }
+    }
+
+    /// Returns the encoded part, its last byte padded with zero bits.
+    pub fn serialized(&self) -> Vec<u8> {
+        let mut bytes = self.inner.get_ref().serialized();
+        bytes.extend(self.inner.pending());
+        bytes
+    }
+}
+
+impl<'a> Default for NextScoped<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<'a> Middleware<'a> for NextScoped<'a> {
+    const PACKS_BITS: bool = true;
+
+    #[inline(always)]
+    fn into_payload<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        value.into_payload(ctx, self)
+    }
+
+    #[inline(always)]
+    fn from_payload<C, T: FromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        T::from_payload(ctx, self)
+    }
+
+    #[inline(always)]
+    fn write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        self.inner.write(data)
+    }
+
+    #[inline(always)]
+    fn read<T>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        self.inner.read(nbytes)
+    }
+
+    #[inline(always)]
+    fn read_mut<T>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        self.inner.read_mut(nbytes)
+    }
+
+    #[inline(always)]
+    fn into_bits<C, T: IntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, ctx: &mut C) -> Result<(), Error> {
+        self.inner.into_bits(value, bits, ctx)
+    }
+
+    #[inline(always)]
+    fn from_bits<C, T: FromPayload<'a, C> + BitField>(&mut self, bits: u32, ctx: &mut C) -> Result<T, Error> {
+        self.inner.from_bits(bits, ctx)
+    }
+
+    #[inline(always)]
+    fn into_scope(&mut self) -> Result<(), Error> {
+        self.inner.into_scope()
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        Middleware::position(&self.inner)
+    }
+
+    #[inline(always)]
+    fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        self.inner.push(value)
+    }
+
+    #[inline(always)]
+    fn push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        self.inner.push_mut(value)
+    }
+
+    #[inline(always)]
+    fn push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        self.inner.push_array(values)
+    }
+
+    #[inline(always)]
+    fn push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        self.inner.push_array_mut(values)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a> AsyncMiddleware<'a> for NextScoped<'a> {
+    const PACKS_BITS: bool = true;
+
+    #[inline(always)]
+    fn poll_into_payload<C: Send + Sync, T: AsyncIntoPayload<C>>(
+        &mut self,
+        value: &T,
+        ctx: &mut C
+    ) -> impl core::future::Future<Output = Result<(), Error>> {
+        value.poll_into_payload(ctx, self)
+    }
+
+    #[inline(always)]
+    fn poll_from_payload<C: Send + Sync, T: AsyncFromPayload<'a, C>>(
+        &mut self,
+        ctx: &mut C,
+    ) -> impl core::future::Future<Output = Result<T, Error>> {
+        T::poll_from_payload(ctx, self)
+    }
+
+    #[inline(always)]
+    async fn poll_write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        self.inner.poll_write(data).await
+    }
+
+    #[inline(always)]
+    async fn poll_read<T: 'a>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        self.inner.poll_read(nbytes).await
+    }
+
+    #[inline(always)]
+    async fn poll_read_mut<T: 'a>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        self.inner.poll_read_mut(nbytes).await
+    }
+
+    #[inline(always)]
+    async fn poll_into_bits<C: Send + Sync, T: AsyncIntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, ctx: &mut C) -> Result<(), Error> {
+        self.inner.poll_into_bits(value, bits, ctx).await
+    }
+
+    #[inline(always)]
+    async fn poll_from_bits<C: Send + Sync, T: AsyncFromPayload<'a, C> + BitField>(&mut self, bits: u32, ctx: &mut C) -> Result<T, Error> {
+        self.inner.poll_from_bits(bits, ctx).await
+    }
+
+    #[inline(always)]
+    async fn poll_into_scope(&mut self) -> Result<(), Error> {
+        self.inner.poll_into_scope().await
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        AsyncMiddleware::position(&self.inner)
+    }
+
+    #[inline(always)]
+    async fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        self.inner.poll_push(value).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        self.inner.poll_push_mut(value).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        self.inner.poll_push_array(values).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        self.inner.poll_push_array_mut(values).await
+    }
+}
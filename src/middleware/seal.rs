@@ -0,0 +1,69 @@
+use core::fmt;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use crate::Error;
+
+use super::PacketFilter;
+
+/// The length of the nonce at the start of every sealed packet, in bytes.
+pub const SEAL_NONCE_LEN: usize = 12;
+
+/// The length of the authentication tag at the end of every sealed packet, in bytes.
+pub const SEAL_TAG_LEN: usize = 16;
+
+/// A `PacketFilter` that encrypts and authenticates messages with ChaCha20-Poly1305, to be used
+/// with `NextPacket`. Requires the `chacha20poly1305` feature to be enabled.
+///
+/// Every packet is a fresh random nonce, followed by the ciphertext and its tag. The tag is
+/// checked before the body is handed to `FromPayload`, so a packet that was modified, truncated
+/// or sealed with another key fails with `Error::AuthenticationFailed` and is never decoded.
+///
+/// Random nonces are safe for up to about 2^32 messages per key, rotate keys well before that.
+#[derive(Clone)]
+pub struct Sealer {
+    cipher: ChaCha20Poly1305,
+}
+
+impl Sealer {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    /// Returns a new random key.
+    pub fn generate_key() -> [u8; 32] {
+        ChaCha20Poly1305::generate_key(&mut OsRng).into()
+    }
+}
+
+impl fmt::Debug for Sealer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sealer").finish_non_exhaustive()
+    }
+}
+
+impl PacketFilter for Sealer {
+    fn encode(&mut self, body: &[u8]) -> Result<Vec<u8>, Error> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, body).map_err(|_| Error::AuthenticationFailed)?;
+
+        let mut packet = Vec::with_capacity(SEAL_NONCE_LEN + ciphertext.len());
+        packet.extend_from_slice(&nonce);
+        packet.extend_from_slice(&ciphertext);
+
+        Ok(packet)
+    }
+
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        if packet.len() < SEAL_NONCE_LEN + SEAL_TAG_LEN {
+            return Err(Error::AuthenticationFailed);
+        }
+
+        let (nonce, ciphertext) = packet.split_at(SEAL_NONCE_LEN);
+
+        self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| Error::AuthenticationFailed)
+    }
+}
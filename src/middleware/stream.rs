@@ -0,0 +1,265 @@
+use std::{io::{self, Read, Write}, marker::PhantomData, mem};
+
+use crate::{FromPayload, IntoPayload, Middleware};
+
+#[cfg(feature = "crossbeam")]
+use crate::{AnyBox, Stack};
+
+use crate::Error;
+
+use super::Scopes;
+
+/// A `Middleware` that decodes payloads directly from any `std::io::Read`.
+///
+/// Bytes are pulled from the reader on demand, so a message does not have to be buffered
+/// before decoding. Since nothing is buffered, there is no storage to lend out and borrowed
+/// types (`&str`, `&[u8]`, `Cow<[u8]>`) fail with `Error::Unsupported`. Owned types such as
+/// `String` or `Vec<T>` are decoded through `read_exact` / `read_owned` instead.
+///
+/// # Middleware Methods
+/// - `fn read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error>`:
+///     - Reads exactly `buf.len()` bytes from the underlying reader.
+/// - `fn read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error>`:
+///     - Reads `nbytes` from the underlying reader into a vector that grows as data arrives.
+/// - `fn read<T>(&mut self, nbytes: usize) -> Result<&'a [T], Error>`:
+///     - Always fails with `Error::Unsupported`.
+/// - `fn write<T>(&mut self, data: &[T]) -> Result<(), Error>`:
+///     - Always fails with `Error::Unsupported`.
+#[derive(Debug)]
+pub struct NextReader<'a, R> {
+    inner: R,
+    nbytes: usize,
+    scopes: Scopes,
+    #[cfg(feature = "crossbeam")]
+    stack: Stack<'a>,
+    marker: PhantomData<&'a ()>,
+}
+
+impl<'a, R: Read> NextReader<'a, R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            nbytes: 0,
+            scopes: Scopes::default(),
+            #[cfg(feature = "crossbeam")]
+            stack: Stack::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the number of bytes read from the underlying reader.
+    #[inline(always)]
+    pub fn position(&self) -> usize {
+        self.nbytes
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<'a, R: Read> Middleware<'a> for NextReader<'a, R> {
+    #[inline(always)]
+    fn into_payload<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        value.into_payload(ctx, self)
+    }
+
+    #[inline(always)]
+    fn from_payload<C, T: FromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        T::from_payload(ctx, self)
+    }
+
+    fn write<T>(&mut self, _data: &[T]) -> Result<(), Error> {
+        Err(Error::Unsupported("NextReader can't be used for encoding".to_string()))
+    }
+
+    fn read<T>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        Err(Error::Unsupported(format!("Borrowed read of {} bytes from a stream, decode into an owned type instead", nbytes)))
+    }
+
+    fn read_mut<T>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        Err(Error::Unsupported(format!("Borrowed read of {} bytes from a stream, decode into an owned type instead", nbytes)))
+    }
+
+    #[inline(always)]
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.reserve::<T>(self.nbytes, len)
+    }
+
+    #[inline(always)]
+    fn from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.scopes.enter(self.nbytes, len, None)
+    }
+
+    #[inline(always)]
+    fn from_scope_end(&mut self) -> Result<(), Error> {
+        let left = self.scopes.leave(self.nbytes);
+        let found = io::copy(&mut (&mut self.inner).take(left as u64), &mut io::sink())? as usize;
+        self.nbytes += found;
+
+        if found != left {
+            return Err(Error::InvalidLength { expected: left, found });
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.nbytes
+    }
+
+    fn read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error> {
+        debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+        self.scopes.read(self.nbytes, buf.len())?;
+
+        let slice = unsafe {
+            ::std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len())
+        };
+
+        self.inner.read_exact(slice)?;
+        self.nbytes += slice.len();
+
+        Ok(())
+    }
+
+    fn read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error> {
+        debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+        self.scopes.read(self.nbytes, nbytes)?;
+
+        // The length comes from the wire, so let the vector grow with the data
+        // instead of trusting it for the initial allocation.
+        let mut vec = Vec::new();
+        let found = (&mut self.inner).take(nbytes as u64).read_to_end(&mut vec)?;
+        self.nbytes += found;
+
+        if found != nbytes {
+            return Err(Error::InvalidLength { expected: nbytes, found });
+        }
+
+        let mut vec = mem::ManuallyDrop::new(vec);
+
+        Ok(unsafe {
+            Vec::from_raw_parts(vec.as_mut_ptr() as *mut T, vec.len(), vec.capacity())
+        })
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[inline(always)]
+    fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        Ok(self.stack.push(value))
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[inline(always)]
+    fn push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        Ok(self.stack.push_mut(value))
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[inline(always)]
+    fn push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        Ok(self.stack.push_array(values))
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[inline(always)]
+    fn push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        Ok(self.stack.push_array_mut(values))
+    }
+}
+
+/// A `Middleware` that encodes payloads directly into any `std::io::Write`.
+///
+/// Every `write` is pushed straight through to the writer with `write_all`, so there is no
+/// intermediate buffer to copy out with `serialized()`. Wrap the writer in a `BufWriter` when
+/// many small writes are a concern. Reading from a `NextWriter` fails with `Error::Unsupported`.
+#[derive(Debug)]
+pub struct NextWriter<W> {
+    inner: W,
+    nbytes: usize,
+}
+
+impl<W: Write> NextWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            nbytes: 0,
+        }
+    }
+
+    /// Returns the number of bytes written to the underlying writer.
+    #[inline(always)]
+    pub fn position(&self) -> usize {
+        self.nbytes
+    }
+
+    #[inline(always)]
+    pub fn flush(&mut self) -> Result<(), Error> {
+        Ok(self.inner.flush()?)
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<'a, W: Write> Middleware<'a> for NextWriter<W> {
+    #[inline(always)]
+    fn into_payload<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        value.into_payload(ctx, self)
+    }
+
+    #[inline(always)]
+    fn from_payload<C, T: FromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        T::from_payload(ctx, self)
+    }
+
+    fn write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        debug_assert_eq!(mem::size_of::<T>(), 1, "Size of T must be 1 byte");
+
+        let slice = unsafe {
+            ::std::slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data))
+        };
+
+        self.inner.write_all(slice)?;
+        self.nbytes += slice.len();
+
+        Ok(())
+    }
+
+    fn read<T>(&mut self, _nbytes: usize) -> Result<&'a [T], Error> {
+        Err(Error::Unsupported("NextWriter can't be used for decoding".to_string()))
+    }
+
+    fn read_mut<T>(&mut self, _nbytes: usize) -> Result<&'a mut [T], Error> {
+        Err(Error::Unsupported("NextWriter can't be used for decoding".to_string()))
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.nbytes
+    }
+}
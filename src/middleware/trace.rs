@@ -0,0 +1,285 @@
+use crate::Error;
+
+use super::MiddlewareLayer;
+
+/// Whether a traced value is being encoded or decoded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TraceDirection {
+    Encode,
+    Decode,
+}
+
+/// A value entered by `NextTrace` or a `Layered` middleware.
+///
+/// - `direction`: whether the value is being encoded or decoded.
+/// - `ty`: the `PayloadInfo::TYPE` of the value, or its Rust type name without the `info` feature.
+/// - `depth`: how many values enclose this one, `0` for the outermost value.
+/// - `offset`: the byte offset at which the value starts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TraceEvent {
+    pub direction: TraceDirection,
+    pub ty: &'static str,
+    pub depth: usize,
+    pub offset: usize,
+}
+
+/// Receives the events reported by `NextTrace` or a `TraceLayer`.
+///
+/// Every `start` is followed by exactly one `end` for the same event, after the `start`/`end`
+/// pairs of all nested values, unless encoding or decoding is aborted by `Error::NestedDepthLimit`.
+///
+/// ### Methods
+/// - `fn start(&mut self, event: &TraceEvent)`:
+///     - Called before a value is encoded or decoded.
+/// - `fn end(&mut self, event: &TraceEvent, nbytes: usize, error: Option<&Error>)`:
+///     - Called once the value is done, with the number of bytes it produced or consumed and the error it failed with, if any.
+pub trait TraceSink: Send + Sync {
+    fn start(&mut self, event: &TraceEvent);
+    fn end(&mut self, event: &TraceEvent, nbytes: usize, error: Option<&Error>);
+}
+
+impl TraceSink for () {
+    #[inline(always)]
+    fn start(&mut self, _event: &TraceEvent) {}
+
+    #[inline(always)]
+    fn end(&mut self, _event: &TraceEvent, _nbytes: usize, _error: Option<&Error>) {}
+}
+
+impl<S: TraceSink> TraceSink for &mut S {
+    #[inline(always)]
+    fn start(&mut self, event: &TraceEvent) {
+        (**self).start(event)
+    }
+
+    #[inline(always)]
+    fn end(&mut self, event: &TraceEvent, nbytes: usize, error: Option<&Error>) {
+        (**self).end(event, nbytes, error)
+    }
+}
+
+/// A `MiddlewareLayer` that reports every value to a `TraceSink`.
+#[derive(Clone, Default, Debug)]
+pub struct TraceLayer<S> {
+    sink: S,
+}
+
+impl<S: TraceSink> TraceLayer<S> {
+    pub fn new(sink: S) -> Self {
+        Self { sink }
+    }
+
+    #[inline(always)]
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    #[inline(always)]
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    #[inline(always)]
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+}
+
+impl<S: TraceSink> MiddlewareLayer for TraceLayer<S> {
+    fn enter(&mut self, event: &TraceEvent) -> Result<(), Error> {
+        self.sink.start(event);
+        Ok(())
+    }
+
+    fn leave(&mut self, event: &TraceEvent, nbytes: usize, error: Option<&Error>) {
+        self.sink.end(event, nbytes, error);
+    }
+}
+
+/// Prints the path of every value to stdout as it is entered, joined with `" -> "` while
+/// encoding and `" <- "` while decoding.
+#[derive(Clone, Default, Debug)]
+pub struct PrintSink {
+    path: Vec<&'static str>,
+}
+
+impl TraceSink for PrintSink {
+    fn start(&mut self, event: &TraceEvent) {
+        self.path.push(event.ty);
+        println!("{}", self.path.join(separator(event.direction)));
+    }
+
+    fn end(&mut self, _event: &TraceEvent, _nbytes: usize, _error: Option<&Error>) {
+        self.path.pop();
+    }
+}
+
+#[inline(always)]
+fn separator(direction: TraceDirection) -> &'static str {
+    match direction {
+        TraceDirection::Encode => " -> ",
+        TraceDirection::Decode => " <- ",
+    }
+}
+
+/// A traced value and the values nested in it, as collected by `CollectSink`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TraceNode {
+    pub direction: TraceDirection,
+    pub ty: &'static str,
+    pub offset: usize,
+    pub nbytes: usize,
+    pub error: Option<Error>,
+    pub children: Vec<TraceNode>,
+}
+
+/// Collects every traced value into an in-memory tree, one root per top-level value.
+#[derive(Clone, Default, Debug)]
+pub struct CollectSink {
+    roots: Vec<TraceNode>,
+    stack: Vec<TraceNode>,
+}
+
+impl CollectSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the completed top-level values.
+    #[inline(always)]
+    pub fn roots(&self) -> &[TraceNode] {
+        &self.roots
+    }
+
+    #[inline(always)]
+    pub fn into_roots(self) -> Vec<TraceNode> {
+        self.roots
+    }
+
+    pub fn clear(&mut self) {
+        self.roots.clear();
+        self.stack.clear();
+    }
+}
+
+impl TraceSink for CollectSink {
+    fn start(&mut self, event: &TraceEvent) {
+        self.stack.push(TraceNode {
+            direction: event.direction,
+            ty: event.ty,
+            offset: event.offset,
+            nbytes: 0,
+            error: None,
+            children: Vec::new(),
+        });
+    }
+
+    fn end(&mut self, _event: &TraceEvent, nbytes: usize, error: Option<&Error>) {
+        if let Some(mut node) = self.stack.pop() {
+            node.nbytes = nbytes;
+            node.error = error.cloned();
+
+            match self.stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => self.roots.push(node),
+            }
+        }
+    }
+}
+
+/// Reports traced values to the `log` crate under the `npsd` target.
+/// Requires the `log` feature to be enabled.
+///
+/// Values are logged at `level` when they are entered and when they are done; errors are
+/// logged at `Level::Warn`.
+#[cfg(feature = "log")]
+#[derive(Clone, Debug)]
+pub struct LogSink {
+    level: log::Level,
+    path: Vec<&'static str>,
+}
+
+#[cfg(feature = "log")]
+impl LogSink {
+    pub fn new(level: log::Level) -> Self {
+        Self {
+            level,
+            path: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl Default for LogSink {
+    fn default() -> Self {
+        Self::new(log::Level::Trace)
+    }
+}
+
+#[cfg(feature = "log")]
+impl TraceSink for LogSink {
+    fn start(&mut self, event: &TraceEvent) {
+        self.path.push(event.ty);
+        log::log!(target: "npsd", self.level, "{} at byte {}", self.path.join(separator(event.direction)), event.offset);
+    }
+
+    fn end(&mut self, event: &TraceEvent, nbytes: usize, error: Option<&Error>) {
+        let path = self.path.join(separator(event.direction));
+        self.path.pop();
+
+        match error {
+            Some(error) => log::warn!(target: "npsd", "{} failed at byte {}: {}", path, event.offset, error),
+            None => log::log!(target: "npsd", self.level, "{} done, {} bytes", path, nbytes),
+        }
+    }
+}
+
+/// Reports traced values to the `tracing` crate, with one `payload` span per nested value.
+/// Requires the `tracing` feature to be enabled.
+///
+/// Spans are created at `TRACE` level with the `ty`, `direction`, `depth` and `offset` fields,
+/// and record `nbytes` and `error` when the value is done.
+#[cfg(feature = "tracing")]
+#[derive(Clone, Default, Debug)]
+pub struct TracingSink {
+    spans: Vec<tracing::Span>,
+}
+
+#[cfg(feature = "tracing")]
+impl TracingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl TraceSink for TracingSink {
+    fn start(&mut self, event: &TraceEvent) {
+        let span = tracing::trace_span!(
+            "payload",
+            ty = event.ty,
+            direction = ?event.direction,
+            depth = event.depth,
+            offset = event.offset,
+            nbytes = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+
+        // The span stays entered across calls, so it's entered through the dispatcher
+        // instead of holding a guard that isn't `Send`.
+        span.with_subscriber(|(id, dispatch)| dispatch.enter(id));
+        self.spans.push(span);
+    }
+
+    fn end(&mut self, _event: &TraceEvent, nbytes: usize, error: Option<&Error>) {
+        if let Some(span) = self.spans.pop() {
+            span.record("nbytes", nbytes);
+
+            if let Some(error) = error {
+                span.record("error", tracing::field::display(error));
+            }
+
+            span.with_subscriber(|(id, dispatch)| dispatch.exit(id));
+        }
+    }
+}
@@ -0,0 +1,332 @@
+#[cfg(feature = "sync")]
+use crate::{FromPayload, IntoPayload, Middleware};
+
+#[cfg(feature = "async")]
+use crate::{AsyncFromPayload, AsyncIntoPayload, AsyncMiddleware};
+
+use crate::{AnyBox, BitField, Error, PayloadInfo};
+
+/// The length of the type hash written in front of every message by `NextTyped`.
+pub const TYPE_HASH_LEN: usize = 8;
+
+/// Returns the type hash at the start of a message written by `NextTyped`, without decoding it.
+pub fn type_hash(packet: &[u8]) -> Result<u64, Error> {
+    match packet.get(..TYPE_HASH_LEN) {
+        Some(hash) => Ok(u64::from_be_bytes([hash[0], hash[1], hash[2], hash[3], hash[4], hash[5], hash[6], hash[7]])),
+        None => Err(Error::InvalidLength { expected: TYPE_HASH_LEN, found: packet.len() }),
+    }
+}
+
+/// A `Middleware` and `AsyncMiddleware` wrapper that makes messages self-identifying.
+/// Requires the `info` feature to be enabled.
+///
+/// The `PayloadInfo::HASH` of the top-level value is written in front of it, as 8 big-endian
+/// bytes, and checked before anything else is decoded, so a message read as the wrong type fails
+/// with `Error::TypeMismatch` instead of being misinterpreted. Nested values are not prefixed.
+///
+/// Since `HASH` is derived from the type name, renaming a type changes its hash, while changing
+/// its fields does not.
+#[derive(Debug)]
+pub struct NextTyped<M> {
+    inner: M,
+    depth: usize,
+}
+
+impl<M> NextTyped<M> {
+    pub fn new(inner: M) -> Self {
+        Self {
+            inner,
+            depth: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &M {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut M {
+        &mut self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> M {
+        self.inner
+    }
+}
+
+#[inline(always)]
+fn check<T: PayloadInfo>(hash: [u8; TYPE_HASH_LEN]) -> Result<(), Error> {
+    let found = u64::from_be_bytes(hash);
+
+    if found != T::HASH {
+        return Err(Error::TypeMismatch { ty: T::TYPE.to_string(), expected: T::HASH, found });
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+impl<'a, M: Middleware<'a>> Middleware<'a> for NextTyped<M> {
+    const PACKS_BITS: bool = M::PACKS_BITS;
+
+    fn into_payload<C, T: IntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        if self.depth == 0 {
+            self.inner.write(&T::HASH.to_be_bytes())?;
+        }
+
+        self.depth += 1;
+        let result = value.into_payload(ctx, self);
+        self.depth -= 1;
+
+        if self.depth == 0 {
+            result?;
+            return self.inner.into_end();
+        }
+
+        result
+    }
+
+    fn from_payload<C, T: FromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        if self.depth == 0 {
+            let mut hash = [0u8; TYPE_HASH_LEN];
+            self.inner.read_exact(&mut hash)?;
+
+            check::<T>(hash)?;
+        }
+
+        self.depth += 1;
+        let result = T::from_payload(ctx, self);
+        self.depth -= 1;
+
+        if self.depth == 0 {
+            let end = self.inner.from_end();
+            return result.and_then(|value| end.map(|()| value));
+        }
+
+        result
+    }
+
+    #[inline(always)]
+    fn write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        self.inner.write(data)
+    }
+
+    #[inline(always)]
+    fn read<T>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        self.inner.read(nbytes)
+    }
+
+    #[inline(always)]
+    fn read_mut<T>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        self.inner.read_mut(nbytes)
+    }
+
+    #[inline(always)]
+    fn read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error> {
+        self.inner.read_exact(buf)
+    }
+
+    #[inline(always)]
+    fn read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error> {
+        self.inner.read_owned(nbytes)
+    }
+
+    #[inline(always)]
+    fn reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.inner.reserve::<T>(len)
+    }
+
+    #[inline(always)]
+    fn into_bits<C, T: IntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, ctx: &mut C) -> Result<(), Error> {
+        self.inner.into_bits(value, bits, ctx)
+    }
+
+    #[inline(always)]
+    fn from_bits<C, T: FromPayload<'a, C> + BitField>(&mut self, bits: u32, ctx: &mut C) -> Result<T, Error> {
+        self.inner.from_bits(bits, ctx)
+    }
+
+    #[inline(always)]
+    fn into_end(&mut self) -> Result<(), Error> {
+        self.inner.into_end()
+    }
+
+    #[inline(always)]
+    fn from_end(&mut self) -> Result<(), Error> {
+        self.inner.from_end()
+    }
+
+    #[inline(always)]
+    fn into_scope(&mut self) -> Result<(), Error> {
+        self.inner.into_scope()
+    }
+
+    #[inline(always)]
+    fn from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.inner.from_scope(len)
+    }
+
+    #[inline(always)]
+    fn from_scope_end(&mut self) -> Result<(), Error> {
+        self.inner.from_scope_end()
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.inner.position()
+    }
+
+    #[inline(always)]
+    fn push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        self.inner.push(value)
+    }
+
+    #[inline(always)]
+    fn push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        self.inner.push_mut(value)
+    }
+
+    #[inline(always)]
+    fn push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        self.inner.push_array(values)
+    }
+
+    #[inline(always)]
+    fn push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        self.inner.push_array_mut(values)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a, M: AsyncMiddleware<'a>> AsyncMiddleware<'a> for NextTyped<M> {
+    const PACKS_BITS: bool = M::PACKS_BITS;
+
+    async fn poll_into_payload<C: Send + Sync, T: AsyncIntoPayload<C>>(&mut self, value: &T, ctx: &mut C) -> Result<(), Error> {
+        if self.depth == 0 {
+            self.inner.poll_write(&T::HASH.to_be_bytes()).await?;
+        }
+
+        self.depth += 1;
+        let result = value.poll_into_payload(ctx, self).await;
+        self.depth -= 1;
+
+        if self.depth == 0 {
+            result?;
+            return self.inner.poll_into_end().await;
+        }
+
+        result
+    }
+
+    async fn poll_from_payload<C: Send + Sync, T: AsyncFromPayload<'a, C>>(&mut self, ctx: &mut C) -> Result<T, Error> {
+        if self.depth == 0 {
+            let mut hash = [0u8; TYPE_HASH_LEN];
+            self.inner.poll_read_exact(&mut hash).await?;
+
+            check::<T>(hash)?;
+        }
+
+        self.depth += 1;
+        let result = T::poll_from_payload(ctx, self).await;
+        self.depth -= 1;
+
+        if self.depth == 0 {
+            let end = self.inner.poll_from_end().await;
+            return result.and_then(|value| end.map(|()| value));
+        }
+
+        result
+    }
+
+    #[inline(always)]
+    async fn poll_write<T>(&mut self, data: &[T]) -> Result<(), Error> {
+        self.inner.poll_write(data).await
+    }
+
+    #[inline(always)]
+    async fn poll_read<T: 'a>(&mut self, nbytes: usize) -> Result<&'a [T], Error> {
+        self.inner.poll_read(nbytes).await
+    }
+
+    #[inline(always)]
+    async fn poll_read_mut<T: 'a>(&mut self, nbytes: usize) -> Result<&'a mut [T], Error> {
+        self.inner.poll_read_mut(nbytes).await
+    }
+
+    #[inline(always)]
+    async fn poll_read_exact<T: Copy + 'a>(&mut self, buf: &mut [T]) -> Result<(), Error> {
+        self.inner.poll_read_exact(buf).await
+    }
+
+    #[inline(always)]
+    async fn poll_read_owned<T: Clone + 'a>(&mut self, nbytes: usize) -> Result<Vec<T>, Error> {
+        self.inner.poll_read_owned(nbytes).await
+    }
+
+    #[inline(always)]
+    async fn poll_reserve<T>(&mut self, len: usize) -> Result<(), Error> {
+        self.inner.poll_reserve::<T>(len).await
+    }
+
+    #[inline(always)]
+    async fn poll_into_bits<C: Send + Sync, T: AsyncIntoPayload<C> + BitField>(&mut self, value: &T, bits: u32, ctx: &mut C) -> Result<(), Error> {
+        self.inner.poll_into_bits(value, bits, ctx).await
+    }
+
+    #[inline(always)]
+    async fn poll_from_bits<C: Send + Sync, T: AsyncFromPayload<'a, C> + BitField>(&mut self, bits: u32, ctx: &mut C) -> Result<T, Error> {
+        self.inner.poll_from_bits(bits, ctx).await
+    }
+
+    #[inline(always)]
+    async fn poll_into_end(&mut self) -> Result<(), Error> {
+        self.inner.poll_into_end().await
+    }
+
+    #[inline(always)]
+    async fn poll_from_end(&mut self) -> Result<(), Error> {
+        self.inner.poll_from_end().await
+    }
+
+    #[inline(always)]
+    async fn poll_into_scope(&mut self) -> Result<(), Error> {
+        self.inner.poll_into_scope().await
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope(&mut self, len: usize) -> Result<(), Error> {
+        self.inner.poll_from_scope(len).await
+    }
+
+    #[inline(always)]
+    async fn poll_from_scope_end(&mut self) -> Result<(), Error> {
+        self.inner.poll_from_scope_end().await
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.inner.position()
+    }
+
+    #[inline(always)]
+    async fn poll_push<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a T, Error> {
+        self.inner.poll_push(value).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_mut<T: AnyBox<'a>>(&mut self, value: Box<T>) -> Result<&'a mut T, Error> {
+        self.inner.poll_push_mut(value).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_array<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a [T], Error> {
+        self.inner.poll_push_array(values).await
+    }
+
+    #[inline(always)]
+    async fn poll_push_array_mut<T: AnyBox<'a>>(&mut self, values: Box<[T]>) -> Result<&'a mut [T], Error> {
+        self.inner.poll_push_array_mut(values).await
+    }
+}
@@ -0,0 +1,235 @@
+use std::marker::PhantomData;
+
+#[doc(hidden)]
+use npsd_schema::SchemaInternal as Schema;
+
+#[doc(hidden)]
+#[cfg(feature = "async")]
+use npsd_schema::AsyncSchemaInternal as AsyncSchema;
+
+#[doc(hidden)]
+use npsd_schema::InfoInternal as Info;
+
+use crate::{Error, Next, PayloadInfo, Middleware, Payload, IntoPayload, FromPayload};
+
+#[cfg(feature = "async")]
+use crate::{AsyncMiddleware, AsyncPayload, AsyncIntoPayload, AsyncFromPayload};
+
+/// A step from a value to one of its parts in the path of an `Op`.
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[derive(Schema, Info, Clone, PartialEq, Debug)]
+pub enum Segment {
+    /// A field of a struct, by its position among the fields that aren't skipped.
+    Field(usize),
+    /// An element of a sequence.
+    Index(usize),
+    /// A value of a map, by its encoded key.
+    Key(Vec<u8>),
+}
+
+/// A change to the value at `path`, values and keys are stored as their encoded payload.
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[derive(Schema, Info, Clone, PartialEq, Debug)]
+pub enum Op {
+    /// Replaces the value.
+    Set { path: Vec<Segment>, value: Vec<u8> },
+    /// Inserts `key` into a set, or `key` and `value` into a map.
+    Insert { path: Vec<Segment>, key: Vec<u8>, value: Vec<u8> },
+    /// Removes `key` from a set or a map.
+    Remove { path: Vec<Segment>, key: Vec<u8> },
+    /// Replaces `remove` elements of a sequence, starting at `at`, with the encoded `values`.
+    Splice { path: Vec<Segment>, at: usize, remove: usize, values: Vec<u8> },
+}
+
+impl Op {
+    /// Returns the path of the value the op changes.
+    pub fn path(&self) -> &[Segment] {
+        match self {
+            Op::Set { path, .. } | Op::Insert { path, .. } | Op::Remove { path, .. } | Op::Splice { path, .. } => path,
+        }
+    }
+}
+
+/// The `Diff` trait finds the changes between two values and applies them.
+///
+/// It is implemented for the primitives, `String` and `Option`, which change as a whole, for `Vec`,
+/// whose elements are diffed in place or spliced when the length changed, and for the maps and sets,
+/// which are diffed key by key. It is derived with `#[derive(Diff)]`, structs are diffed field by
+/// field and enums change as a whole.
+///
+/// ### Methods
+/// - `fn diff(&self, new: &Self, path: &mut Vec<Segment>, ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error>`:
+///     - Appends the ops turning `self` into `new` to `ops`, `path` leads from the root to `self`.
+/// - `fn apply(&mut self, path: &'a [Segment], op: &'a Op, ctx: &mut C) -> Result<(), Error>`:
+///     - Applies `op` to the part of `self` at `path`, the rest of the op's path below `self`.
+pub trait Diff<'a, C>: Sized {
+    fn diff(&self, new: &Self, path: &mut Vec<Segment>, ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error>;
+    fn apply(&mut self, path: &'a [Segment], op: &'a Op, ctx: &mut C) -> Result<(), Error>;
+}
+
+/// The changes turning one `T` into another, as a list of `Op`s.
+///
+/// A patch is made with `Patch::diff`, sent like any other payload, and applied to the old value
+/// with `Patch::apply`. Applying it to a value it wasn't made from fails with
+/// `Error::InvalidPatch` when a path, key or range doesn't exist.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Patch<T> {
+    ops: Vec<Op>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Patch<T> {
+    /// Returns a patch applying `ops` in order.
+    pub fn from_ops(ops: Vec<Op>) -> Self {
+        Self {
+            ops,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the changes between `old` and `new`.
+    pub fn diff<'a, C>(old: &T, new: &T, ctx: &mut C) -> Result<Self, Error>
+        where T: Diff<'a, C>
+    {
+        let mut ops = Vec::new();
+        old.diff(new, &mut Vec::new(), &mut ops, ctx)?;
+
+        Ok(Self::from_ops(ops))
+    }
+
+    /// Applies the changes to `value`, the old value of `Patch::diff`.
+    pub fn apply<'a, C>(&'a self, value: &mut T, ctx: &mut C) -> Result<(), Error>
+        where T: Diff<'a, C>
+    {
+        for op in &self.ops {
+            value.apply(op.path(), op, ctx)?;
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl<T> PayloadInfo for Patch<T> {
+    const TYPE: &'static str = "Patch";
+}
+
+impl<C, T> IntoPayload<C> for Patch<T> {
+    fn into_payload<'m, M: Middleware<'m>>(&self, ctx: &mut C, next: &mut M) -> Result<(), Error> {
+        next.into_payload(&self.ops, ctx)
+    }
+}
+
+impl<'a, C, T> FromPayload<'a, C> for Patch<T> {
+    fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
+        Ok(Self::from_ops(next.from_payload(ctx)?))
+    }
+}
+
+impl<'a, C, T> Payload<'a, C> for Patch<T> {}
+
+#[cfg(feature = "async")]
+impl<C: Send + Sync, T> AsyncIntoPayload<C> for Patch<T> {
+    async fn poll_into_payload<'m, M: AsyncMiddleware<'m>>(&self, ctx: &mut C, next: &mut M) -> Result<(), Error> {
+        next.poll_into_payload(&self.ops, ctx).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a, C: Send + Sync, T> AsyncFromPayload<'a, C> for Patch<T> {
+    async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
+        Ok(Self::from_ops(next.poll_from_payload(ctx).await?))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a, C: Send + Sync, T: 'a> AsyncPayload<'a, C> for Patch<T> {}
+
+/// Returns the payload of `value`, as stored in an `Op`.
+pub fn encode<C, T: IntoPayload<C>>(value: &T, ctx: &mut C) -> Result<Vec<u8>, Error> {
+    let mut next = Next::default();
+    next.into_payload(value, ctx)?;
+
+    Ok(next.serialized())
+}
+
+/// Returns the value of a payload stored in an `Op`.
+pub fn decode<'a, C, T: FromPayload<'a, C>>(bytes: &'a [u8], ctx: &mut C) -> Result<T, Error> {
+    Next::from(bytes).from_payload(ctx)
+}
+
+/// Diffs values that change as a whole, with an `Op::Set` of `new` when they aren't equal.
+pub fn diff_value<C, T: IntoPayload<C> + PartialEq>(old: &T, new: &T, path: &[Segment], ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error> {
+    if old != new {
+        ops.push(Op::Set { path: path.to_vec(), value: encode(new, ctx)? });
+    }
+
+    Ok(())
+}
+
+/// Applies an `Op::Set` to a value that changes as a whole.
+pub fn apply_value<'a, C, T: FromPayload<'a, C>>(value: &mut T, path: &'a [Segment], op: &'a Op, ctx: &mut C) -> Result<(), Error> {
+    match (path, op) {
+        ([], Op::Set { value: bytes, .. }) => {
+            *value = decode(bytes, ctx)?;
+            Ok(())
+        },
+        _ => Err(invalid(path, op)),
+    }
+}
+
+/// The error of an op that doesn't fit the value at `path`.
+pub fn invalid(path: &[Segment], op: &Op) -> Error {
+    let kind = match op {
+        Op::Set { .. } => "set",
+        Op::Insert { .. } => "insert",
+        Op::Remove { .. } => "remove",
+        Op::Splice { .. } => "splice",
+    };
+
+    match path {
+        [] => Error::InvalidPatch(format!("Can't {} the value at `{:?}`", kind, op.path())),
+        _ => Error::InvalidPatch(format!("No value at `{:?}` of `{:?}`", path, op.path())),
+    }
+}
+
+macro_rules! diff_value_impl {
+    ($($type:ty),*) => {
+        $(
+            impl<'a, C> Diff<'a, C> for $type {
+                #[inline]
+                fn diff(&self, new: &Self, path: &mut Vec<Segment>, ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error> {
+                    diff_value(self, new, path, ops, ctx)
+                }
+
+                #[inline]
+                fn apply(&mut self, path: &'a [Segment], op: &'a Op, ctx: &mut C) -> Result<(), Error> {
+                    apply_value(self, path, op, ctx)
+                }
+            }
+        )*
+    };
+}
+
+diff_value_impl!(bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, String);
+
+impl<'a, C, T: IntoPayload<C> + FromPayload<'a, C> + PartialEq> Diff<'a, C> for Option<T> {
+    #[inline]
+    fn diff(&self, new: &Self, path: &mut Vec<Segment>, ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error> {
+        diff_value(self, new, path, ops, ctx)
+    }
+
+    #[inline]
+    fn apply(&mut self, path: &'a [Segment], op: &'a Op, ctx: &mut C) -> Result<(), Error> {
+        apply_value(self, path, op, ctx)
+    }
+}
@@ -3,17 +3,17 @@ use super::{Error, FromPayload, IntoPayload, Middleware, Payload};
 impl<'a, C, T: IntoPayload<C>> IntoPayload<C> for Option<T> {
     fn into_payload<'b, M: Middleware<'b>>(&self, ctx: &mut C, next: &mut M) -> Result<(), Error> {
         if let Some(data) = self {
-            next.into_payload(&1u8, ctx)?;
+            next.into_bits(&1u8, 1, ctx)?;
             next.into_payload(data, ctx)
         } else {
-            next.into_payload(&0u8, ctx)
+            next.into_bits(&0u8, 1, ctx)
         }
     }
 }
 
 impl<'a, C, T: FromPayload<'a, C>> FromPayload<'a, C> for Option<T> {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-        let byte: u8 = next.from_payload(ctx)?;
+        let byte: u8 = next.from_bits(1, ctx)?;
 
         if byte != 0 {
             let res: T = next.from_payload(ctx)?;
@@ -31,11 +31,11 @@ impl<'a, C, T: IntoPayload<C>, E: IntoPayload<C>> IntoPayload<C> for Result<T, E
     fn into_payload<'b, M: Middleware<'b>>(&self, ctx: &mut C, next: &mut M) -> Result<(), Error> {
         match self {
             Ok(res) => {
-                next.into_payload(&1u8, ctx)?;
+                next.into_bits(&1u8, 1, ctx)?;
                 next.into_payload(res, ctx)
             },
             Err(error) => {
-                next.into_payload(&0u8, ctx)?;
+                next.into_bits(&0u8, 1, ctx)?;
                 next.into_payload(error, ctx)
             }
         }
@@ -44,7 +44,7 @@ impl<'a, C, T: IntoPayload<C>, E: IntoPayload<C>> IntoPayload<C> for Result<T, E
 
 impl<'a, C, T: FromPayload<'a, C>, E: FromPayload<'a, C>> FromPayload<'a, C> for Result<T, E> {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-        let byte: u8 = next.from_payload(ctx)?;
+        let byte: u8 = next.from_bits(1, ctx)?;
 
         if byte != 0 {
             let res: T = next.from_payload(ctx)?;
@@ -15,11 +15,10 @@ macro_rules! payload_be_bytes {
         impl<'a, C> FromPayload<'a, C> for $type {
             #[inline]
             fn from_payload<M: Middleware<'a>>(_ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-                let slice: &[u8] = next.read(mem::size_of::<Self>())?;
+                let mut bytes = [0u8; mem::size_of::<Self>()];
+                next.read_exact(&mut bytes)?;
 
-                Ok(<Self>::from_be_bytes(unsafe {
-                    *(slice.as_ptr() as *const [u8; mem::size_of::<Self>()])
-                }))
+                Ok(<Self>::from_be_bytes(bytes))
             }
         }
 
@@ -125,16 +124,16 @@ impl<'a, C> Payload<'a, C> for () {}
 impl<C> IntoPayload<C>  for bool {
     fn into_payload<'m, M: Middleware<'m>>(&self, ctx: &mut C, next: &mut M) -> Result<(), Error> {
         if *self {
-            next.into_payload(&1u8, ctx)
+            next.into_bits(&1u8, 1, ctx)
         } else {
-            next.into_payload(&0u8, ctx)
+            next.into_bits(&0u8, 1, ctx)
         }
     }
 }
 
 impl<'a, C> FromPayload<'a, C> for bool {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-        let byte: u8 = next.from_payload(ctx)?;
+        let byte: u8 = next.from_bits(1, ctx)?;
 
         if byte != 0 {
             Ok(true)
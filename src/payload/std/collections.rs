@@ -2,6 +2,7 @@ use std::{borrow::Cow, collections::{BTreeMap, BTreeSet, BinaryHeap, LinkedList,
 use std::{collections::{HashMap, HashSet}, hash::Hash};
 
 use crate::AnyBox;
+use crate::patch::{self, Diff, Op, Segment};
 
 use super::{Error, Middleware, Payload, IntoPayload, FromPayload};
 
@@ -23,6 +24,7 @@ impl<'a, C, T: FromPayload<'a, C>> FromPayload<'a, C> for VecDeque<T> {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut deque = VecDeque::new();
         let count: usize = next.from_payload(ctx)?;
+        next.reserve::<T>(count)?;
 
         for _ in 0..count {
             deque.push_back(next.from_payload::<C, T>(ctx)?);
@@ -50,6 +52,7 @@ impl<'a, C, T: FromPayload<'a, C>> FromPayload<'a, C> for LinkedList<T> {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut list = LinkedList::new();
         let count: usize = next.from_payload(ctx)?;
+        next.reserve::<T>(count)?;
 
         for _ in 0..count {
             list.push_back(next.from_payload::<C, T>(ctx)?);
@@ -80,6 +83,7 @@ impl<'a, C, K: FromPayload<'a, C>, V: FromPayload<'a, C>> FromPayload<'a, C> for
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut map = HashMap::new();
         let count: usize = next.from_payload(ctx)?;
+        next.reserve::<(K, V)>(count)?;
 
         for _ in 0..count {
             let key: K = next.from_payload(ctx)?;
@@ -114,6 +118,7 @@ impl<'a, C, K: FromPayload<'a, C> + Ord, V: FromPayload<'a, C>> FromPayload<'a,
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut map = BTreeMap::new();
         let count: usize = next.from_payload(ctx)?;
+        next.reserve::<(K, V)>(count)?;
 
         for _ in 0..count {
             let key: K = next.from_payload(ctx)?;
@@ -147,6 +152,7 @@ impl<'a, C, K: FromPayload<'a, C>> FromPayload<'a, C> for HashSet<K>
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut set = HashSet::new();
         let count: usize = next.from_payload(ctx)?;
+        next.reserve::<K>(count)?;
 
         for _ in 0..count {
             let key: K = next.from_payload(ctx)?;
@@ -179,6 +185,7 @@ impl<'a, C, K: FromPayload<'a, C>> FromPayload<'a, C> for BTreeSet<K>
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut set = BTreeSet::new();
         let count: usize = next.from_payload(ctx)?;
+        next.reserve::<K>(count)?;
 
         for _ in 0..count {
             let key: K = next.from_payload(ctx)?;
@@ -209,6 +216,7 @@ impl<'a, C, T: FromPayload<'a, C> + Ord> FromPayload<'a, C> for BinaryHeap<T> {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut heap = BinaryHeap::new();
         let count: usize = next.from_payload(ctx)?;
+        next.reserve::<T>(count)?;
 
         for _ in 0..count {
             heap.push(next.from_payload::<C, T>(ctx)?);
@@ -230,9 +238,15 @@ impl<'a, C, T: IntoPayload<C>> IntoPayload<C> for Vec<T> {
 impl<'a, C, T: FromPayload<'a, C>> FromPayload<'a, C> for Vec<T> 
     where T: Clone + 'a 
 {
-    #[inline]
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-        Ok(Vec::from(next.from_payload::<C, Cow<'a, [T]>>(ctx)?.into_owned()))
+        if mem::size_of::<T>() == 1 {
+            let len: usize = next.from_payload(ctx)?;
+            next.reserve::<T>(len)?;
+
+            next.read_owned(len)
+        } else {
+            Ok(next.from_payload::<C, Cow<'a, [T]>>(ctx)?.into_owned())
+        }
     }
 }
 
@@ -282,6 +296,7 @@ impl<'a, C, T: FromPayload<'a, C>> FromPayload<'a, C> for Cow<'a, [T]>
 {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let len: usize = next.from_payload(ctx)?;
+        next.reserve::<T>(len)?;
 
         if mem::size_of::<T>() == 1 {
             Ok(Cow::Borrowed(next.read(len)?))
@@ -298,4 +313,194 @@ impl<'a, C, T: FromPayload<'a, C>> FromPayload<'a, C> for Cow<'a, [T]>
 }
 
 impl<'a, C, T: Payload<'a, C>> Payload<'a, C> for Cow<'a, [T]> 
-    where T: Clone {}
\ No newline at end of file
+    where T: Clone {}
+
+/// Elements of equal length vectors are diffed in place, otherwise the elements between the common
+/// prefix and suffix are replaced with one `Op::Splice`.
+impl<'a, C, T: Diff<'a, C> + IntoPayload<C> + FromPayload<'a, C> + PartialEq + Clone + 'a> Diff<'a, C> for Vec<T> {
+    fn diff(&self, new: &Self, path: &mut Vec<Segment>, ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error> {
+        if self.len() == new.len() {
+            for (index, (old, new)) in self.iter().zip(new).enumerate() {
+                if old != new {
+                    path.push(Segment::Index(index));
+                    old.diff(new, path, ops, ctx)?;
+                    path.pop();
+                }
+            }
+
+            return Ok(());
+        }
+
+        let prefix = self.iter().zip(new).take_while(|(old, new)| old == new).count();
+        let suffix = self[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(old, new)| old == new).count();
+
+        ops.push(Op::Splice {
+            path: path.clone(),
+            at: prefix,
+            remove: self.len() - prefix - suffix,
+            values: patch::encode(&&new[prefix..new.len() - suffix], ctx)?,
+        });
+
+        Ok(())
+    }
+
+    fn apply(&mut self, path: &'a [Segment], op: &'a Op, ctx: &mut C) -> Result<(), Error> {
+        match (path, op) {
+            ([], Op::Splice { at, remove, values, .. }) => {
+                if *remove > self.len() || *at > self.len() - *remove {
+                    return Err(Error::InvalidPatch(format!("Splice of {} at {} is out of `{:?}` of length {}", remove, at, op.path(), self.len())));
+                }
+
+                let values: Vec<T> = patch::decode(values, ctx)?;
+                self.splice(*at..*at + *remove, values);
+
+                Ok(())
+            },
+            ([Segment::Index(index), rest @ ..], _) => match self.get_mut(*index) {
+                Some(value) => value.apply(rest, op, ctx),
+                None => Err(patch::invalid(path, op)),
+            },
+            _ => Err(patch::invalid(path, op)),
+        }
+    }
+}
+
+impl<'a, C, K, V> Diff<'a, C> for HashMap<K, V>
+    where K: IntoPayload<C> + FromPayload<'a, C> + Hash + Eq, V: Diff<'a, C> + IntoPayload<C> + FromPayload<'a, C> + PartialEq
+{
+    fn diff(&self, new: &Self, path: &mut Vec<Segment>, ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error> {
+        diff_map(self.iter(), |key| new.get(key), path, ops, ctx)?;
+
+        for (key, value) in new {
+            if !self.contains_key(key) {
+                ops.push(Op::Insert { path: path.clone(), key: patch::encode(key, ctx)?, value: patch::encode(value, ctx)? });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply(&mut self, path: &'a [Segment], op: &'a Op, ctx: &mut C) -> Result<(), Error> {
+        match (path, op) {
+            ([], Op::Insert { key, value, .. }) => {
+                self.insert(patch::decode(key, ctx)?, patch::decode(value, ctx)?);
+                Ok(())
+            },
+            ([], Op::Remove { key, .. }) => match self.remove(&patch::decode::<C, K>(key, ctx)?) {
+                Some(_) => Ok(()),
+                None => Err(Error::InvalidPatch(format!("No key to remove from `{:?}`", op.path()))),
+            },
+            ([Segment::Key(key), rest @ ..], _) => match self.get_mut(&patch::decode::<C, K>(key, ctx)?) {
+                Some(value) => value.apply(rest, op, ctx),
+                None => Err(patch::invalid(path, op)),
+            },
+            _ => Err(patch::invalid(path, op)),
+        }
+    }
+}
+
+impl<'a, C, K, V> Diff<'a, C> for BTreeMap<K, V>
+    where K: IntoPayload<C> + FromPayload<'a, C> + Ord, V: Diff<'a, C> + IntoPayload<C> + FromPayload<'a, C> + PartialEq
+{
+    fn diff(&self, new: &Self, path: &mut Vec<Segment>, ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error> {
+        diff_map(self.iter(), |key| new.get(key), path, ops, ctx)?;
+
+        for (key, value) in new {
+            if !self.contains_key(key) {
+                ops.push(Op::Insert { path: path.clone(), key: patch::encode(key, ctx)?, value: patch::encode(value, ctx)? });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply(&mut self, path: &'a [Segment], op: &'a Op, ctx: &mut C) -> Result<(), Error> {
+        match (path, op) {
+            ([], Op::Insert { key, value, .. }) => {
+                self.insert(patch::decode(key, ctx)?, patch::decode(value, ctx)?);
+                Ok(())
+            },
+            ([], Op::Remove { key, .. }) => match self.remove(&patch::decode::<C, K>(key, ctx)?) {
+                Some(_) => Ok(()),
+                None => Err(Error::InvalidPatch(format!("No key to remove from `{:?}`", op.path()))),
+            },
+            ([Segment::Key(key), rest @ ..], _) => match self.get_mut(&patch::decode::<C, K>(key, ctx)?) {
+                Some(value) => value.apply(rest, op, ctx),
+                None => Err(patch::invalid(path, op)),
+            },
+            _ => Err(patch::invalid(path, op)),
+        }
+    }
+}
+
+impl<'a, C, K: IntoPayload<C> + FromPayload<'a, C> + Hash + Eq> Diff<'a, C> for HashSet<K> {
+    fn diff(&self, new: &Self, path: &mut Vec<Segment>, ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error> {
+        diff_set(self.difference(new), new.difference(self), path, ops, ctx)
+    }
+
+    fn apply(&mut self, path: &'a [Segment], op: &'a Op, ctx: &mut C) -> Result<(), Error> {
+        match (path, op) {
+            ([], Op::Insert { key, .. }) => {
+                self.insert(patch::decode(key, ctx)?);
+                Ok(())
+            },
+            ([], Op::Remove { key, .. }) => match self.remove(&patch::decode::<C, K>(key, ctx)?) {
+                true => Ok(()),
+                false => Err(Error::InvalidPatch(format!("No key to remove from `{:?}`", op.path()))),
+            },
+            _ => Err(patch::invalid(path, op)),
+        }
+    }
+}
+
+impl<'a, C, K: IntoPayload<C> + FromPayload<'a, C> + Ord> Diff<'a, C> for BTreeSet<K> {
+    fn diff(&self, new: &Self, path: &mut Vec<Segment>, ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error> {
+        diff_set(self.difference(new), new.difference(self), path, ops, ctx)
+    }
+
+    fn apply(&mut self, path: &'a [Segment], op: &'a Op, ctx: &mut C) -> Result<(), Error> {
+        match (path, op) {
+            ([], Op::Insert { key, .. }) => {
+                self.insert(patch::decode(key, ctx)?);
+                Ok(())
+            },
+            ([], Op::Remove { key, .. }) => match self.remove(&patch::decode::<C, K>(key, ctx)?) {
+                true => Ok(()),
+                false => Err(Error::InvalidPatch(format!("No key to remove from `{:?}`", op.path()))),
+            },
+            _ => Err(patch::invalid(path, op)),
+        }
+    }
+}
+
+/// Removes the keys of `old` that `new` doesn't have, and diffs the values of the others.
+fn diff_map<'a, 'm, C, K, V>(old: impl Iterator<Item = (&'m K, &'m V)>, new: impl Fn(&K) -> Option<&'m V>, path: &mut Vec<Segment>, ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error>
+    where K: IntoPayload<C> + 'm, V: Diff<'a, C> + PartialEq + 'm
+{
+    for (key, old) in old {
+        match new(key) {
+            None => ops.push(Op::Remove { path: path.clone(), key: patch::encode(key, ctx)? }),
+            Some(new) if new != old => {
+                path.push(Segment::Key(patch::encode(key, ctx)?));
+                old.diff(new, path, ops, ctx)?;
+                path.pop();
+            },
+            Some(_) => {},
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes an `Op::Remove` per key of `removed` and an `Op::Insert` per key of `inserted`.
+fn diff_set<'k, C, K: IntoPayload<C> + 'k>(removed: impl Iterator<Item = &'k K>, inserted: impl Iterator<Item = &'k K>, path: &[Segment], ops: &mut Vec<Op>, ctx: &mut C) -> Result<(), Error> {
+    for key in removed {
+        ops.push(Op::Remove { path: path.to_vec(), key: patch::encode(key, ctx)? });
+    }
+
+    for key in inserted {
+        ops.push(Op::Insert { path: path.to_vec(), key: patch::encode(key, ctx)?, value: Vec::new() });
+    }
+
+    Ok(())
+}
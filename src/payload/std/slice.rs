@@ -25,6 +25,7 @@ impl<'a, C, T: IntoPayload<C>> IntoPayload<C> for &'a [T] {
 impl<'a, C, T: FromPayload<'a, C> + AnyBox<'a>> FromPayload<'a, C> for &'a [T] {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let len: usize = next.from_payload(ctx)?;
+        next.reserve::<T>(len)?;
 
         if mem::size_of::<T>() == 1 {
             next.read(len)
@@ -53,6 +54,7 @@ impl<'a, C, T: FromPayload<'a, C> + AnyBox<'a>> FromPayload<'a, C> for &'a mut [
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         if mem::size_of::<T>() == 1 {
             let nbytes: usize = next.from_payload(ctx)?;
+            next.reserve::<T>(nbytes)?;
 
             next.read_mut(nbytes)
         } else {
@@ -87,8 +89,7 @@ impl<'a, C, T: FromPayload<'a, C> + Default + 'a, const N: usize> FromPayload<'a
         let mut result = [T::default(); N];
 
         if mem::size_of::<T>() == 1 {
-            let bytes: &[T] = next.read(N)?;
-            result.copy_from_slice(bytes);
+            next.read_exact(&mut result)?;
         } else {
             for i in 0..N {
                 result[i] = next.from_payload(ctx)?;
@@ -1,4 +1,4 @@
-use std::{borrow::Cow, cell::{Cell, Ref, RefCell, UnsafeCell}, pin::Pin, ptr, rc::Rc, sync::{Arc, Weak}};
+use std::{cell::{Cell, Ref, RefCell, UnsafeCell}, pin::Pin, ptr, rc::Rc, sync::{Arc, Weak}};
 
 use super::{Error, Middleware, Payload, IntoPayload, FromPayload};
 
@@ -35,7 +35,7 @@ macro_rules! impl_payload_smart_slice_traits {
         {
             #[inline]
             fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-                Ok($container::from(next.from_payload::<C, Cow<'a, [T]>>(ctx)?.into_owned()))
+                Ok($container::from(next.from_payload::<C, Vec<T>>(ctx)?))
             }
         }
 
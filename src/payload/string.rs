@@ -1,5 +1,4 @@
 use core::str;
-use std::str::FromStr;
 
 use super::{Error, Middleware, Payload, IntoPayload, FromPayload};
 
@@ -35,6 +34,7 @@ impl<'a, C> IntoPayload<C>  for &'a str {
 impl<'a, C> FromPayload<'a, C> for &'a str {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let nbytes: usize = next.from_payload(ctx)?;
+        next.reserve::<u8>(nbytes)?;
 
         str::from_utf8(next.read(nbytes)?).map_err(|e| {
             Error::InvalidUtf8(e.to_string())
@@ -54,6 +54,7 @@ impl<C> IntoPayload<C>  for &mut str {
 impl<'a, C> FromPayload<'a, C> for &'a mut str {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let nbytes: usize = next.from_payload(ctx)?;
+        next.reserve::<u8>(nbytes)?;
         
         str::from_utf8_mut(next.read_mut(nbytes)?).map_err(|e| {
             Error::InvalidUtf8(e.to_string())
@@ -71,7 +72,10 @@ impl<C> IntoPayload<C>  for String {
 
 impl<'a, C> FromPayload<'a, C> for String {
     fn from_payload<M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-        String::from_str(next.from_payload(ctx)?).map_err(|e| {
+        let nbytes: usize = next.from_payload(ctx)?;
+        next.reserve::<u8>(nbytes)?;
+
+        String::from_utf8(next.read_owned(nbytes)?).map_err(|e| {
             Error::InvalidUtf8(e.to_string())
         })
     }
@@ -3,17 +3,17 @@ use super::{Error, AsyncFromPayload, AsyncIntoPayload, AsyncMiddleware, AsyncPay
 impl<C: Send + Sync, T: AsyncIntoPayload<C>> AsyncIntoPayload<C> for Option<T> {
     async fn poll_into_payload<'m, M: AsyncMiddleware<'m>>(&self, ctx: &mut C, next: &mut M) -> Result<(), Error> {
         if let Some(data) = self {
-            next.poll_into_payload(&1u8, ctx).await?;
+            next.poll_into_bits(&1u8, 1, ctx).await?;
             next.poll_into_payload(data, ctx).await
         } else {
-            next.poll_into_payload(&0u8, ctx).await
+            next.poll_into_bits(&0u8, 1, ctx).await
         }
     }
 }
 
 impl<'a, C: Send + Sync, T: AsyncFromPayload<'a, C>> AsyncFromPayload<'a, C> for Option<T> {
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-        let byte: u8 = next.poll_from_payload(ctx).await?;
+        let byte: u8 = next.poll_from_bits(1, ctx).await?;
 
         if byte != 0 {
             let res: T = next.poll_from_payload(ctx).await?;
@@ -31,11 +31,11 @@ impl<C: Send + Sync, T: AsyncIntoPayload<C>, E: AsyncIntoPayload<C>> AsyncIntoPa
     async fn poll_into_payload<'m, M: AsyncMiddleware<'m>>(&self, ctx: &mut C, next: &mut M) -> Result<(), Error> {
         match self {
             Ok(res) => {
-                next.poll_into_payload(&1u8, ctx).await?;
+                next.poll_into_bits(&1u8, 1, ctx).await?;
                 next.poll_into_payload(res, ctx).await
             },
             Err(error) => {
-                next.poll_into_payload(&0u8, ctx).await?;
+                next.poll_into_bits(&0u8, 1, ctx).await?;
                 next.poll_into_payload(error, ctx).await
             }
         }
@@ -44,7 +44,7 @@ impl<C: Send + Sync, T: AsyncIntoPayload<C>, E: AsyncIntoPayload<C>> AsyncIntoPa
 
 impl<'a, C: Send + Sync, T: AsyncFromPayload<'a, C>, E: AsyncFromPayload<'a, C>> AsyncFromPayload<'a, C> for Result<T, E> {
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-        let byte: u8 = next.poll_from_payload(ctx).await?;
+        let byte: u8 = next.poll_from_bits(1, ctx).await?;
 
         if byte != 0 {
             let res: T = next.poll_from_payload(ctx).await?;
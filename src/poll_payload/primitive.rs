@@ -15,11 +15,10 @@ macro_rules! async_payload_be_bytes {
         impl<'a, C: Send + Sync> AsyncFromPayload<'a, C> for $type {
             #[inline]
             async fn poll_from_payload<M: AsyncMiddleware<'a>>(_ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-                let slice: &[u8] = next.poll_read(mem::size_of::<Self>()).await?;
+                let mut bytes = [0u8; mem::size_of::<Self>()];
+                next.poll_read_exact(&mut bytes).await?;
 
-                Ok(<Self>::from_be_bytes(unsafe {
-                    *(slice.as_ptr() as *const [u8; mem::size_of::<Self>()])
-                }))
+                Ok(<Self>::from_be_bytes(bytes))
             }
         }
 
@@ -125,16 +124,16 @@ impl<'a, C: Send + Sync> AsyncPayload<'a, C> for () {}
 impl<C: Send + Sync> AsyncIntoPayload<C> for bool {
     async fn poll_into_payload<'m, M: AsyncMiddleware<'m>>(&self, ctx: &mut C, next: &mut M) -> Result<(), Error> {
         if *self {
-            next.poll_into_payload(&1u8, ctx).await
+            next.poll_into_bits(&1u8, 1, ctx).await
         } else {
-            next.poll_into_payload(&0u8, ctx).await
+            next.poll_into_bits(&0u8, 1, ctx).await
         }
     }
 }
 
 impl<'a, C: Send + Sync> AsyncFromPayload<'a, C> for bool {
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-        let byte: u8 = next.poll_from_payload(ctx).await?;
+        let byte: u8 = next.poll_from_bits(1, ctx).await?;
 
         if byte != 0 {
             Ok(true)
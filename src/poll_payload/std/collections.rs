@@ -21,6 +21,7 @@ impl<'a, C: Send + Sync, T: AsyncFromPayload<'a, C>> AsyncFromPayload<'a, C> for
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut deque = VecDeque::new();
         let count: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<T>(count).await?;
 
         for _ in 0..count {
             deque.push_back(next.poll_from_payload::<C, T>(ctx).await?);
@@ -48,6 +49,7 @@ impl<'a, C: Send + Sync, T: AsyncFromPayload<'a, C>> AsyncFromPayload<'a, C> for
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut list = LinkedList::new();
         let count: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<T>(count).await?;
 
         for _ in 0..count {
             list.push_back(next.poll_from_payload::<C, T>(ctx).await?);
@@ -78,6 +80,7 @@ impl<'a, C: Send + Sync, K: AsyncFromPayload<'a, C>, V: AsyncFromPayload<'a, C>>
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut map = HashMap::new();
         let count: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<(K, V)>(count).await?;
 
         for _ in 0..count {
             let key: K = next.poll_from_payload(ctx).await?;
@@ -112,6 +115,7 @@ impl<'a, C: Send + Sync, K: AsyncFromPayload<'a, C> + Ord, V: AsyncFromPayload<'
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut map = BTreeMap::new();
         let count: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<(K, V)>(count).await?;
 
         for _ in 0..count {
             let key: K = next.poll_from_payload(ctx).await?;
@@ -145,6 +149,7 @@ impl<'a, C: Send + Sync, K: AsyncFromPayload<'a, C>> AsyncFromPayload<'a, C> for
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut set = HashSet::new();
         let count: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<K>(count).await?;
 
         for _ in 0..count {
             let key: K = next.poll_from_payload(ctx).await?;
@@ -177,6 +182,7 @@ impl<'a, C: Send + Sync, K: AsyncFromPayload<'a, C>> AsyncFromPayload<'a, C> for
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut set = BTreeSet::new();
         let count: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<K>(count).await?;
 
         for _ in 0..count {
             let key: K = next.poll_from_payload(ctx).await?;
@@ -207,6 +213,7 @@ impl<'a, C: Send + Sync, T: AsyncFromPayload<'a, C> + Ord> AsyncFromPayload<'a,
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let mut heap = BinaryHeap::new();
         let count: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<T>(count).await?;
 
         for _ in 0..count {
             heap.push(next.poll_from_payload::<C, T>(ctx).await?);
@@ -228,9 +235,15 @@ impl<C: Send + Sync, T: AsyncIntoPayload<C>> AsyncIntoPayload<C> for Vec<T> {
 impl<'a, C: Send + Sync, T: AsyncFromPayload<'a, C>> AsyncFromPayload<'a, C> for Vec<T> 
     where T: Clone + 'a 
 {
-    #[inline]
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-        Ok(Vec::from(next.poll_from_payload::<C, Cow<'a, [T]>>(ctx).await?.into_owned()))
+        if mem::size_of::<T>() == 1 {
+            let len: usize = next.poll_from_payload(ctx).await?;
+            next.poll_reserve::<T>(len).await?;
+
+            next.poll_read_owned(len).await
+        } else {
+            Ok(next.poll_from_payload::<C, Cow<'a, [T]>>(ctx).await?.into_owned())
+        }
     }
 }
 
@@ -280,6 +293,7 @@ impl<'a, C: Send + Sync, T: AsyncFromPayload<'a, C>> AsyncFromPayload<'a, C> for
 {
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let len: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<T>(len).await?;
 
         if mem::size_of::<T>() == 1 {
             Ok(Cow::Borrowed(next.poll_read(len).await?))
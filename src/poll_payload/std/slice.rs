@@ -23,6 +23,7 @@ impl<'a, C: Send + Sync, T: AsyncIntoPayload<C>> AsyncIntoPayload<C> for &'a [T]
 impl<'a, C: Send + Sync, T: AsyncFromPayload<'a, C> + 'a> AsyncFromPayload<'a, C> for &'a [T] {
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let len: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<T>(len).await?;
 
         if mem::size_of::<T>() == 1 {
             next.poll_read(len).await
@@ -51,10 +52,13 @@ impl<'a, C: Send + Sync, T: AsyncFromPayload<'a, C>> AsyncFromPayload<'a, C> for
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         if mem::size_of::<T>() == 1 {
             let nbytes: usize = next.poll_from_payload(ctx).await?;
+            next.poll_reserve::<T>(nbytes).await?;
 
             next.poll_read_mut(nbytes).await
         } else {
             let len: usize = next.poll_from_payload(ctx).await?;
+            next.poll_reserve::<T>(len).await?;
+
             let mut vec = Vec::with_capacity(len);
 
             for _ in 0..len {
@@ -90,8 +94,7 @@ impl<'a, C: Send + Sync, T: AsyncFromPayload<'a, C> + Default + 'a, const N: usi
         let mut result = [T::default(); N];
 
         if mem::size_of::<T>() == 1 {
-            let bytes: &[T] = next.poll_read(N).await?;
-            result.copy_from_slice(bytes);
+            next.poll_read_exact(&mut result).await?;
         } else {
             for i in 0..N {
                 result[i] = next.poll_from_payload(ctx).await?;
@@ -1,4 +1,4 @@
-use std::{borrow::Cow, 
+use std::{
     // cell::{Cell, Ref, RefCell, UnsafeCell}, 
     pin::Pin, 
     // ptr, rc::Rc, 
@@ -9,10 +9,12 @@ use super::{Error, AsyncMiddleware, AsyncPayload, AsyncIntoPayload, AsyncFromPay
 
 macro_rules! impl_payload_smart_slice_traits {
     ($container:ident) => {
+        // The inner future is boxed so that recursive types, such as an enum holding
+        // `Box<Self>`, don't end up with an infinitely sized future.
         impl<C: Send + Sync, T: AsyncIntoPayload<C>> AsyncIntoPayload<C> for $container<T> {
             #[inline]
             async fn poll_into_payload<'m, M: AsyncMiddleware<'m>>(&self, ctx: &mut C, next: &mut M) -> Result<(), Error> {
-                next.poll_into_payload(self.as_ref(), ctx).await
+                Box::pin(next.poll_into_payload(self.as_ref(), ctx)).await
             }
         }
 
@@ -21,7 +23,7 @@ macro_rules! impl_payload_smart_slice_traits {
         {
             #[inline]
             async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-                Ok($container::new(next.poll_from_payload::<C, T>(ctx).await?))
+                Ok($container::new(Box::pin(next.poll_from_payload::<C, T>(ctx)).await?))
             }
         }
 
@@ -40,7 +42,7 @@ macro_rules! impl_payload_smart_slice_traits {
         {
             #[inline]
             async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-                Ok($container::from(next.poll_from_payload::<C, Cow<'a, [T]>>(ctx).await?.into_owned()))
+                Ok($container::from(next.poll_from_payload::<C, Vec<T>>(ctx).await?))
             }
         }
 
@@ -1,5 +1,4 @@
 use core::str;
-use std::str::FromStr;
 
 use super::{Error, AsyncMiddleware, AsyncPayload, AsyncIntoPayload, AsyncFromPayload};
 
@@ -34,6 +33,7 @@ impl<'a, C: Send + Sync> AsyncIntoPayload<C> for &'a str {
 impl<'a, C: Send + Sync> AsyncFromPayload<'a, C> for &'a str {
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let nbytes: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<u8>(nbytes).await?;
 
         str::from_utf8(next.poll_read(nbytes).await?).map_err(|e| {
             Error::InvalidUtf8(e.to_string())
@@ -53,6 +53,7 @@ impl<'a, C: Send + Sync> AsyncIntoPayload<C> for &'a mut str {
 impl<'a, C: Send + Sync> AsyncFromPayload<'a, C> for &'a mut str {
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
         let nbytes: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<u8>(nbytes).await?;
         
         str::from_utf8_mut(next.poll_read_mut(nbytes).await?).map_err(|e| {
             Error::InvalidUtf8(e.to_string())
@@ -70,7 +71,10 @@ impl<C: Send + Sync> AsyncIntoPayload<C> for String {
 
 impl<'a, C: Send + Sync> AsyncFromPayload<'a, C> for String {
     async fn poll_from_payload<M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Self, Error> {
-        String::from_str(next.poll_from_payload(ctx).await?).map_err(|e| {
+        let nbytes: usize = next.poll_from_payload(ctx).await?;
+        next.poll_reserve::<u8>(nbytes).await?;
+
+        String::from_utf8(next.poll_read_owned(nbytes).await?).map_err(|e| {
             Error::InvalidUtf8(e.to_string())
         })
     }
@@ -0,0 +1,77 @@
+use crate::Error;
+
+/// A linear mapping of the floats in `min..=max` onto the integers `0..=2^bits - 1`, used by
+/// `#[npsd(quantize(min = .., max = .., bits = ..))]` fields.
+///
+/// Values are clamped into `min..=max` and rounded to the nearest of `2^bits` evenly spaced levels,
+/// `step()` apart. A value in range decodes within `step() / 2` of where it was, e.g. within
+/// `0.0153` for `-1000.0..=1000.0` in 16 bits, and `min` and `max` decode exactly. Decoding into an
+/// `f32` adds the `f32` rounding on top, and above 53 bits the `f64` arithmetic is the limit.
+/// `NaN` encodes as `min`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quantizer {
+    min: f64,
+    max: f64,
+    bits: u32,
+}
+
+impl Quantizer {
+    /// Panics unless `min < max`, both are finite and `bits` is between 1 and 64.
+    pub fn new(min: f64, max: f64, bits: u32) -> Self {
+        assert!(min.is_finite() && max.is_finite() && min < max, "Quantizer range must be finite and not empty");
+        assert!((1..=64).contains(&bits), "Quantizer bits must be between 1 and 64");
+
+        Self {
+            min,
+            max,
+            bits,
+        }
+    }
+
+    #[inline(always)]
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    #[inline(always)]
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    #[inline(always)]
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Returns the highest level, the one `max` maps to.
+    #[inline(always)]
+    pub fn max_level(&self) -> u64 {
+        u64::MAX >> (64 - self.bits)
+    }
+
+    /// Returns the distance between two neighbouring levels.
+    #[inline(always)]
+    pub fn step(&self) -> f64 {
+        (self.max - self.min) / self.max_level() as f64
+    }
+
+    /// Returns the level closest to `value`, clamped into `min..=max`.
+    pub fn quantize(&self, value: f64) -> u64 {
+        let value = if value.is_nan() { self.min } else { value.clamp(self.min, self.max) };
+
+        (((value - self.min) / (self.max - self.min)) * self.max_level() as f64).round() as u64
+    }
+
+    /// Returns the value of `level`, failing with `Error::OutOfRange` above `max_level()`.
+    pub fn dequantize(&self, level: u64) -> Result<f64, Error> {
+        if level > self.max_level() {
+            return Err(Error::OutOfRange(format!("Level `{}` is above `{}`, the highest of {} bits", level, self.max_level(), self.bits)));
+        }
+
+        if level == self.max_level() {
+            return Ok(self.max);
+        }
+
+        Ok(self.min + (level as f64 / self.max_level() as f64) * (self.max - self.min))
+    }
+}
@@ -0,0 +1,214 @@
+//! Dispatching of self-identifying messages to per-type handlers.
+//!
+//! Messages are expected in the format written by `NextTyped`: the `PayloadInfo::HASH` of the
+//! type as 8 big-endian bytes, followed by the encoded value. This lets many message types
+//! share one connection without a hand-written enum to multiplex them.
+
+use std::collections::HashMap;
+
+#[cfg(feature = "async")]
+use std::{future::Future, pin::Pin, sync::Arc};
+
+#[cfg(feature = "sync")]
+use crate::{FromPayload, Middleware};
+
+#[cfg(feature = "async")]
+use crate::{AsyncFromPayload, AsyncMiddleware};
+
+use crate::{type_hash, Error, Next, PayloadInfo, TYPE_HASH_LEN};
+
+#[cfg(feature = "sync")]
+type Handler<C> = Box<dyn FnMut(&[u8], &mut C) -> Result<(), Error> + Send + Sync>;
+
+type Fallback<C> = Box<dyn FnMut(u64, &[u8], &mut C) -> Result<(), Error> + Send + Sync>;
+
+#[cfg(feature = "async")]
+type AsyncHandler<C> = Box<dyn for<'r> Fn(&'r [u8], &'r mut C) -> Pin<Box<dyn Future<Output = Result<(), Error>> + 'r>> + Send + Sync>;
+
+/// Calls the fallback handler for an unregistered hash, or fails with `Error::UnknownType`.
+fn fallback<C>(fallback: &mut Option<Fallback<C>>, hash: u64, body: &[u8], ctx: &mut C) -> Result<(), Error> {
+    match fallback {
+        Some(fallback) => fallback(hash, body, ctx),
+        None => Err(Error::UnknownType(hash)),
+    }
+}
+
+/// Routes messages to the handler registered for their type. Requires the `info` feature to be enabled.
+///
+/// ```
+/// use npsd::{Info, Next, NextTyped, Payload, Registry, Schema};
+///
+/// #[derive(Schema, Info, PartialEq, Debug)]
+/// struct Login {
+///     user: String,
+/// }
+///
+/// let mut registry = Registry::new();
+///
+/// registry.on::<Login>(|login, users: &mut Vec<String>| {
+///     users.push(login.user);
+///     Ok(())
+/// });
+///
+/// let mut next = NextTyped::new(Next::default());
+/// Login { user: "admin".to_string() }.into_packet(&mut Vec::<String>::new(), &mut next).unwrap();
+///
+/// let mut users = Vec::new();
+/// registry.dispatch(next.get_ref().as_slice(), &mut users).unwrap();
+///
+/// assert_eq!(users, vec!["admin"]);
+/// ```
+#[cfg(feature = "sync")]
+pub struct Registry<C> {
+    handlers: HashMap<u64, Handler<C>>,
+    fallback: Option<Fallback<C>>,
+}
+
+#[cfg(feature = "sync")]
+impl<C> Registry<C> {
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Registers the handler for messages of type `T`, replacing any previous one.
+    pub fn on<T: for<'a> FromPayload<'a, C> + PayloadInfo>(&mut self, mut handler: impl FnMut(T, &mut C) -> Result<(), Error> + Send + Sync + 'static) -> &mut Self {
+        self.handlers.insert(T::HASH, Box::new(move |body, ctx| {
+            let value = Next::from(body).from_payload::<C, T>(ctx)?;
+            handler(value, ctx)
+        }));
+
+        self
+    }
+
+    /// Registers the handler for messages of unregistered types, called with the type hash and the
+    /// undecoded value. Without it, such messages fail with `Error::UnknownType`.
+    pub fn fallback(&mut self, handler: impl FnMut(u64, &[u8], &mut C) -> Result<(), Error> + Send + Sync + 'static) -> &mut Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns whether a handler is registered for `T`.
+    pub fn contains<T: PayloadInfo>(&self) -> bool {
+        self.handlers.contains_key(&T::HASH)
+    }
+
+    /// Decodes `packet` and calls the handler registered for its type.
+    pub fn dispatch(&mut self, packet: &[u8], ctx: &mut C) -> Result<(), Error> {
+        let hash = type_hash(packet)?;
+        let body = &packet[TYPE_HASH_LEN..];
+
+        match self.handlers.get_mut(&hash) {
+            Some(handler) => handler(body, ctx),
+            None => fallback(&mut self.fallback, hash, body, ctx),
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<C> Default for Registry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The asynchronous flavor of `Registry`, decoding with `AsyncFromPayload`.
+/// Requires the `info` feature to be enabled.
+///
+/// The future returned by a handler may borrow the context until it completes. The value is
+/// decoded by awaiting `AsyncFromPayload`, whose futures aren't `Send`, so neither is the future
+/// of `dispatch`; await it on the task that reads the messages, or spawn it on a `LocalSet`.
+///
+/// ```
+/// # async fn run() -> Result<(), npsd::Error> {
+/// use npsd::{AsyncPayload, AsyncRegistry, AsyncSchema, Info, Next, NextTyped};
+///
+/// #[derive(AsyncSchema, Info, PartialEq, Debug)]
+/// struct Login {
+///     user: String,
+/// }
+///
+/// let mut registry = AsyncRegistry::new();
+///
+/// registry.on::<Login, _>(|login, users: &mut Vec<String>| Box::pin(async move {
+///     users.push(login.user);
+///     Ok(())
+/// }));
+///
+/// let mut next = NextTyped::new(Next::default());
+/// Login { user: "admin".to_string() }.poll_into_packet(&mut Vec::<String>::new(), &mut next).await?;
+///
+/// let mut users = Vec::new();
+/// registry.dispatch(next.get_ref().as_slice(), &mut users).await?;
+///
+/// assert_eq!(users, vec!["admin"]);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub struct AsyncRegistry<C> {
+    handlers: HashMap<u64, AsyncHandler<C>>,
+    fallback: Option<Fallback<C>>,
+}
+
+#[cfg(feature = "async")]
+impl<C: Send + Sync> AsyncRegistry<C> {
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Registers the handler for messages of type `T`, replacing any previous one.
+    pub fn on<T, F>(&mut self, handler: F) -> &mut Self
+    where
+        T: for<'a> AsyncFromPayload<'a, C> + PayloadInfo + 'static,
+        F: for<'c> Fn(T, &'c mut C) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'c>> + Send + Sync + 'static,
+    {
+        let handler = Arc::new(handler);
+
+        self.handlers.insert(T::HASH, Box::new(move |body, ctx| {
+            let handler = handler.clone();
+
+            Box::pin(async move {
+                let value = Next::from(body).poll_from_payload::<C, T>(ctx).await?;
+                handler(value, ctx).await
+            })
+        }));
+
+        self
+    }
+
+    /// Registers the handler for messages of unregistered types, called with the type hash and the
+    /// undecoded value. Without it, such messages fail with `Error::UnknownType`.
+    pub fn fallback(&mut self, handler: impl FnMut(u64, &[u8], &mut C) -> Result<(), Error> + Send + Sync + 'static) -> &mut Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns whether a handler is registered for `T`.
+    pub fn contains<T: PayloadInfo>(&self) -> bool {
+        self.handlers.contains_key(&T::HASH)
+    }
+
+    /// Decodes `packet` and calls the handler registered for its type.
+    pub async fn dispatch(&mut self, packet: &[u8], ctx: &mut C) -> Result<(), Error> {
+        let hash = type_hash(packet)?;
+        let body = &packet[TYPE_HASH_LEN..];
+
+        match self.handlers.get(&hash) {
+            Some(handler) => handler(body, ctx).await,
+            None => fallback(&mut self.fallback, hash, body, ctx),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<C: Send + Sync> Default for AsyncRegistry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,359 @@
+//! Request/response RPC over any frame transport. Requires the `rpc` feature to be enabled.
+//!
+//! Services are usually declared with `#[npsd::service]`, which generates a client stub calling
+//! `RpcClient::call` and a server dispatcher implementing `Service`, to be run with `serve`.
+//!
+//! Every request frame is a `u64` correlation id and a `u64` method hash, both big-endian,
+//! followed by the arguments encoded as a tuple. Every response frame is the correlation id of
+//! the request, a status byte and either the encoded return value (`0`) or an encoded `Error`
+//! (`1`). Responses can come back in any order, so several calls can be in flight at once, though
+//! `serve` answers them one at a time.
+//!
+//! Like the rest of the async API, the futures in this module are not `Send`; run servers and
+//! concurrent calls with `tokio::join!` or on a `LocalSet` rather than with `tokio::spawn`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::Mutex;
+
+use crate::{AsyncFromPayload, AsyncIntoPayload, AsyncMiddleware, Error, Next};
+
+/// The default maximum size of a frame sent over a `StreamTransport`, 8 MiB.
+pub const DEFAULT_MAX_RPC_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+const FRAME_LEN_SIZE: usize = 4;
+const REQUEST_HEADER_LEN: usize = 16;
+const RESPONSE_HEADER_LEN: usize = 9;
+
+const STATUS_OK: u8 = 0;
+const STATUS_ERROR: u8 = 1;
+
+/// The sending half of a `Transport`.
+pub trait FrameSender: Send {
+    fn send(&mut self, frame: &[u8]) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+/// The receiving half of a `Transport`.
+///
+/// ### Methods
+/// - `fn recv(&mut self) -> impl Future<Output = Result<Option<Vec<u8>>, Error>>`:
+///     - Waits for the next complete frame, or returns `Ok(None)` once the peer is gone. It must be
+///       cancel safe: dropping the future before it completes must not lose part of a frame.
+pub trait FrameReceiver: Send {
+    fn recv(&mut self) -> impl Future<Output = Result<Option<Vec<u8>>, Error>> + Send;
+}
+
+/// A bidirectional, message-oriented connection, split into halves that can be used concurrently.
+pub trait Transport {
+    type Sender: FrameSender;
+    type Receiver: FrameReceiver;
+
+    fn split(self) -> (Self::Sender, Self::Receiver);
+}
+
+/// A `Transport` over a byte stream, where every frame is prefixed with its length as a
+/// big-endian `u32`. Frames over `max_frame_size` fail with `Error::FrameTooLarge` on both ends.
+#[derive(Debug)]
+pub struct StreamTransport<S> {
+    inner: S,
+    max_frame_size: usize,
+}
+
+impl<S: AsyncRead + AsyncWrite + Send> StreamTransport<S> {
+    pub fn new(inner: S) -> Self {
+        Self::with_max_frame_size(inner, DEFAULT_MAX_RPC_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(inner: S, max_frame_size: usize) -> Self {
+        Self {
+            inner,
+            max_frame_size: max_frame_size.min(u32::MAX as usize),
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl StreamTransport<TcpStream> {
+    /// Opens a TCP connection to `addr`.
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+
+        Ok(Self::new(stream))
+    }
+}
+
+/// Returns both ends of an in-memory `StreamTransport`, for tests and in-process services.
+pub fn loopback() -> (StreamTransport<DuplexStream>, StreamTransport<DuplexStream>) {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    (StreamTransport::new(client), StreamTransport::new(server))
+}
+
+impl<S: AsyncRead + AsyncWrite + Send> Transport for StreamTransport<S> {
+    type Sender = StreamSender<WriteHalf<S>>;
+    type Receiver = StreamReceiver<ReadHalf<S>>;
+
+    fn split(self) -> (Self::Sender, Self::Receiver) {
+        let (reader, writer) = tokio::io::split(self.inner);
+
+        (
+            StreamSender { inner: writer, max_frame_size: self.max_frame_size },
+            StreamReceiver { inner: reader, max_frame_size: self.max_frame_size, buffer: Vec::new() },
+        )
+    }
+}
+
+/// The sending half of a `StreamTransport`.
+#[derive(Debug)]
+pub struct StreamSender<W> {
+    inner: W,
+    max_frame_size: usize,
+}
+
+impl<W: AsyncWrite + Unpin + Send> FrameSender for StreamSender<W> {
+    async fn send(&mut self, frame: &[u8]) -> Result<(), Error> {
+        if frame.len() > self.max_frame_size {
+            return Err(Error::FrameTooLarge { size: frame.len(), max: self.max_frame_size });
+        }
+
+        self.inner.write_all(&(frame.len() as u32).to_be_bytes()).await?;
+        self.inner.write_all(frame).await?;
+
+        Ok(self.inner.flush().await?)
+    }
+}
+
+/// The receiving half of a `StreamTransport`.
+///
+/// The bytes of a frame are buffered until it is complete, so a `recv` that is dropped halfway
+/// picks up where it left off the next time.
+#[derive(Debug)]
+pub struct StreamReceiver<R> {
+    inner: R,
+    max_frame_size: usize,
+    buffer: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin + Send> FrameReceiver for StreamReceiver<R> {
+    async fn recv(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        loop {
+            let mut needed = FRAME_LEN_SIZE;
+
+            if let Some(len) = self.buffer.get(..FRAME_LEN_SIZE) {
+                let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+
+                if len > self.max_frame_size {
+                    return Err(Error::FrameTooLarge { size: len, max: self.max_frame_size });
+                }
+
+                needed += len;
+
+                if self.buffer.len() >= needed {
+                    let frame = self.buffer[FRAME_LEN_SIZE..needed].to_vec();
+                    self.buffer.drain(..needed);
+
+                    return Ok(Some(frame));
+                }
+            }
+
+            self.buffer.reserve(needed - self.buffer.len());
+
+            if self.inner.read_buf(&mut self.buffer).await? == 0 {
+                return match self.buffer.is_empty() {
+                    true => Ok(None),
+                    false => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                };
+            }
+        }
+    }
+}
+
+/// Encodes a value on its own, as done for arguments and return values.
+pub async fn encode_message<T: AsyncIntoPayload<()>>(value: &T) -> Result<Vec<u8>, Error> {
+    let mut next = Next::default();
+    next.poll_into_payload(value, &mut ()).await?;
+
+    Ok(next.serialized())
+}
+
+/// Decodes a value encoded with `encode_message`.
+pub async fn decode_message<T: for<'a> AsyncFromPayload<'a, ()>>(bytes: &[u8]) -> Result<T, Error> {
+    Next::from(bytes).poll_from_payload(&mut ()).await
+}
+
+#[inline(always)]
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]])
+}
+
+/// The client end of a connection, matching responses to calls by correlation id.
+///
+/// `call` takes `&self`, so several calls can be awaited concurrently. Whichever call holds the
+/// receiving half reads the next response, and keeps it aside if it belongs to another call.
+/// A call that is dropped before its response came in is forgotten, and its response is
+/// discarded when it arrives.
+pub struct RpcClient<T: Transport> {
+    sender: Mutex<T::Sender>,
+    receiver: Mutex<T::Receiver>,
+    responses: Responses,
+    next_id: AtomicU64,
+}
+
+/// The calls waiting for their response, with the response once another call received it.
+type Responses = std::sync::Mutex<HashMap<u64, Option<Vec<u8>>>>;
+
+/// Removes a call from the responses when it completes or is dropped.
+struct PendingCall<'c> {
+    responses: &'c Responses,
+    id: u64,
+}
+
+impl Drop for PendingCall<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut responses) = self.responses.lock() {
+            responses.remove(&self.id);
+        }
+    }
+}
+
+impl<T: Transport> RpcClient<T> {
+    pub fn new(transport: T) -> Self {
+        let (sender, receiver) = transport.split();
+
+        Self {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+            responses: std::sync::Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the number of calls waiting for their response.
+    pub fn pending(&self) -> usize {
+        self.responses.lock().map(|responses| responses.len()).unwrap_or(0)
+    }
+
+    /// Calls `method` with `args`, usually a tuple of the method arguments.
+    ///
+    /// Fails with the `Error` returned by the server when it couldn't run the method, for
+    /// example `Error::UnknownMethod`, or with any transport or decoding error.
+    pub async fn call<A: AsyncIntoPayload<()>, R: for<'a> AsyncFromPayload<'a, ()>>(&self, method: u64, args: &A) -> Result<R, Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        let mut next = Next::default();
+        next.poll_write(&id.to_be_bytes()).await?;
+        next.poll_write(&method.to_be_bytes()).await?;
+        next.poll_into_payload(args, &mut ()).await?;
+
+        let _pending = self.register(id);
+        self.sender.lock().await.send(next.as_slice()).await?;
+
+        let response = self.response(id).await?;
+
+        match response[0] {
+            STATUS_OK => decode_message(&response[1..]).await,
+            STATUS_ERROR => Err(decode_message(&response[1..]).await?),
+            status => Err(Error::UnknownVariant(format!("Response status `{}`", status))),
+        }
+    }
+
+    fn register(&self, id: u64) -> PendingCall<'_> {
+        if let Ok(mut responses) = self.responses.lock() {
+            responses.insert(id, None);
+        }
+
+        PendingCall { responses: &self.responses, id }
+    }
+
+    fn take(&self, id: u64) -> Option<Vec<u8>> {
+        self.responses.lock().ok()?.get_mut(&id)?.take()
+    }
+
+    async fn response(&self, id: u64) -> Result<Vec<u8>, Error> {
+        loop {
+            if let Some(response) = self.take(id) {
+                return Ok(response);
+            }
+
+            let mut receiver = self.receiver.lock().await;
+
+            // Another call may have received it while we were waiting for the receiver.
+            if let Some(response) = self.take(id) {
+                return Ok(response);
+            }
+
+            let frame = match receiver.recv().await? {
+                Some(frame) if frame.len() >= RESPONSE_HEADER_LEN => frame,
+                Some(frame) => return Err(Error::InvalidLength { expected: RESPONSE_HEADER_LEN, found: frame.len() }),
+                None => return Err(Error::Io("Connection closed".to_string())),
+            };
+
+            let found = read_u64(&frame);
+
+            if found == id {
+                return Ok(frame[8..].to_vec());
+            }
+
+            // Responses to calls that were dropped are discarded.
+            if let Some(response) = self.responses.lock().ok().as_mut().and_then(|responses| responses.get_mut(&found)) {
+                *response = Some(frame[8..].to_vec());
+            }
+        }
+    }
+}
+
+/// The server end of a service, usually generated by `#[npsd::service]`.
+///
+/// ### Methods
+/// - `fn call(&self, method: u64, args: &[u8]) -> impl Future<Output = Result<Vec<u8>, Error>>`:
+///     - Decodes `args`, runs `method` and returns its encoded return value. Fails with
+///       `Error::UnknownMethod` for methods the service doesn't have.
+pub trait Service: Send + Sync {
+    fn call(&self, method: u64, args: &[u8]) -> impl Future<Output = Result<Vec<u8>, Error>>;
+}
+
+/// Answers the requests arriving on `transport` until the client goes away.
+///
+/// Requests are handled one at a time, in the order they arrive: the next request is only read
+/// once the response to the previous one was sent, so a slow method delays every call queued
+/// behind it on the same connection. Errors from the service are sent back to the client, only
+/// transport errors and malformed frames end the loop.
+pub async fn serve<S: Service, T: Transport>(service: &S, transport: T) -> Result<(), Error> {
+    let (mut sender, mut receiver) = transport.split();
+
+    while let Some(frame) = receiver.recv().await? {
+        if frame.len() < REQUEST_HEADER_LEN {
+            return Err(Error::InvalidLength { expected: REQUEST_HEADER_LEN, found: frame.len() });
+        }
+
+        let method = read_u64(&frame[8..]);
+
+        let (status, body) = match service.call(method, &frame[REQUEST_HEADER_LEN..]).await {
+            Ok(body) => (STATUS_OK, body),
+            Err(error) => (STATUS_ERROR, encode_message(&error).await?),
+        };
+
+        let mut response = Vec::with_capacity(RESPONSE_HEADER_LEN + body.len());
+        response.extend_from_slice(&frame[..8]);
+        response.push(status);
+        response.extend_from_slice(&body);
+
+        sender.send(&response).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+#[cfg(feature = "tokio")]
+use npsd::{Info, AsyncSchema, AsyncPayload, Error, AsyncNextReader, AsyncNextWriter};
+
+#[cfg(feature = "tokio")]
+#[derive(AsyncSchema, Info, PartialEq, Debug)]
+enum Animal {
+    Dog,
+    Frog(String, Vec<isize>),
+    Cat { age: usize, name: String },
+    AntHive(Vec<String>),
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_tokio_duplex() -> Result<(), Error> {
+    let (client, server) = tokio::io::duplex(16);
+
+    // The duplex buffer is smaller than the messages, so both sides have to make progress together.
+    let sender = async move {
+        let mut next = AsyncNextWriter::new(client);
+
+        Animal::Cat { age: 3, name: "Felix".to_string() }.poll_into_packet(&mut (), &mut next).await?;
+        Animal::AntHive(vec!["Queen".to_string(), "Worker".to_string()]).poll_into_packet(&mut (), &mut next).await?;
+        Animal::Frog("Frog".to_string(), vec![12393818, -19383812, 11111, -1093838482]).poll_into_packet(&mut (), &mut next).await
+    };
+
+    let receiver = async move {
+        let mut next = AsyncNextReader::new(server);
+
+        assert_eq!(Animal::poll_from_packet(&mut (), &mut next).await?, Animal::Cat { age: 3, name: "Felix".to_string() });
+        assert_eq!(Animal::poll_from_packet(&mut (), &mut next).await?, Animal::AntHive(vec!["Queen".to_string(), "Worker".to_string()]));
+        assert_eq!(Animal::poll_from_packet(&mut (), &mut next).await?, Animal::Frog("Frog".to_string(), vec![12393818, -19383812, 11111, -1093838482]));
+
+        Ok(())
+    };
+
+    tokio::try_join!(sender, receiver).map(|_| ())
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_tokio_tcp() -> Result<(), Error> {
+    use tokio::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.map_err(Error::from)?;
+    let addr = listener.local_addr().map_err(Error::from)?;
+
+    let sender = async move {
+        let mut next = AsyncNextWriter::new(TcpStream::connect(addr).await.map_err(Error::from)?);
+
+        Animal::Dog.poll_into_packet(&mut (), &mut next).await
+    };
+
+    let receiver = async move {
+        let (stream, _) = listener.accept().await.map_err(Error::from)?;
+        let mut next = AsyncNextReader::new(stream);
+
+        assert_eq!(Animal::poll_from_packet(&mut (), &mut next).await?, Animal::Dog);
+
+        Ok(())
+    };
+
+    tokio::try_join!(sender, receiver).map(|_| ())
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_tokio_borrowed() {
+    let (client, server) = tokio::io::duplex(64);
+
+    let mut writer = AsyncNextWriter::new(client);
+    "Hello World!!!".poll_into_packet(&mut (), &mut writer).await.unwrap();
+
+    let mut reader = AsyncNextReader::new(server);
+    assert!(matches!(<&str>::poll_from_packet(&mut (), &mut reader).await, Err(Error::Unsupported(_))));
+}
@@ -0,0 +1,561 @@
+#![cfg(any(feature = "sync", feature = "async"))]
+
+use npsd::{Error, Info, Next};
+
+#[cfg(feature = "sync")]
+use npsd::{Schema, Payload};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncSchema, AsyncPayload};
+
+/// Variants were reordered and `Ping` was added, the tags keep the old wire format.
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+#[npsd(tag_type = u8)]
+enum Command {
+    #[npsd(tag = 7)]
+    Ping,
+    #[npsd(tag = 1)]
+    Move { x: i32, y: i32 },
+    #[npsd(tag = 0)]
+    Quit,
+    #[npsd(tag = 2)]
+    Say(String),
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+#[repr(u16)]
+enum Status {
+    Ok = 200,
+    NotFound = 404,
+    Teapot = 418,
+    Unknown,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+enum Level {
+    Low = 10,
+    Mid,
+    #[npsd(tag = 300)]
+    High,
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_explicit_tags() -> Result<(), Error> {
+    let cases = [
+        (Command::Ping, vec![7u8]),
+        (Command::Move { x: 1, y: -1 }, vec![1, 0, 0, 0, 1, 0xff, 0xff, 0xff, 0xff]),
+        (Command::Quit, vec![0]),
+        (Command::Say("hi".to_string()), vec![2, 2, b'h', b'i']),
+    ];
+
+    for (command, bytes) in cases {
+        let mut next = Next::default();
+        command.into_packet(&mut (), &mut next)?;
+        assert_eq!(next.serialized(), bytes);
+
+        let mut next = Next::from(bytes.as_slice());
+        assert_eq!(Command::from_packet(&mut (), &mut next)?, command);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_discriminant_tags() -> Result<(), Error> {
+    let mut next = Next::default();
+    Status::NotFound.into_packet(&mut (), &mut next)?;
+    assert_eq!(next.serialized(), 404u16.to_be_bytes());
+
+    let mut next = Next::default();
+    Status::Unknown.into_packet(&mut (), &mut next)?;
+    assert_eq!(next.serialized(), 419u16.to_be_bytes());
+
+    let bytes = 418u16.to_be_bytes();
+    let mut next = Next::from(&bytes[..]);
+    assert_eq!(Status::from_packet(&mut (), &mut next)?, Status::Teapot);
+
+    let mut next = Next::default();
+    (Level::Low, Level::Mid, Level::High).into_packet(&mut (), &mut next)?;
+    assert_eq!(next.serialized(), [10, 11, 0xac, 0x02]);
+
+    let mut next = Next::from(&[3u8][..]);
+    assert!(matches!(Command::from_packet(&mut (), &mut next).map_err(|e| e.kind().clone()), Err(Error::UnknownVariant(_))));
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_tags() -> Result<(), Error> {
+    let mut next = Next::default();
+    (Command::Move { x: 1, y: -1 }, Status::Teapot, Level::High).poll_into_packet(&mut (), &mut next).await?;
+    let bytes = next.serialized();
+    assert_eq!(bytes[0], 1);
+
+    let mut next = Next::from(bytes.as_slice());
+    let value = <(Command, Status, Level)>::poll_from_packet(&mut (), &mut next).await?;
+    assert_eq!(value, (Command::Move { x: 1, y: -1 }, Status::Teapot, Level::High));
+
+    Ok(())
+}
+
+/// Encodes a `Duration` as whole milliseconds in a `u32`.
+mod millis {
+    use std::time::Duration;
+
+    use npsd::Error;
+
+    #[cfg(feature = "sync")]
+    use npsd::Middleware;
+
+    #[cfg(feature = "async")]
+    use npsd::AsyncMiddleware;
+
+    #[cfg(feature = "sync")]
+    pub fn into_payload<'a, C, M: Middleware<'a>>(value: &Duration, ctx: &mut C, next: &mut M) -> Result<(), Error> {
+        next.into_payload(&(value.as_millis() as u32), ctx)
+    }
+
+    #[cfg(feature = "sync")]
+    pub fn from_payload<'a, C, M: Middleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Duration, Error> {
+        Ok(Duration::from_millis(next.from_payload::<C, u32>(ctx)? as u64))
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn poll_into_payload<'a, C: Send + Sync, M: AsyncMiddleware<'a>>(value: &Duration, ctx: &mut C, next: &mut M) -> Result<(), Error> {
+        next.poll_into_payload(&(value.as_millis() as u32), ctx).await
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn poll_from_payload<'a, C: Send + Sync, M: AsyncMiddleware<'a>>(ctx: &mut C, next: &mut M) -> Result<Duration, Error> {
+        Ok(Duration::from_millis(next.poll_from_payload::<C, u32>(ctx).await? as u64))
+    }
+}
+
+fn not_empty(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("name is empty");
+    }
+
+    Ok(())
+}
+
+fn unknown() -> String {
+    "unknown".to_string()
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Session {
+    #[npsd(validate = "not_empty")]
+    user: String,
+    #[npsd(with = "millis")]
+    timeout: std::time::Duration,
+    #[npsd(skip)]
+    cache: Vec<u8>,
+    #[npsd(skip, default = "unknown")]
+    origin: String,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+enum Event {
+    Login(#[npsd(validate = "not_empty")] String, #[npsd(skip)] u64),
+    Idle {
+        #[npsd(with = "millis")]
+        after: std::time::Duration,
+        #[npsd(skip)]
+        seen: bool,
+    },
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+#[npsd(transparent)]
+struct UserId(String, #[npsd(skip)] std::marker::PhantomData<u64>);
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_field_attributes() -> Result<(), Error> {
+    use std::time::Duration;
+
+    let session = Session {
+        user: "admin".to_string(),
+        timeout: Duration::from_millis(1500),
+        cache: vec![1, 2, 3],
+        origin: "localhost".to_string(),
+    };
+
+    let mut next = Next::default();
+    session.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    assert_eq!(bytes, [&[5][..], b"admin", &1500u32.to_be_bytes()].concat());
+
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(Session::from_packet(&mut (), &mut next)?, Session {
+        cache: Vec::new(),
+        origin: "unknown".to_string(),
+        ..session
+    });
+
+    let mut next = Next::default();
+    (Event::Login("admin".to_string(), 42), Event::Idle { after: Duration::from_secs(2), seen: true }).into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(<(Event, Event)>::from_packet(&mut (), &mut next)?, (Event::Login("admin".to_string(), 0), Event::Idle { after: Duration::from_secs(2), seen: false }));
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_validate_and_transparent() -> Result<(), Error> {
+    let mut next = Next::default();
+    Session { user: String::new(), timeout: Default::default(), cache: Vec::new(), origin: String::new() }.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    let error = Session::from_packet(&mut (), &mut Next::from(bytes.as_slice())).unwrap_err();
+
+    assert_eq!(error.kind(), &Error::Validation("name is empty".to_string()));
+    assert_eq!(error.path(), Some(&["Session", "user"].map(String::from)[..]));
+
+    let mut next = Next::default();
+    UserId("root".to_string(), Default::default()).into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    assert_eq!(bytes, [&[4][..], b"root"].concat());
+
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(UserId::from_packet(&mut (), &mut next)?, UserId("root".to_string(), Default::default()));
+
+    // The error is reported at the inner string, without a `UserId` segment.
+    let error = UserId::from_packet(&mut (), &mut Next::from(&bytes[..3])).unwrap_err();
+    assert_eq!(error.path(), None);
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_field_attributes() -> Result<(), Error> {
+    use std::time::Duration;
+
+    let session = Session {
+        user: "admin".to_string(),
+        timeout: Duration::from_millis(1500),
+        cache: vec![1, 2, 3],
+        origin: "localhost".to_string(),
+    };
+
+    let mut next = Next::default();
+    (session, Event::Idle { after: Duration::from_secs(2), seen: true }, UserId("root".to_string(), Default::default())).poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let (session, event, id) = <(Session, Event, UserId)>::poll_from_packet(&mut (), &mut next).await?;
+
+    assert_eq!(session.origin, "unknown");
+    assert_eq!(session.timeout, Duration::from_millis(1500));
+    assert_eq!(event, Event::Idle { after: Duration::from_secs(2), seen: false });
+    assert_eq!(id.0, "root");
+
+    Ok(())
+}
+
+mod v1 {
+    use super::*;
+
+    #[cfg_attr(feature = "async", derive(AsyncSchema))]
+    #[cfg_attr(feature = "sync", derive(Schema))]
+    #[derive(Info, PartialEq, Debug)]
+    #[npsd(tagged)]
+    pub struct Profile {
+        pub name: String,
+        pub age: u8,
+        #[npsd(id = 5)]
+        pub email: String,
+    }
+}
+
+mod v2 {
+    use super::*;
+
+    /// `age` was removed and `country` added, `email` kept its id.
+    #[cfg_attr(feature = "async", derive(AsyncSchema))]
+    #[cfg_attr(feature = "sync", derive(Schema))]
+    #[derive(Info, PartialEq, Debug)]
+    #[npsd(tagged)]
+    pub struct Profile {
+        pub name: String,
+        #[npsd(id = 5)]
+        pub email: String,
+        #[npsd(id = 7, default = "default_country")]
+        pub country: String,
+        #[npsd(skip)]
+        pub cached: bool,
+    }
+
+    fn default_country() -> String {
+        "FI".to_string()
+    }
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_tagged_fields() -> Result<(), Error> {
+    let old = v1::Profile { name: "Matti".to_string(), age: 42, email: "matti@teppo.com".to_string() };
+
+    let mut next = Next::default();
+    old.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    assert_eq!(&bytes[..4], &[3, 0, 6, 5]);
+
+    // A newer peer skips `age` and falls back to the default `country`.
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(v2::Profile::from_packet(&mut (), &mut next)?, v2::Profile {
+        name: "Matti".to_string(),
+        email: "matti@teppo.com".to_string(),
+        country: "FI".to_string(),
+        cached: false,
+    });
+
+    let new = v2::Profile { name: "Teppo".to_string(), email: "teppo@matti.com".to_string(), country: "SE".to_string(), cached: true };
+
+    let mut next = Next::default();
+    new.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(v2::Profile::from_packet(&mut (), &mut next)?, v2::Profile { cached: false, ..new });
+
+    // An older peer skips `country`, but `age` has no default.
+    let error = v1::Profile::from_packet(&mut (), &mut Next::from(bytes.as_slice())).unwrap_err();
+    assert_eq!(error.kind(), &Error::MissingField("Profile.age".to_string()));
+    assert_eq!(error.path(), Some(&["Profile", "age"].map(String::from)[..]));
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_tagged_fields() -> Result<(), Error> {
+    let old = v1::Profile { name: "Matti".to_string(), age: 42, email: "matti@teppo.com".to_string() };
+
+    let mut next = Next::default();
+    old.poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let new = v2::Profile::poll_from_packet(&mut (), &mut next).await?;
+
+    assert_eq!(new.email, "matti@teppo.com");
+    assert_eq!(new.country, "FI");
+
+    Ok(())
+}
+
+/// Version 2 added `color`, version 4 dropped `legacy` and added `tags`.
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Shape {
+    sides: u8,
+    #[npsd(since = 2)]
+    color: u32,
+    #[npsd(until = 4, default = "legacy")]
+    legacy: String,
+    #[npsd(since = 4)]
+    tags: Vec<String>,
+}
+
+fn legacy() -> String {
+    "legacy".to_string()
+}
+
+/// A context carrying the negotiated version along with other state.
+#[cfg(feature = "sync")]
+struct Peer {
+    version: u32,
+}
+
+#[cfg(feature = "sync")]
+impl npsd::HasVersion for Peer {
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+#[npsd(tagged)]
+struct TaggedShape {
+    sides: u8,
+    #[npsd(since = 2)]
+    color: u32,
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_versioned_fields() -> Result<(), Error> {
+    let shape = Shape { sides: 3, color: 0xff0000, legacy: "old".to_string(), tags: vec!["red".to_string()] };
+
+    let mut next = Next::default();
+    shape.into_packet(&mut 1u32, &mut next)?;
+
+    let bytes = next.serialized();
+    assert_eq!(bytes, [&[3, 3][..], b"old"].concat());
+
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(Shape::from_packet(&mut 1u32, &mut next)?, Shape { sides: 3, color: 0, legacy: "old".to_string(), tags: Vec::new() });
+
+    let mut next = Next::default();
+    shape.into_packet(&mut Peer { version: 4 }, &mut next)?;
+
+    let bytes = next.serialized();
+    assert_eq!(bytes, [&[3][..], &0xff0000u32.to_be_bytes(), &[1, 3], b"red"].concat());
+
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(Shape::from_packet(&mut Peer { version: 4 }, &mut next)?, Shape { legacy: "legacy".to_string(), ..shape });
+
+    let mut next = Next::default();
+    TaggedShape { sides: 4, color: 7 }.into_packet(&mut 1u32, &mut next)?;
+
+    let bytes = next.serialized();
+    assert_eq!(bytes, [1, 0, 1, 4]);
+
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(TaggedShape::from_packet(&mut 2u32, &mut next)?, TaggedShape { sides: 4, color: 0 });
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_versioned_fields() -> Result<(), Error> {
+    let shape = Shape { sides: 3, color: 0xff0000, legacy: "old".to_string(), tags: vec!["red".to_string()] };
+
+    for mut version in 1..5u32 {
+        let mut next = Next::default();
+        (&shape, TaggedShape { sides: 4, color: 7 }).poll_into_packet(&mut version, &mut next).await?;
+
+        let bytes = next.serialized();
+        let mut next = Next::from(bytes.as_slice());
+        let (decoded, tagged) = <(Shape, TaggedShape)>::poll_from_packet(&mut version, &mut next).await?;
+
+        assert_eq!(decoded.sides, 3);
+        assert_eq!(decoded.color, if version >= 2 { 0xff0000 } else { 0 });
+        assert_eq!(decoded.legacy, if version < 4 { "old" } else { "legacy" });
+        assert_eq!(tagged.color, if version >= 2 { 7 } else { 0 });
+    }
+
+    Ok(())
+}
+
+mod v3 {
+    use super::*;
+
+    #[cfg_attr(feature = "async", derive(AsyncSchema))]
+    #[cfg_attr(feature = "sync", derive(Schema))]
+    #[derive(Info, Clone, PartialEq, Debug)]
+    #[npsd(open, tag_type = u8)]
+    pub enum Message {
+        Ping,
+        Text(String),
+        #[npsd(other)]
+        Unknown(u8, Vec<u8>),
+    }
+}
+
+mod v4 {
+    use super::*;
+
+    /// `Text` gained a field and `Move` was added.
+    #[cfg_attr(feature = "async", derive(AsyncSchema))]
+    #[cfg_attr(feature = "sync", derive(Schema))]
+    #[derive(Info, Clone, PartialEq, Debug)]
+    #[npsd(open, tag_type = u8)]
+    pub enum Message {
+        Ping,
+        Text(String, u32),
+        Move { x: i32, y: i32 },
+        #[npsd(other)]
+        Unknown { tag: u8, bytes: Vec<u8> },
+    }
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_open_enum() -> Result<(), Error> {
+    let messages = vec![
+        v4::Message::Ping,
+        v4::Message::Text("hello".to_string(), 7),
+        v4::Message::Move { x: 1, y: 2 },
+    ];
+
+    let mut next = Next::default();
+    messages.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    assert_eq!(&bytes[..3], &[3, 0, 0]);
+
+    // An older peer reads the known prefix of `Text` and keeps `Move` as raw bytes.
+    let mut next = Next::from(bytes.as_slice());
+    let old = Vec::<v3::Message>::from_packet(&mut (), &mut next)?;
+
+    assert_eq!(old, vec![
+        v3::Message::Ping,
+        v3::Message::Text("hello".to_string()),
+        v3::Message::Unknown(2, [1i32.to_be_bytes(), 2i32.to_be_bytes()].concat()),
+    ]);
+
+    // Forwarding the unknown variant writes it back unchanged.
+    let mut next = Next::default();
+    old[2].into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(v4::Message::from_packet(&mut (), &mut next)?, v4::Message::Move { x: 1, y: 2 });
+
+    let mut next = Next::from(&[9u8, 2, 0xab, 0xcd][..]);
+    assert_eq!(v4::Message::from_packet(&mut (), &mut next)?, v4::Message::Unknown { tag: 9, bytes: vec![0xab, 0xcd] });
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_open_enum() -> Result<(), Error> {
+    let mut next = Next::default();
+    (v4::Message::Move { x: -1, y: 5 }, v4::Message::Text("hi".to_string(), 1)).poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let (moved, text) = <(v3::Message, v3::Message)>::poll_from_packet(&mut (), &mut next).await?;
+
+    assert_eq!(moved, v3::Message::Unknown(2, [(-1i32).to_be_bytes(), 5i32.to_be_bytes()].concat()));
+    assert_eq!(text, v3::Message::Text("hi".to_string()));
+
+    let mut next = Next::default();
+    moved.poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(v4::Message::poll_from_packet(&mut (), &mut next).await?, v4::Message::Move { x: -1, y: 5 });
+
+    Ok(())
+}
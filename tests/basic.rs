@@ -1,3 +1,5 @@
+#![cfg(any(feature = "sync", feature = "async"))]
+
 use npsd::Info;
 
 #[cfg(feature = "sync")]
@@ -0,0 +1,153 @@
+#![cfg(any(feature = "sync", feature = "async"))]
+
+use npsd::{BitField, Error, Info, Next};
+
+#[cfg(feature = "sync")]
+use npsd::{Bitmap, Payload};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncBitmap, AsyncPayload};
+
+#[derive(BitField, PartialEq, Debug, Clone, Copy)]
+enum Priority {
+    Idle,
+    Low,
+    Normal,
+    High,
+    #[npsd(tag = 7)]
+    Critical,
+}
+
+/// A 3-bit priority and five flags share one byte.
+#[cfg_attr(feature = "async", derive(AsyncBitmap))]
+#[cfg_attr(feature = "sync", derive(Bitmap))]
+#[derive(Info, PartialEq, Debug)]
+struct Header {
+    #[npsd(bits = 3)]
+    priority: Priority,
+    ack: bool,
+    syn: bool,
+    fin: bool,
+    rst: bool,
+    urgent: bool,
+}
+
+#[cfg(feature = "sync")]
+#[derive(Bitmap, Info, PartialEq, Debug)]
+struct Flags12(bool, bool, bool, bool, bool, bool, bool, bool, bool, bool, bool, bool);
+
+#[cfg_attr(feature = "async", derive(AsyncBitmap))]
+#[cfg_attr(feature = "sync", derive(Bitmap))]
+#[derive(Info, PartialEq, Debug)]
+struct Packed {
+    #[npsd(bits = 4)]
+    channel: u8,
+    #[npsd(bits = 20)]
+    sequence: u32,
+    #[npsd(skip)]
+    cached: Option<u64>,
+    reliable: bool,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncBitmap))]
+#[cfg_attr(feature = "sync", derive(Bitmap))]
+#[derive(Info, PartialEq, Debug)]
+struct Wide {
+    #[npsd(bits = 100)]
+    id: u128,
+    #[npsd(bits = 40)]
+    stamp: u64,
+    first: bool,
+    last: bool,
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_bitmap_sub_byte_fields() -> Result<(), Error> {
+    let header = Header { priority: Priority::High, ack: true, syn: false, fin: true, rst: false, urgent: true };
+
+    let mut next = Next::default();
+    header.into_packet(&mut (), &mut next)?;
+    assert_eq!(next.serialized(), [0b1010_1011]);
+
+    let mut next = Next::from(&[0b1010_1011u8][..]);
+    assert_eq!(Header::from_packet(&mut (), &mut next)?, header);
+
+    let mut next = Next::from(&[0b0000_0101u8][..]);
+    let error = Header::from_packet(&mut (), &mut next).unwrap_err();
+    assert_eq!(error.kind(), &Error::UnknownVariant("Unknown tag `5` for enum".to_string()));
+    assert_eq!(error.path(), Some(&["Header", "priority"].map(String::from)[..]));
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_bitmap_storage() -> Result<(), Error> {
+    let flags = Flags12(true, false, false, false, false, false, false, false, false, true, false, true);
+
+    let mut next = Next::default();
+    flags.into_packet(&mut (), &mut next)?;
+    assert_eq!(next.serialized(), 0b1010_0000_0001u16.to_be_bytes());
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(Flags12::from_packet(&mut (), &mut next)?, flags);
+
+    let packed = Packed { channel: 9, sequence: 0xabcde, cached: Some(1), reliable: true };
+
+    let mut next = Next::default();
+    packed.into_packet(&mut (), &mut next)?;
+    assert_eq!(next.serialized(), (9u32 | 0xabcde << 4 | 1 << 24).to_be_bytes());
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(Packed::from_packet(&mut (), &mut next)?, Packed { cached: None, ..packed });
+
+    let mut next = Next::default();
+    let result = Packed { channel: 16, sequence: 0, cached: None, reliable: false }.into_packet(&mut (), &mut next);
+    assert_eq!(result, Err(Error::OutOfRange("`16` doesn't fit into 4 bits of `Packed.channel`".to_string())));
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_bitmap_bitset() -> Result<(), Error> {
+    let wide = Wide { id: (1 << 99) | 5, stamp: 0xff_0000_0001, first: true, last: true };
+
+    let mut next = Next::default();
+    wide.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    assert_eq!(bytes.len(), 18);
+    assert_eq!(bytes[0], 0b0000_0101);
+    assert_eq!(bytes[12], 0b0001_1000);
+    assert_eq!(bytes[17], 0b0011_1111);
+
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(Wide::from_packet(&mut (), &mut next)?, wide);
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_bitmap() -> Result<(), Error> {
+    let value = (
+        Header { priority: Priority::Critical, ack: false, syn: true, fin: false, rst: true, urgent: false },
+        Packed { channel: 15, sequence: 1, cached: None, reliable: false },
+        Wide { id: u128::MAX >> 28, stamp: 3, first: false, last: true },
+    );
+
+    let mut next = Next::default();
+    value.poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.serialized();
+    assert_eq!(bytes[0], 0b0101_0111);
+
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(<(Header, Packed, Wide)>::poll_from_packet(&mut (), &mut next).await?, value);
+
+    Ok(())
+}
@@ -0,0 +1,266 @@
+#![cfg(any(feature = "sync", feature = "async"))]
+
+use npsd::{Error, Info, Limits, Next, NextBits, NextLimits};
+
+#[cfg(feature = "sync")]
+use npsd::{Schema, Payload};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncSchema, AsyncPayload};
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug, Clone)]
+enum Team {
+    Red,
+    Blue,
+    Green,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug, Clone)]
+struct Position {
+    #[npsd(range = -512..512)]
+    x: i32,
+    #[npsd(range = -512..512)]
+    y: i32,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug, Clone)]
+struct Entity {
+    id: u16,
+    team: Team,
+    alive: bool,
+    crouching: bool,
+    #[npsd(range = 0..=100)]
+    health: u8,
+    position: Position,
+    weapon: Option<u8>,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Snapshot {
+    tick: u32,
+    paused: bool,
+    map: String,
+    entities: Vec<Entity>,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Flags {
+    a: bool,
+    b: bool,
+    c: bool,
+    d: u8,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+#[npsd(tagged)]
+struct Toggles {
+    flags: Flags,
+    visible: bool,
+}
+
+fn entity(id: u16) -> Entity {
+    Entity {
+        id,
+        team: Team::Green,
+        alive: true,
+        crouching: id & 1 == 0,
+        health: 100 - id as u8,
+        position: Position { x: -512, y: 511 - id as i32 },
+        weapon: (id != 4).then_some(id as u8),
+    }
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_bits_packing() -> Result<(), Error> {
+    let mut next = NextBits::new(Next::default());
+    (true, false, true).into_packet(&mut (), &mut next)?;
+    assert_eq!(next.get_ref().serialized(), [0b101]);
+
+    // Whole bytes start on the bit after the `bool`, the last byte is padded.
+    let mut next = NextBits::new(Next::default());
+    (true, 300u16).into_packet(&mut (), &mut next)?;
+    assert_eq!(next.get_ref().serialized(), [0x03, 0x58, 0x00]);
+
+    let bytes = next.into_inner().serialized();
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_eq!(<(bool, u16)>::from_packet(&mut (), &mut next)?, (true, 300));
+
+    // Every message is byte aligned.
+    let mut next = NextBits::new(Next::default());
+    true.into_packet(&mut (), &mut next)?;
+    false.into_packet(&mut (), &mut next)?;
+    assert_eq!(next.get_ref().serialized(), [1, 0]);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_bits_nested() -> Result<(), Error> {
+    let value = entity(1);
+
+    // 16 bits of id, 2 of team, 2 flags, 7 of health, 2 * 10 of position, 1 + 8 of weapon.
+    let mut next = NextBits::new(Next::default());
+    value.into_packet(&mut (), &mut next)?;
+    assert_eq!(next.get_ref().serialized().len(), 7);
+
+    let bytes = next.into_inner().serialized();
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_eq!(Entity::from_packet(&mut (), &mut next)?, value);
+
+    // Without bit packing, ranges still take the smallest unsigned integer.
+    let mut next = Next::default();
+    value.into_packet(&mut (), &mut next)?;
+    assert_eq!(next.serialized().len(), 12);
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    assert_eq!(Entity::from_packet(&mut (), &mut next)?, value);
+
+    let mut next = NextBits::new(Next::default());
+    let result = Entity { health: 101, ..value }.into_packet(&mut (), &mut next);
+    assert_eq!(result, Err(Error::OutOfRange("`101` is outside of `0..101` of `Entity.health`".to_string())));
+
+    // A health offset of 127 is on the wire but outside of `0..=100`.
+    let mut next = Next::default();
+    (7u16, 2u8, true, true, 127u8).into_packet(&mut (), &mut next)?;
+    (0u16, 0u16, 0u8).into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let error = Entity::from_packet(&mut (), &mut next).unwrap_err();
+    assert_eq!(error.kind(), &Error::OutOfRange("`127` is outside of `0..101` of `Entity.health`".to_string()));
+    assert_eq!(error.path(), Some(&["Entity", "health"].map(String::from)[..]));
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_bits_snapshot() -> Result<(), Error> {
+    let snapshot = Snapshot {
+        tick: 7,
+        paused: true,
+        map: "dust".to_string(),
+        entities: (0..10).map(entity).collect(),
+    };
+
+    let mut next = NextBits::new(Next::default());
+    snapshot.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.into_inner().serialized();
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_eq!(Snapshot::from_packet(&mut (), &mut next)?, snapshot);
+
+    // Borrowed reads that don't start on a byte boundary are copied.
+    let mut next = NextBits::new(Next::default());
+    (false, "unaligned").into_packet(&mut (), &mut next)?;
+
+    let bytes = next.into_inner().serialized();
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_eq!(<(bool, &str)>::from_packet(&mut (), &mut next)?, (false, "unaligned"));
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_bits_layered() -> Result<(), Error> {
+    let flags = Flags { a: true, b: false, c: true, d: 7 };
+
+    let mut next = NextBits::new(Next::default());
+    flags.into_packet(&mut (), &mut next)?;
+    let bare = next.into_inner().serialized();
+    assert_eq!(bare, [61, 0]);
+
+    let mut next = NextLimits::new(NextBits::new(Next::default()), Limits::default());
+    flags.into_packet(&mut (), &mut next)?;
+    let layered = next.into_inner().into_inner().serialized();
+    assert_eq!(layered, bare);
+
+    // Each one reads what the other wrote, message after message.
+    let bytes = layered.repeat(2);
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_eq!(Flags::from_packet(&mut (), &mut next)?, flags);
+    assert_eq!(Flags::from_packet(&mut (), &mut next)?, flags);
+
+    let bytes = bare.repeat(2);
+    let mut next = NextLimits::new(NextBits::new(Next::from(bytes.as_slice())), Limits::default());
+    assert_eq!(Flags::from_packet(&mut (), &mut next)?, flags);
+    assert_eq!(Flags::from_packet(&mut (), &mut next)?, flags);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_bits_tagged() -> Result<(), Error> {
+    let toggles = || Toggles { flags: Flags { a: true, b: false, c: true, d: 7 }, visible: true };
+
+    // The count, then the id, length and bits of each field.
+    let mut next = NextBits::new(Next::default());
+    toggles().into_packet(&mut (), &mut next)?;
+    assert_eq!(next.into_inner().serialized(), [2, 0, 2, 61, 0, 1, 1, 1]);
+
+    // Fields start on a byte boundary, whatever bits come before them.
+    let mut next = NextLimits::new(NextBits::new(Next::default()), Limits::default());
+    (true, toggles(), false).into_packet(&mut (), &mut next)?;
+
+    let bytes = next.into_inner().into_inner().serialized();
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_eq!(<(bool, Toggles, bool)>::from_packet(&mut (), &mut next)?, (true, toggles(), false));
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_bits() -> Result<(), Error> {
+    let snapshot = Snapshot {
+        tick: 9,
+        paused: false,
+        map: "nuke".to_string(),
+        entities: (3..7).map(entity).collect(),
+    };
+
+    let mut next = NextBits::new(Next::default());
+    snapshot.poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.into_inner().serialized();
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_eq!(Snapshot::poll_from_packet(&mut (), &mut next).await?, snapshot);
+
+    let flags = Flags { a: false, b: true, c: true, d: 255 };
+
+    let mut next = NextLimits::new(NextBits::new(Next::default()), Limits::default());
+    flags.poll_into_packet(&mut (), &mut next).await?;
+    let bytes = next.into_inner().into_inner().serialized();
+    assert_eq!(bytes, [254, 7]);
+
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_eq!(Flags::poll_from_packet(&mut (), &mut next).await?, flags);
+
+    let toggles = Toggles { flags, visible: false };
+
+    let mut next = NextBits::new(Next::default());
+    (true, &toggles).poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.into_inner().serialized();
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_eq!(<(bool, Toggles)>::poll_from_packet(&mut (), &mut next).await?, (true, toggles));
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+#![cfg(any(feature = "sync", feature = "async"))]
+
+use npsd::{Error, Info, NextPacket, Checksum, ChecksumAlgorithm};
+
+#[cfg(feature = "sync")]
+use npsd::{Schema, Payload, PayloadHash};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncSchema, AsyncPayload};
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+enum Animal {
+    Dog,
+    Frog(String, Vec<isize>),
+    Cat { age: usize, name: String },
+    AntHive(Vec<String>),
+}
+
+#[cfg(feature = "sync")]
+fn encode(animal: &Animal, checksum: Checksum) -> Result<Vec<u8>, Error> {
+    let mut next = NextPacket::new(checksum);
+    animal.into_packet(&mut (), &mut next)?;
+    next.seal()
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_checksum_xxh3() -> Result<(), Error> {
+    let animal = Animal::Cat { age: 7, name: "Tom".to_string() };
+    let packet = encode(&animal, Checksum::new(ChecksumAlgorithm::Xxh3))?;
+
+    let (body, trailer) = packet.split_at(packet.len() - 8);
+    assert_eq!(trailer, PayloadHash(body).to_be_bytes());
+
+    let mut next = NextPacket::open(Checksum::default(), &packet)?;
+    assert_eq!(Animal::from_packet(&mut (), &mut next)?, animal);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_checksum_corrupted() -> Result<(), Error> {
+    let animal = Animal::Cat { age: 7, name: "Tom".to_string() };
+    let packet = encode(&animal, Checksum::default())?;
+
+    // "Tom" -> "Tim" still decodes into a plausible `Animal` without the trailer.
+    let mut corrupted = packet.clone();
+    let index = corrupted.windows(3).position(|window| window == b"Tom").unwrap();
+    corrupted[index + 1] = b'i';
+
+    assert!(matches!(NextPacket::open(Checksum::default(), &corrupted), Err(Error::ChecksumMismatch { .. })));
+    assert!(matches!(NextPacket::open(Checksum::default(), &packet[..4]), Err(Error::InvalidLength { .. })));
+
+    Ok(())
+}
+
+#[cfg(all(feature = "sync", feature = "crc32c"))]
+#[test]
+fn test_checksum_crc32c() -> Result<(), Error> {
+    let checksum = Checksum::new(ChecksumAlgorithm::Crc32c);
+    let animal = Animal::Frog("Frog".to_string(), vec![12393818, -19383812, 11111, -1093838482]);
+    let mut packet = encode(&animal, checksum)?;
+
+    let (body, trailer) = packet.split_at(packet.len() - 4);
+    assert_eq!(trailer, (ChecksumAlgorithm::Crc32c.checksum(body) as u32).to_be_bytes());
+
+    let mut next = NextPacket::open(checksum, &packet)?;
+    assert_eq!(Animal::from_packet(&mut (), &mut next)?, animal);
+
+    packet[0] ^= 0x10;
+    assert!(matches!(NextPacket::open(checksum, &packet), Err(Error::ChecksumMismatch { .. })));
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_checksum_async() -> Result<(), Error> {
+    let animal = Animal::AntHive(vec!["Queen".to_string(), "Worker".to_string()]);
+    let checksum = Checksum::new(ChecksumAlgorithm::Xxh3);
+
+    let mut next = NextPacket::new(checksum);
+    animal.poll_into_packet(&mut (), &mut next).await?;
+
+    let mut packet = next.seal()?;
+    let mut next = NextPacket::open(checksum, &packet)?;
+
+    assert_eq!(Animal::poll_from_packet(&mut (), &mut next).await?, animal);
+
+    let last = packet.len() - 1;
+    packet[last] ^= 0xff;
+    assert!(matches!(NextPacket::open(checksum, &packet), Err(Error::ChecksumMismatch { .. })));
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+#[cfg(feature = "sync")]
+use std::collections::HashMap;
+
+#[cfg(any(feature = "sync", feature = "async"))]
+use npsd::{Error, Compressor, NextPacket, PacketFilter};
+
+#[cfg(feature = "sync")]
+use npsd::{Compression, Payload};
+
+#[cfg(feature = "sync")]
+fn roundtrip<T: for<'a> Payload<'a, ()> + PartialEq + std::fmt::Debug>(value: &T, compressor: Compressor) -> Result<Vec<u8>, Error> {
+    let mut next = NextPacket::new(compressor.clone());
+    value.into_packet(&mut (), &mut next)?;
+
+    let packet = next.seal()?;
+    let mut next = NextPacket::open(compressor, &packet)?;
+
+    assert_eq!(&T::from_packet(&mut (), &mut next)?, value);
+
+    Ok(packet)
+}
+
+#[cfg(feature = "sync")]
+fn words() -> Vec<String> {
+    (0..200).map(|i| format!("word-{}", i % 10)).collect()
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_compress_algorithms() -> Result<(), Error> {
+    let words = words();
+    let uncompressed = roundtrip(&words, Compressor::new(Compression::None))?;
+
+    assert_eq!(uncompressed[0], Compression::None as u8);
+
+    let algorithms = [
+        #[cfg(feature = "lz4")]
+        Compression::Lz4,
+
+        #[cfg(feature = "zstd")]
+        Compression::Zstd,
+
+        #[cfg(feature = "deflate")]
+        Compression::Deflate,
+    ];
+
+    for algorithm in algorithms {
+        let packet = roundtrip(&words, Compressor::new(algorithm))?;
+
+        assert_eq!(packet[0], algorithm as u8);
+        assert!(packet.len() < uncompressed.len() / 2);
+
+        let map: HashMap<String, Vec<String>> = (0..50).map(|i| (format!("key-{}", i), words[..10].to_vec())).collect();
+        assert_eq!(roundtrip(&map, Compressor::new(algorithm))?[0], algorithm as u8);
+    }
+
+    Ok(())
+}
+
+#[cfg(all(feature = "sync", feature = "lz4"))]
+#[test]
+fn test_compress_threshold() -> Result<(), Error> {
+    let compressor = Compressor::new(Compression::Lz4).with_threshold(1024);
+
+    // Below the threshold.
+    let packet = roundtrip(&"word".repeat(64), compressor.clone())?;
+    assert_eq!(packet[0], Compression::None as u8);
+
+    // Above the threshold, but not compressible.
+    let noise: Vec<u8> = (0..256u64).flat_map(|i| xxhash_rust::xxh3::xxh3_64(&i.to_le_bytes()).to_le_bytes()).collect();
+    let packet = roundtrip(&noise, compressor.clone())?;
+    assert_eq!(packet[0], Compression::None as u8);
+
+    let packet = roundtrip(&"word".repeat(1024), compressor)?;
+    assert_eq!(packet[0], Compression::Lz4 as u8);
+
+    Ok(())
+}
+
+#[cfg(all(feature = "sync", feature = "lz4"))]
+#[test]
+fn test_compress_max_size() -> Result<(), Error> {
+    let mut next = NextPacket::new(Compressor::new(Compression::Lz4));
+    vec![0u8; 1 << 16].into_packet(&mut (), &mut next)?;
+
+    let packet = next.seal()?;
+    let result = NextPacket::open(Compressor::default().with_max_size(1 << 12), &packet);
+
+    assert!(matches!(result, Err(Error::Compression(_))));
+
+    Ok(())
+}
+
+#[cfg(all(feature = "sync", any(feature = "zstd", feature = "deflate")))]
+#[test]
+fn test_compress_corrupted() -> Result<(), Error> {
+    let algorithms = [
+        #[cfg(feature = "zstd")]
+        Compression::Zstd,
+
+        #[cfg(feature = "deflate")]
+        Compression::Deflate,
+    ];
+
+    for algorithm in algorithms {
+        let mut next = NextPacket::new(Compressor::new(algorithm));
+        words().into_packet(&mut (), &mut next)?;
+
+        let mut packet = next.seal()?;
+        let len = packet.len();
+        packet[len / 2..].iter_mut().for_each(|byte| *byte ^= 0x5a);
+
+        let result = NextPacket::open(Compressor::default(), &packet);
+        assert!(matches!(result, Err(Error::Compression(_))), "{:?}", algorithm);
+    }
+
+    Ok(())
+}
+
+#[cfg(any(feature = "sync", feature = "async"))]
+#[test]
+fn test_compress_unknown() {
+    assert!(matches!(Compressor::default().decode(&[0xff, 1, 2, 3]), Err(Error::Compression(_))));
+    assert!(matches!(Compressor::default().decode(&[]), Err(Error::InvalidLength { .. })));
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_compress_async() -> Result<(), Error> {
+    use npsd::AsyncPayload;
+
+    let words: Vec<String> = (0..200).map(|i| format!("word-{}", i % 10)).collect();
+
+    let mut next = NextPacket::new(Compressor::default());
+    words.poll_into_packet(&mut (), &mut next).await?;
+
+    let packet = next.seal()?;
+    let mut next = NextPacket::open(Compressor::default(), &packet)?;
+
+    assert_eq!(Vec::<String>::poll_from_packet(&mut (), &mut next).await?, words);
+
+    Ok(())
+}
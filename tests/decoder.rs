@@ -0,0 +1,128 @@
+#[cfg(feature = "sync")]
+use npsd::{Info, Schema, Payload, Error, Decoder, Next};
+
+#[cfg(feature = "sync")]
+#[derive(Schema, Info, PartialEq, Debug)]
+enum Animal {
+    Dog,
+    Frog(String, Vec<isize>),
+    Cat { age: usize, name: String },
+    AntHive(Vec<String>),
+}
+
+#[cfg(feature = "sync")]
+#[derive(Schema, Info, PartialEq, Debug)]
+#[npsd(tagged)]
+struct Profile {
+    name: String,
+}
+
+#[cfg(feature = "sync")]
+fn encode(animals: &[Animal]) -> Vec<Vec<u8>> {
+    animals.iter().map(|animal| {
+        let mut next = Next::default();
+        animal.into_packet(&mut (), &mut next).unwrap();
+        next.serialized()
+    }).collect()
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_decoder_partial() -> Result<(), Error> {
+    let animals = vec![
+        Animal::Frog("Frog".to_string(), vec![12393818, -19383812, 11111, -1093838482]),
+        Animal::Cat { age: 7, name: "Tom".to_string() },
+    ];
+
+    let packets = encode(&animals);
+    let mut decoder = Decoder::<Animal>::default();
+    let mut decoded = Vec::new();
+
+    for byte in packets.concat() {
+        decoder.feed(&[byte]);
+
+        if let Some(animal) = decoder.poll_decode()? {
+            decoded.push((animal, decoder.last_consumed()));
+        }
+    }
+
+    assert!(decoder.buffered().is_empty());
+    assert_eq!(decoded, vec![
+        (Animal::Frog("Frog".to_string(), vec![12393818, -19383812, 11111, -1093838482]), packets[0].len()),
+        (Animal::Cat { age: 7, name: "Tom".to_string() }, packets[1].len()),
+    ]);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_decoder_back_to_back() -> Result<(), Error> {
+    let animals = vec![
+        Animal::Dog,
+        Animal::AntHive(vec!["Queen".to_string(), "Worker".to_string()]),
+        Animal::Cat { age: 7, name: "Tom".to_string() },
+    ];
+
+    let packets = encode(&animals);
+    let bytes = packets.concat();
+
+    let mut decoder = Decoder::<Animal>::default();
+
+    // Everything but the last byte arrives in one receive buffer.
+    decoder.feed(&bytes[..bytes.len() - 1]);
+
+    assert_eq!(decoder.poll_decode()?, Some(Animal::Dog));
+    assert_eq!(decoder.last_consumed(), packets[0].len());
+    assert_eq!(decoder.poll_decode()?, Some(Animal::AntHive(vec!["Queen".to_string(), "Worker".to_string()])));
+    assert_eq!(decoder.last_consumed(), packets[1].len());
+    assert_eq!(decoder.poll_decode()?, None);
+    assert_eq!(decoder.buffered().len(), packets[2].len() - 1);
+
+    decoder.feed(&bytes[bytes.len() - 1..]);
+
+    assert_eq!(decoder.poll_decode()?, Some(Animal::Cat { age: 7, name: "Tom".to_string() }));
+    assert_eq!(decoder.poll_decode()?, None);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_decoder_corrupt() {
+    let mut decoder = Decoder::<Animal>::default();
+
+    // Variant index 42 does not exist, no amount of extra data will make this decodable.
+    decoder.feed(&[42]);
+    assert!(matches!(decoder.poll_decode().map_err(|e| e.kind().clone()), Err(Error::UnknownVariant(_))));
+
+    decoder.clear();
+
+    // A string claiming to be far larger than the limit is rejected as soon as its length is read.
+    let mut decoder = Decoder::<String>::with_max_message_size((), 16);
+    decoder.feed(&[0xff]);
+    assert_eq!(decoder.poll_decode(), Ok(None));
+
+    decoder.feed(&[0xff, 0x03]);
+    assert_eq!(decoder.poll_decode(), Err(Error::FrameTooLarge { size: 0xffff + 2 + 1, max: 16 }));
+
+    // A string running past the end of its tagged field is corrupt, however much data follows.
+    let mut next = Next::default();
+    Profile { name: "Tom".to_string() }.into_packet(&mut (), &mut next).unwrap();
+
+    let mut bytes = next.serialized();
+    let len = bytes.len();
+    bytes[len - 4] += 1;
+
+    let mut decoder = Decoder::<Profile>::default();
+    decoder.feed(&bytes);
+    assert!(matches!(decoder.poll_decode().map_err(|e| e.kind().clone()), Err(Error::OutOfRange(_))));
+
+    // Bytes that are merely missing are waited for.
+    let mut decoder = Decoder::<Profile>::default();
+    decoder.feed(&next.serialized()[..len - 1]);
+    assert_eq!(decoder.poll_decode(), Ok(None));
+
+    decoder.feed(&next.serialized()[len - 1..]);
+    assert_eq!(decoder.poll_decode(), Ok(Some(Profile { name: "Tom".to_string() })));
+}
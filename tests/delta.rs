@@ -0,0 +1,132 @@
+#![cfg(any(feature = "sync", feature = "async"))]
+
+use std::collections::HashMap;
+
+use npsd::{Error, Next};
+
+#[cfg(feature = "sync")]
+use npsd::{DeltaSchema, DeltaPayload};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncDeltaSchema, AsyncDeltaPayload};
+
+#[cfg_attr(feature = "async", derive(AsyncDeltaSchema))]
+#[cfg_attr(feature = "sync", derive(DeltaSchema))]
+#[derive(PartialEq, Debug, Clone)]
+struct Position {
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncDeltaSchema))]
+#[cfg_attr(feature = "sync", derive(DeltaSchema))]
+#[derive(PartialEq, Debug, Clone)]
+struct Player {
+    id: u32,
+    name: String,
+    health: u8,
+    alive: bool,
+    #[npsd(delta)]
+    position: Position,
+    inventory: Vec<u16>,
+    stats: HashMap<String, u32>,
+    #[npsd(skip)]
+    cached: Option<u64>,
+}
+
+fn player() -> Player {
+    Player {
+        id: 7,
+        name: "ranger".to_string(),
+        health: 100,
+        alive: true,
+        position: Position { x: 10, y: 20, z: 30 },
+        inventory: vec![1, 2, 3],
+        stats: HashMap::from([("kills".to_string(), 3)]),
+        cached: None,
+    }
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_delta_changed_fields() -> Result<(), Error> {
+    let baseline = player();
+
+    // Nothing changed, the delta is an empty change mask.
+    let mut next = Next::default();
+    baseline.encode_delta(&baseline, &mut (), &mut next)?;
+    assert_eq!(next.serialized(), [0]);
+
+    let mut value = baseline.clone();
+    value.health = 80;
+    value.position.y = -1;
+
+    // Bits 2 and 4 of the mask, the health, then the position's own mask and its `y`.
+    let mut next = Next::default();
+    value.encode_delta(&baseline, &mut (), &mut next)?;
+    assert_eq!(next.serialized(), [0b1_0100, 80, 0b010, 0xff, 0xff, 0xff, 0xff]);
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let mut applied = baseline.clone();
+    applied.apply_delta(&mut (), &mut next)?;
+    assert_eq!(applied, value);
+
+    // The position's mask says its `y` changed, but the bytes end there.
+    let mut next = Next::from(&bytes[..3]);
+    let error = baseline.clone().apply_delta(&mut (), &mut next).unwrap_err();
+    assert_eq!(error.kind(), &Error::InvalidLength { expected: 4, found: 0 });
+    assert_eq!(error.path(), Some(&["Player", "position", "Position", "y"].map(String::from)[..]));
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_delta_collections() -> Result<(), Error> {
+    let baseline = player();
+
+    let mut value = baseline.clone();
+    value.name = "scout".to_string();
+    value.inventory.push(4);
+    value.stats.insert("deaths".to_string(), 1);
+    value.cached = Some(9);
+
+    let mut next = Next::default();
+    value.encode_delta(&baseline, &mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    assert_eq!(bytes[0], 0b110_0010);
+
+    let mut next = Next::from(bytes.as_slice());
+    let mut applied = baseline.clone();
+    applied.apply_delta(&mut (), &mut next)?;
+
+    // Collections are written whole, skipped fields keep the baseline's value.
+    assert_eq!(applied, Player { cached: None, ..value });
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_delta() -> Result<(), Error> {
+    let baseline = player();
+
+    let mut value = baseline.clone();
+    value.alive = false;
+    value.position.z += 5;
+    value.inventory.clear();
+
+    let mut next = Next::default();
+    value.poll_encode_delta(&baseline, &mut (), &mut next).await?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let mut applied = baseline.clone();
+    applied.poll_apply_delta(&mut (), &mut next).await?;
+    assert_eq!(applied, value);
+
+    Ok(())
+}
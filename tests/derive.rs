@@ -1,3 +1,5 @@
+#![cfg(any(feature = "sync", feature = "async"))]
+
 use std::{collections::{HashMap, HashSet}, net::SocketAddr};
 
 use npsd::Info;
@@ -150,6 +152,7 @@ struct TestStruct<'a> {
     field_name18: GenericStruct<String>,
     field_name19: Flags,
     field_name20: MapSet,
+    field_name21: TupleStruct,
 }
 
 #[cfg(feature = "sync")]
@@ -237,6 +240,7 @@ fn test_schema() {
                 "Bob".to_string(),
             ]),
         },
+        field_name21: TupleStruct(0x1337, 42, "Teppo".to_string()),
     };
 
     instance.into_packet(&mut ctx, &mut next).unwrap();
@@ -335,6 +339,7 @@ async fn test_tokio_schema() {
                 "Bob".to_string(),
             ]),
         },
+        field_name21: TupleStruct(0x1337, 42, "Teppo".to_string()),
     };
 
     instance.poll_into_packet(&mut ctx, &mut next).await.unwrap();
@@ -434,6 +439,7 @@ async fn test_async_std_schema() {
                 "Bob".to_string(),
             ]),
         },
+        field_name21: TupleStruct(0x1337, 42, "Teppo".to_string()),
     };
 
     instance.poll_into_packet(&mut ctx, &mut next).await.unwrap();
@@ -0,0 +1,129 @@
+#![cfg(any(feature = "sync", feature = "async"))]
+
+use npsd::{Info, Error, Next};
+
+#[cfg(feature = "sync")]
+use npsd::{Schema, Payload};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncSchema, AsyncPayload};
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, Clone, PartialEq, Debug)]
+enum Animal {
+    Dog,
+    Frog(String, Vec<isize>),
+    Cat { age: usize, name: String },
+    AntHive(Vec<String>),
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, Clone, PartialEq, Debug)]
+struct Zoo {
+    keeper: String,
+    animals: Vec<Animal>,
+}
+
+#[cfg(feature = "sync")]
+fn corrupt_zoo() -> (Vec<u8>, usize) {
+    let zoo = Zoo {
+        keeper: "Bob".to_string(),
+        animals: vec![Animal::Dog, Animal::Cat { age: 7, name: "Tom".to_string() }],
+    };
+
+    let mut next = Next::default();
+    zoo.into_packet(&mut (), &mut next).unwrap();
+
+    let mut bytes = next.serialized();
+    let name = bytes.windows(3).position(|window| window == b"Tom").unwrap();
+    bytes[name] = 0xFF;
+
+    (bytes, name + 3)
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_error_location() {
+    let (bytes, offset) = corrupt_zoo();
+    let error = Zoo::from_packet(&mut (), &mut Next::from(bytes.as_slice())).unwrap_err();
+
+    assert_eq!(error.offset(), Some(offset));
+    assert_eq!(error.path(), Some(&["Zoo", "animals", "Animal::Cat", "name"].map(String::from)[..]));
+    assert!(matches!(error.kind(), Error::InvalidUtf8(_)));
+    assert!(error.to_string().contains(&format!("at byte `{}` in `Zoo.animals.Animal::Cat.name`", offset)));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_error_location_truncated() {
+    let mut next = Next::default();
+    Animal::Frog("Frog".to_string(), vec![1, 2, 3]).into_packet(&mut (), &mut next).unwrap();
+
+    let bytes = next.serialized();
+    let error = Animal::from_packet(&mut (), &mut Next::from(&bytes[..bytes.len() - 1])).unwrap_err();
+
+    assert_eq!(error.path(), Some(&["Animal::Frog", "1"].map(String::from)[..]));
+    assert!(matches!(error.kind(), Error::InvalidLength { .. }));
+
+    let error = Animal::from_packet(&mut (), &mut Next::from(&[9u8][..])).unwrap_err();
+
+    assert_eq!(error.offset(), Some(1));
+    assert_eq!(error.path(), Some(&["Animal".to_string()][..]));
+    assert!(matches!(error.kind(), Error::UnknownVariant(_)));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_error_location_stream() {
+    use npsd::NextReader;
+
+    let (bytes, offset) = corrupt_zoo();
+    let error = Zoo::from_packet(&mut (), &mut NextReader::new(bytes.as_slice())).unwrap_err();
+
+    assert_eq!(error.offset(), Some(offset));
+    assert_eq!(error.path(), Some(&["Zoo", "animals", "Animal::Cat", "name"].map(String::from)[..]));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_error_payload() -> Result<(), Error> {
+    let (bytes, _) = corrupt_zoo();
+    let error = Zoo::from_packet(&mut (), &mut Next::from(bytes.as_slice())).unwrap_err();
+
+    let mut next = Next::default();
+    error.into_packet(&mut (), &mut next)?;
+
+    assert_eq!(Error::from_packet(&mut (), &mut next)?, error);
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_error_location_async() -> Result<(), Error> {
+    let zoo = Zoo {
+        keeper: "Bob".to_string(),
+        animals: vec![Animal::Cat { age: 7, name: "Tom".to_string() }],
+    };
+
+    let mut next = Next::default();
+    zoo.poll_into_packet(&mut (), &mut next).await?;
+
+    let mut bytes = next.serialized();
+    let name = bytes.windows(3).position(|window| window == b"Tom").unwrap();
+    bytes[name] = 0xFF;
+
+    let error = Zoo::poll_from_packet(&mut (), &mut Next::from(bytes.as_slice())).await.unwrap_err();
+
+    assert_eq!(error.offset(), Some(name + 3));
+    assert_eq!(error.path(), Some(&["Zoo", "animals", "Animal::Cat", "name"].map(String::from)[..]));
+
+    let mut next = Next::default();
+    error.poll_into_packet(&mut (), &mut next).await?;
+
+    assert_eq!(Error::poll_from_packet(&mut (), &mut next).await?, error);
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+#[cfg(feature = "sync")]
+use std::{thread, time::Duration};
+
+#[cfg(feature = "sync")]
+use npsd::{Error, Info, Schema, Fragmenter, FragmentHeader, Reassembler, FRAGMENT_HEADER_LEN};
+
+#[cfg(feature = "sync")]
+#[derive(Schema, Info, Clone, PartialEq, Debug)]
+enum Animal {
+    Dog,
+    Frog(String, Vec<isize>),
+    Cat { age: usize, name: String },
+    AntHive(Vec<String>),
+}
+
+#[cfg(feature = "sync")]
+fn hive() -> Animal {
+    Animal::AntHive((0..100).map(|i| format!("Worker {}", i)).collect())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_fragment_mtu() -> Result<(), Error> {
+    let mut fragmenter = Fragmenter::new(64);
+    let fragments = fragmenter.encode(&hive(), &mut ())?;
+
+    assert!(fragments.len() > 1);
+    assert!(fragments.iter().all(|fragment| fragment.len() <= 64));
+
+    for (i, fragment) in fragments.iter().enumerate() {
+        let (header, _) = FragmentHeader::parse(fragment)?;
+        assert_eq!(header, FragmentHeader { message_id: 0, index: i as u16, count: fragments.len() as u16 });
+    }
+
+    // Every message gets its own id, even a single empty fragment.
+    let fragments = fragmenter.encode(&Animal::Dog, &mut ())?;
+    assert_eq!(fragments.len(), 1);
+    assert_eq!(FragmentHeader::parse(&fragments[0])?.0.message_id, 1);
+
+    let fragments = fragmenter.fragment(&[])?;
+    assert_eq!(fragments, vec![vec![0, 0, 0, 2, 0, 0, 0, 1]]);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_fragment_reorder() -> Result<(), Error> {
+    let mut fragmenter = Fragmenter::new(FRAGMENT_HEADER_LEN + 16);
+    let mut reassembler = Reassembler::new();
+
+    let first = fragmenter.encode(&hive(), &mut ())?;
+    let second = fragmenter.encode(&Animal::Cat { age: 7, name: "Tom".to_string() }, &mut ())?;
+
+    // Interleaved, reversed and duplicated.
+    let mut decoded = Vec::new();
+
+    for (a, b) in first.iter().rev().zip(second.iter().chain(second.iter()).cycle()) {
+        for fragment in [a, a, b] {
+            if let Some(animal) = reassembler.decode::<(), Animal>(fragment, &mut ())? {
+                decoded.push(animal);
+            }
+        }
+    }
+
+    assert_eq!(decoded, vec![Animal::Cat { age: 7, name: "Tom".to_string() }, hive()]);
+    assert_eq!(reassembler.pending(), 0);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_fragment_timeout() -> Result<(), Error> {
+    let mut fragmenter = Fragmenter::new(32);
+    let mut reassembler = Reassembler::new().with_timeout(Duration::from_millis(20));
+
+    let fragments = fragmenter.encode(&hive(), &mut ())?;
+    assert_eq!(reassembler.push(&fragments[0])?, None);
+
+    thread::sleep(Duration::from_millis(40));
+    assert_eq!(reassembler.expire(), 1);
+
+    // The rest arrives too late to complete the message.
+    for fragment in &fragments[1..] {
+        assert_eq!(reassembler.push(fragment)?, None);
+    }
+
+    assert_eq!(reassembler.pending(), 1);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_fragment_invalid() -> Result<(), Error> {
+    let mut fragmenter = Fragmenter::new(32);
+    let mut reassembler = Reassembler::new().with_max_message_size(64).with_max_pending(1);
+
+    assert!(matches!(reassembler.push(&[0, 0, 0]), Err(Error::InvalidLength { .. })));
+    assert!(matches!(reassembler.push(&[0, 0, 0, 0, 0, 1, 0, 1]), Err(Error::Fragment(_))));
+    assert!(matches!(reassembler.push(&[0, 0, 0, 0, 0, 0, 0, 0]), Err(Error::Fragment(_))));
+
+    // More fragments than the message could have bytes.
+    assert!(matches!(reassembler.push(&[0, 0, 0, 0, 0, 0, 0xff, 0xff]), Err(Error::FrameTooLarge { size: 65535, max: 64 })));
+    assert_eq!(reassembler.pending(), 0);
+
+    let fragments = fragmenter.encode(&hive(), &mut ())?;
+    let result = fragments.iter().try_for_each(|fragment| reassembler.push(fragment).map(|_| ()));
+    assert!(matches!(result, Err(Error::FrameTooLarge { .. })));
+
+    // Only the newest message is kept.
+    let first = fragmenter.encode(&hive(), &mut ())?;
+    let second = fragmenter.encode(&hive(), &mut ())?;
+
+    reassembler.push(&first[0])?;
+    reassembler.push(&second[0])?;
+    assert_eq!(reassembler.pending(), 1);
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+#[cfg(feature = "sync")]
+use npsd::{Info, Schema, Error, FramedReader, FramedWriter};
+
+#[cfg(feature = "sync")]
+#[derive(Schema, Info, PartialEq, Debug)]
+enum Animal {
+    Dog,
+    Frog(String, Vec<isize>),
+    Cat { age: usize, name: String },
+    AntHive(Vec<String>),
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_framed_io() -> Result<(), Error> {
+    use std::io::Cursor;
+
+    let animals = vec![
+        Animal::Dog,
+        Animal::Frog("Frog".to_string(), vec![12393818, -19383812, 11111, -1093838482]),
+        Animal::Cat { age: 7, name: "Tom".to_string() },
+        Animal::AntHive(vec!["Queen".to_string(); 100]),
+    ];
+
+    let mut writer = FramedWriter::new(Vec::new());
+
+    for animal in &animals {
+        writer.write(animal, &mut ())?;
+    }
+
+    let mut reader = FramedReader::new(Cursor::new(writer.into_inner()));
+
+    for animal in &animals {
+        assert_eq!(&reader.read::<(), Animal>(&mut ())?, animal);
+    }
+
+    assert!(matches!(reader.read::<(), Animal>(&mut ()), Err(Error::Io(_))));
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_framed_max_frame_size() {
+    use std::io::Cursor;
+
+    let animal = Animal::AntHive(vec!["Queen".to_string(); 100]);
+
+    let mut writer = FramedWriter::with_max_frame_size(Vec::new(), 64);
+    assert!(matches!(writer.write(&animal, &mut ()), Err(Error::FrameTooLarge { max: 64, .. })));
+    assert!(writer.get_ref().is_empty());
+
+    let mut writer = FramedWriter::new(Vec::new());
+    writer.write(&animal, &mut ()).unwrap();
+
+    let mut reader = FramedReader::with_max_frame_size(Cursor::new(writer.into_inner()), 64);
+    assert!(matches!(reader.read::<(), Animal>(&mut ()), Err(Error::FrameTooLarge { max: 64, .. })));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_framed_trailing_bytes() {
+    use std::io::Cursor;
+    use npsd::{decode_frame, Next, Payload};
+
+    let mut next = Next::default();
+    Animal::Cat { age: 7, name: "Tom".to_string() }.into_packet(&mut (), &mut next).unwrap();
+
+    let mut body = next.serialized();
+    body.push(0);
+
+    let error = Error::InvalidLength { expected: body.len() - 1, found: body.len() };
+    assert_eq!(decode_frame::<(), Animal>(&body, &mut ()), Err(error.clone()));
+
+    let mut frame = vec![body.len() as u8];
+    frame.extend_from_slice(&body);
+
+    let mut reader = FramedReader::new(Cursor::new(frame));
+    assert_eq!(reader.read::<(), Animal>(&mut ()), Err(error));
+}
+
+#[cfg(feature = "codec")]
+#[test]
+fn test_payload_codec() -> Result<(), Error> {
+    use bytes::BytesMut;
+    use npsd::PayloadCodec;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    let mut codec = PayloadCodec::<Animal, ()>::default();
+    let mut encoded = BytesMut::new();
+
+    codec.encode(Animal::Cat { age: 7, name: "Tom".to_string() }, &mut encoded)?;
+    codec.encode(Animal::AntHive(vec!["Queen".to_string(); 100]), &mut encoded)?;
+
+    // Feed the frames one byte at a time, nothing may be decoded before a frame is complete.
+    let mut src = BytesMut::new();
+    let mut decoded = Vec::new();
+
+    for byte in encoded.iter() {
+        src.extend_from_slice(&[*byte]);
+
+        if let Some(animal) = codec.decode(&mut src)? {
+            decoded.push(animal);
+        }
+    }
+
+    assert!(src.is_empty());
+    assert_eq!(decoded, vec![
+        Animal::Cat { age: 7, name: "Tom".to_string() },
+        Animal::AntHive(vec!["Queen".to_string(); 100]),
+    ]);
+
+    Ok(())
+}
+
+#[cfg(feature = "codec")]
+#[test]
+fn test_payload_codec_max_frame_size() {
+    use bytes::BytesMut;
+    use npsd::PayloadCodec;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    let mut encoded = BytesMut::new();
+    PayloadCodec::<Animal, ()>::default().encode(Animal::AntHive(vec!["Queen".to_string(); 100]), &mut encoded).unwrap();
+
+    let mut codec = PayloadCodec::<Animal, ()>::with_max_frame_size((), 64);
+    assert!(matches!(codec.encode(Animal::AntHive(vec!["Queen".to_string(); 100]), &mut BytesMut::new()), Err(Error::FrameTooLarge { max: 64, .. })));
+
+    // The frame is rejected as soon as the length prefix is known.
+    let mut src = BytesMut::from(&encoded[..2]);
+    assert!(matches!(codec.decode(&mut src), Err(Error::FrameTooLarge { max: 64, .. })));
+}
@@ -0,0 +1,154 @@
+#![cfg(any(feature = "sync", feature = "async"))]
+
+use npsd::{Error, Info, Limits, LimitsLayer, MetricsLayer, ChecksumLayer, MiddlewareBuilder, Next};
+
+#[cfg(feature = "sync")]
+use npsd::{Schema, Payload, TraceLayer, CollectSink, MiddlewareLayer, TraceEvent};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncSchema, AsyncPayload};
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, Clone, PartialEq, Debug)]
+enum Animal {
+    Dog,
+    Frog(String, Vec<isize>),
+    Cat { age: usize, name: String },
+    AntHive(Vec<String>),
+}
+
+fn animals() -> Vec<Animal> {
+    vec![
+        Animal::Dog,
+        Animal::Frog("Frog".to_string(), vec![12393818, -19383812, 11111, -1093838482]),
+        Animal::Cat { age: 7, name: "Tom".to_string() },
+        Animal::AntHive(vec!["Queen".to_string(), "Worker".to_string()]),
+    ]
+}
+
+/// Records the order in which its hooks are called.
+#[cfg(feature = "sync")]
+struct Record<'a>(&'static str, &'a std::sync::Mutex<Vec<String>>);
+
+#[cfg(feature = "sync")]
+impl MiddlewareLayer for Record<'_> {
+    fn enter(&mut self, event: &TraceEvent) -> Result<(), Error> {
+        if event.depth == 0 {
+            self.1.lock().unwrap().push(format!("{} enter", self.0));
+        }
+
+        Ok(())
+    }
+
+    fn leave(&mut self, event: &TraceEvent, _nbytes: usize, error: Option<&Error>) {
+        if event.depth == 0 {
+            self.1.lock().unwrap().push(format!("{} leave {}", self.0, error.is_some()));
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_layer_stack() -> Result<(), Error> {
+    let animals = animals();
+
+    let mut metrics = MetricsLayer::default();
+    let mut checksum = ChecksumLayer::new();
+
+    let mut next = MiddlewareBuilder::new(Next::default())
+        .layer(LimitsLayer::new(Limits::default()))
+        .layer(&mut metrics)
+        .layer(&mut checksum)
+        .layer(TraceLayer::new(CollectSink::new()))
+        .build();
+
+    animals.into_packet(&mut (), &mut next)?;
+
+    let (layers, inner) = next.into_parts();
+    let bytes = inner.serialized();
+    let roots = layers.1.into_sink().into_roots();
+
+    assert_eq!(roots.len(), 1);
+    assert_eq!((roots[0].offset, roots[0].nbytes), (0, bytes.len()));
+
+    assert_eq!(metrics.bytes_written(), bytes.len());
+    assert_eq!(metrics.errors(), 0);
+    assert!(metrics.encoded() > animals.len());
+    assert!(metrics.max_depth() > 2);
+
+    let mut next = MiddlewareBuilder::new(Next::from(bytes.as_slice()))
+        .layer(LimitsLayer::new(Limits::default()))
+        .layer(&mut metrics)
+        .layer(&mut checksum)
+        .build();
+
+    assert_eq!(Vec::<Animal>::from_packet(&mut (), &mut next)?, animals);
+    drop(next);
+
+    assert_eq!(metrics.bytes_read(), bytes.len());
+    assert!(metrics.decoded() > animals.len());
+    assert_eq!(checksum.read_digest(), checksum.written_digest());
+    assert_eq!(checksum.written_digest(), xxhash_rust::xxh3::xxh3_64(&bytes));
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_layer_order() -> Result<(), Error> {
+    let calls = std::sync::Mutex::new(Vec::new());
+
+    let mut next = MiddlewareBuilder::new(Next::default())
+        .layer(Record("outer", &calls))
+        .layer(LimitsLayer::new(Limits { max_depth: 0, ..Limits::default() }))
+        .layer(Record("inner", &calls))
+        .build();
+
+    let result = Animal::Dog.into_packet(&mut (), &mut next);
+
+    assert!(matches!(result, Err(Error::LimitExceeded { .. })));
+    assert_eq!(*calls.lock().unwrap(), vec!["outer enter", "outer leave true"]);
+
+    calls.lock().unwrap().clear();
+    next.layer_mut().0.1.limits_mut().max_depth = 8;
+
+    Animal::Dog.into_packet(&mut (), &mut next)?;
+    assert_eq!(*calls.lock().unwrap(), vec!["outer enter", "inner enter", "inner leave false", "outer leave false"]);
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_layer_async() -> Result<(), Error> {
+    let animals = animals();
+
+    let mut metrics = MetricsLayer::default();
+    let mut checksum = ChecksumLayer::new();
+
+    let mut next = MiddlewareBuilder::new(Next::default())
+        .layer(LimitsLayer::new(Limits::default()))
+        .layer(&mut metrics)
+        .layer(&mut checksum)
+        .build();
+
+    animals.poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.into_inner().serialized();
+
+    let mut next = MiddlewareBuilder::new(Next::from(bytes.as_slice()))
+        .layer(LimitsLayer::new(Limits::default()))
+        .layer(&mut metrics)
+        .layer(&mut checksum)
+        .build();
+
+    assert_eq!(Vec::<Animal>::poll_from_packet(&mut (), &mut next).await?, animals);
+    drop(next);
+
+    assert_eq!((metrics.bytes_written(), metrics.bytes_read()), (bytes.len(), bytes.len()));
+    assert!(metrics.decoded() > animals.len());
+    assert_eq!(checksum.read_digest(), checksum.written_digest());
+
+    Ok(())
+}
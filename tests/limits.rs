@@ -0,0 +1,235 @@
+#[cfg(feature = "sync")]
+use std::collections::HashMap;
+
+#[cfg(feature = "sync")]
+use npsd::{Info, Schema, Payload};
+
+#[cfg(any(feature = "sync", feature = "async"))]
+use npsd::{Error, Limits, Next, NextLimits};
+
+#[cfg(feature = "sync")]
+#[derive(Schema, Info, Clone, PartialEq, Debug)]
+enum Animal {
+    Dog,
+    Frog(String, Vec<isize>),
+    Cat { age: usize, name: String },
+    AntHive(Vec<String>),
+}
+
+#[cfg(feature = "sync")]
+#[derive(Schema, Info, PartialEq, Debug)]
+#[npsd(tagged)]
+struct Tagged {
+    items: Vec<u64>,
+}
+
+#[cfg(feature = "sync")]
+#[derive(Schema, Info, PartialEq, Debug)]
+#[npsd(open, tag_type = u8)]
+enum Open {
+    Items(Vec<u64>),
+    #[npsd(other)]
+    Unknown(u8, Vec<u8>),
+}
+
+#[cfg(feature = "sync")]
+#[derive(Schema, Info, Clone, PartialEq, Debug)]
+enum PlainTree {
+    Leaf,
+    Node(Box<PlainTree>),
+}
+
+#[cfg(feature = "sync")]
+#[derive(Schema, Info, Clone, PartialEq, Debug)]
+#[npsd(open)]
+enum OpenTree {
+    Leaf,
+    Node(Box<OpenTree>),
+}
+
+#[cfg(feature = "sync")]
+fn encode<T: for<'a> Payload<'a, ()>>(value: &T) -> Vec<u8> {
+    let mut next = Next::default();
+    value.into_packet(&mut (), &mut next).unwrap();
+    next.serialized()
+}
+
+#[cfg(feature = "sync")]
+fn decode<'a, T: Payload<'a, ()>>(bytes: &'a [u8], limits: Limits) -> Result<T, Error> {
+    T::from_packet(&mut (), &mut NextLimits::new(Next::from(bytes), limits))
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_limits_roundtrip() -> Result<(), Error> {
+    let animals = vec![
+        Animal::Dog,
+        Animal::Frog("Frog".to_string(), vec![12393818, -19383812, 11111, -1093838482]),
+        Animal::Cat { age: 7, name: "Tom".to_string() },
+        Animal::AntHive(vec!["Queen".to_string(), "Worker".to_string()]),
+    ];
+
+    let mut next = NextLimits::new(Next::default(), Limits::default());
+    animals.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.get_ref().serialized();
+    assert_eq!(decode::<Vec<Animal>>(&bytes, Limits::default())?, animals);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_limits_collection_length() {
+    // Only the length prefix is sent, the decoder must refuse before allocating.
+    let bytes = encode(&(1usize << 40));
+
+    assert_eq!(decode::<Vec<u64>>(&bytes, Limits::default()), Err(Error::LimitExceeded {
+        limit: "collection length".to_string(),
+        found: 1 << 40,
+        max: Limits::default().max_len,
+    }));
+
+    assert!(matches!(decode::<HashMap<u32, u32>>(&bytes, Limits::default()), Err(Error::LimitExceeded { .. })));
+    assert!(matches!(decode::<std::collections::VecDeque<u32>>(&bytes, Limits::default()), Err(Error::LimitExceeded { .. })));
+    assert!(matches!(decode::<std::borrow::Cow<[u32]>>(&bytes, Limits::default()), Err(Error::LimitExceeded { .. })));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_limits_tagged() {
+    let limits = Limits { max_len: 16, max_alloc: 1024, ..Limits::default() };
+
+    // Tagged fields are decoded in place, through the same limits as any other field.
+    let bytes = encode(&Tagged { items: (0..100).collect() });
+    let error = decode::<Tagged>(&bytes, limits).unwrap_err();
+
+    assert_eq!(error.kind(), &Error::LimitExceeded { limit: "collection length".to_string(), found: 100, max: 16 });
+    assert_eq!(error.path(), Some(&["Tagged", "items"].map(String::from)[..]));
+
+    // Without limits, a length still can't go past the bytes of its field. One field with id 0,
+    // whose bytes only hold the length of `items`.
+    let bytes = encode(&(1usize, 0usize, encode(&(1usize << 40))));
+    let error = Tagged::from_packet(&mut (), &mut Next::from(bytes.as_slice())).unwrap_err();
+
+    assert_eq!(error.kind(), &Error::OutOfRange("Length `1099511627776` is over the `0` bytes left in the part".to_string()));
+    assert_eq!(error.path(), Some(&["Tagged", "items"].map(String::from)[..]));
+
+    let bytes = encode(&Tagged { items: vec![1, 2, 3] });
+    assert_eq!(decode::<Tagged>(&bytes, limits), Ok(Tagged { items: vec![1, 2, 3] }));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_limits_open_enum() {
+    // The body of `Items` only holds the length of its `Vec`.
+    let bytes = encode(&(0u8, encode(&(1usize << 40))));
+    let limits = Limits { max_len: 16, max_alloc: 1024, ..Limits::default() };
+
+    let error = decode::<Open>(&bytes, limits).unwrap_err();
+    assert_eq!(error.kind(), &Error::LimitExceeded { limit: "collection length".to_string(), found: 1 << 40, max: 16 });
+    assert_eq!(error.path(), Some(&["Open::Items", "0"].map(String::from)[..]));
+
+    let bytes = encode(&Open::Items(vec![7; 16]));
+    assert_eq!(decode::<Open>(&bytes, limits), Ok(Open::Items(vec![7; 16])));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_limits_open_enum_depth() {
+    let plain = (0..20).fold(PlainTree::Leaf, |tree, _| PlainTree::Node(Box::new(tree)));
+    let open = (0..20).fold(OpenTree::Leaf, |tree, _| OpenTree::Node(Box::new(tree)));
+    let limits = Limits { max_depth: 8, ..Limits::default() };
+
+    let is_too_deep = |error: &Error| matches!(error.kind(), Error::LimitExceeded { limit, found: 9, max: 8 } if limit == "nesting depth");
+
+    // Open enum bodies nest through the outer middleware like any other value.
+    let bytes = encode(&plain);
+    assert!(is_too_deep(&decode::<PlainTree>(&bytes, limits).unwrap_err()));
+    assert_eq!(decode::<PlainTree>(&bytes, Limits::default()), Ok(plain));
+
+    let bytes = encode(&open);
+    assert!(is_too_deep(&decode::<OpenTree>(&bytes, limits).unwrap_err()));
+    assert_eq!(decode::<OpenTree>(&bytes, Limits::default()), Ok(open));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_limits_byte_length() {
+    let bytes = encode(&"x".repeat(64));
+    let limits = Limits { max_bytes: 32, ..Limits::default() };
+
+    assert_eq!(decode::<String>(&bytes, limits), Err(Error::LimitExceeded {
+        limit: "byte length".to_string(),
+        found: 64,
+        max: 32,
+    }));
+
+    assert!(matches!(decode::<&str>(&bytes, limits), Err(Error::LimitExceeded { .. })));
+    assert!(matches!(decode::<Vec<u8>>(&bytes, limits), Err(Error::LimitExceeded { .. })));
+    assert_eq!(decode::<String>(&bytes, Limits::default()), Ok("x".repeat(64)));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_limits_allocated_bytes() {
+    // Every string fits on its own, but the message as a whole goes over budget.
+    let hive = Animal::AntHive(vec!["x".repeat(40); 4]);
+    let bytes = encode(&hive);
+
+    let limits = Limits { max_bytes: 64, max_alloc: 128, ..Limits::default() };
+    let error = decode::<Animal>(&bytes, limits).unwrap_err();
+    assert!(matches!(error.kind(), Error::LimitExceeded { limit, .. } if limit == "allocated bytes"));
+
+    // The budget is per message, so decoding the same message twice is fine.
+    let limits = Limits { max_alloc: 512, ..Limits::default() };
+    let mut next = NextLimits::new(Next::from(bytes.as_slice()), limits);
+
+    assert_eq!(Animal::from_packet(&mut (), &mut next), Ok(hive));
+    assert!(next.allocated() <= 512);
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_limits_depth() {
+    let bytes = encode(&vec![vec![vec![1u32]]]);
+    let limits = Limits { max_depth: 3, ..Limits::default() };
+
+    assert!(matches!(decode::<Vec<Vec<Vec<u32>>>>(&bytes, limits), Err(Error::LimitExceeded { ref limit, .. }) if limit == "nesting depth"));
+    assert_eq!(decode::<Vec<Vec<Vec<u32>>>>(&bytes, Limits::default()), Ok(vec![vec![vec![1u32]]]));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_limits_stream() -> Result<(), Error> {
+    use npsd::NextReader;
+
+    let bytes = encode(&(1usize << 40));
+    let mut next = NextLimits::new(NextReader::new(bytes.as_slice()), Limits::default());
+
+    assert!(matches!(Vec::<u8>::from_packet(&mut (), &mut next), Err(Error::LimitExceeded { .. })));
+
+    let bytes = encode(&Animal::Cat { age: 3, name: "Felix".to_string() });
+    let mut next = NextLimits::new(NextReader::new(bytes.as_slice()), Limits::default());
+
+    assert_eq!(Animal::from_packet(&mut (), &mut next)?, Animal::Cat { age: 3, name: "Felix".to_string() });
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_limits_async() {
+    use npsd::AsyncPayload;
+
+    let bytes = {
+        let mut next = Next::default();
+        (1usize << 40).poll_into_packet(&mut (), &mut next).await.unwrap();
+        next.serialized()
+    };
+
+    let mut next = NextLimits::new(Next::from(bytes.as_slice()), Limits::default());
+    let result = Vec::<String>::poll_from_packet(&mut (), &mut next).await;
+
+    assert!(matches!(result, Err(Error::LimitExceeded { .. })));
+}
@@ -0,0 +1,178 @@
+#![cfg(feature = "sync")]
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use npsd::{patch, Diff, Error, Info, Next, Op, Patch, Payload, Schema, Segment};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncPayload, AsyncSchema};
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[derive(Schema, Diff, Info, PartialEq, Debug, Clone)]
+enum Mode {
+    Off,
+    On(u8),
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[derive(Schema, Diff, Info, PartialEq, Debug, Clone)]
+struct Limits {
+    max: u32,
+    burst: Option<u32>,
+}
+
+#[derive(Diff, PartialEq, Debug, Clone)]
+struct Config {
+    name: String,
+    mode: Mode,
+    #[npsd(skip)]
+    revision: u64,
+    limits: Limits,
+    routes: HashMap<String, Vec<u16>>,
+    tags: BTreeSet<String>,
+    hosts: BTreeMap<String, Limits>,
+}
+
+fn config() -> Config {
+    Config {
+        name: "edge".to_string(),
+        mode: Mode::Off,
+        revision: 1,
+        limits: Limits { max: 100, burst: None },
+        routes: HashMap::from([
+            ("api".to_string(), vec![80, 443]),
+            ("admin".to_string(), vec![8080]),
+        ]),
+        tags: BTreeSet::from(["eu".to_string(), "prod".to_string()]),
+        hosts: BTreeMap::from([
+            ("a".to_string(), Limits { max: 10, burst: Some(20) }),
+            ("b".to_string(), Limits { max: 30, burst: None }),
+        ]),
+    }
+}
+
+fn key(key: &str) -> Result<Vec<u8>, Error> {
+    patch::encode(&key.to_string(), &mut ())
+}
+
+#[test]
+fn test_patch_ops() -> Result<(), Error> {
+    let old = config();
+    assert!(Patch::diff(&old, &old.clone(), &mut ())?.is_empty());
+
+    let mut new = old.clone();
+    new.mode = Mode::On(3);
+    new.revision = 2;
+    new.limits.burst = Some(5);
+    new.tags.remove("eu");
+    new.tags.insert("us".to_string());
+    new.hosts.get_mut("a").unwrap().max = 11;
+    new.hosts.remove("b");
+    new.hosts.insert("c".to_string(), Limits { max: 1, burst: None });
+
+    let patch = Patch::diff(&old, &new, &mut ())?;
+
+    assert_eq!(patch.ops(), [
+        Op::Set { path: vec![Segment::Field(1)], value: patch::encode(&Mode::On(3), &mut ())? },
+        Op::Set { path: vec![Segment::Field(2), Segment::Field(1)], value: patch::encode(&Some(5u32), &mut ())? },
+        Op::Remove { path: vec![Segment::Field(4)], key: key("eu")? },
+        Op::Insert { path: vec![Segment::Field(4)], key: key("us")?, value: vec![] },
+        Op::Set { path: vec![Segment::Field(5), Segment::Key(key("a")?), Segment::Field(0)], value: 11u32.to_be_bytes().to_vec() },
+        Op::Remove { path: vec![Segment::Field(5)], key: key("b")? },
+        Op::Insert { path: vec![Segment::Field(5)], key: key("c")?, value: patch::encode(&Limits { max: 1, burst: None }, &mut ())? },
+    ]);
+
+    let mut value = old.clone();
+    patch.apply(&mut value, &mut ())?;
+
+    // Skipped fields aren't diffed.
+    assert_eq!(value, Config { revision: 1, ..new.clone() });
+
+    // The key was already removed.
+    let mut value = new.clone();
+    assert_eq!(patch.apply(&mut value, &mut ()), Err(Error::InvalidPatch("No key to remove from `[Field(4)]`".to_string())));
+
+    let patch = Patch::<Config>::from_ops(vec![Op::Set { path: vec![Segment::Field(9)], value: vec![] }]);
+    assert_eq!(patch.apply(&mut config(), &mut ()), Err(Error::InvalidPatch("No value at `[Field(9)]` of `[Field(9)]`".to_string())));
+
+    Ok(())
+}
+
+#[test]
+fn test_patch_sequences() -> Result<(), Error> {
+    let old = config();
+
+    // Equal lengths are diffed by index, anything else is spliced between the common ends.
+    let mut new = old.clone();
+    new.routes.get_mut("api").unwrap()[1] = 8443;
+    new.routes.get_mut("admin").unwrap().splice(0..0, [21, 22]);
+
+    let patch = Patch::diff(&old, &new, &mut ())?;
+    let mut ops = patch.ops().to_vec();
+    ops.sort_by_key(|op| matches!(op, Op::Splice { .. }));
+
+    assert_eq!(ops, [
+        Op::Set { path: vec![Segment::Field(3), Segment::Key(key("api")?), Segment::Index(1)], value: 8443u16.to_be_bytes().to_vec() },
+        Op::Splice { path: vec![Segment::Field(3), Segment::Key(key("admin")?)], at: 0, remove: 0, values: patch::encode(&vec![21u16, 22], &mut ())? },
+    ]);
+
+    let list = vec![1u16, 2, 3, 4, 5];
+    let patch = Patch::diff(&list, &vec![1, 7, 5], &mut ())?;
+    assert_eq!(patch.ops(), [Op::Splice { path: vec![], at: 1, remove: 3, values: patch::encode(&vec![7u16], &mut ())? }]);
+
+    let mut value = list.clone();
+    patch.apply(&mut value, &mut ())?;
+    assert_eq!(value, [1, 7, 5]);
+
+    let patch = Patch::<Vec<u16>>::from_ops(vec![Op::Splice { path: vec![], at: 2, remove: 2, values: vec![0] }]);
+    assert_eq!(patch.apply(&mut vec![1, 2, 3], &mut ()), Err(Error::InvalidPatch("Splice of 2 at 2 is out of `[]` of length 3".to_string())));
+
+    Ok(())
+}
+
+#[test]
+fn test_patch_payload() -> Result<(), Error> {
+    let old = config();
+
+    let mut new = old.clone();
+    new.name = "core".to_string();
+    new.routes.remove("admin");
+    new.routes.insert("metrics".to_string(), vec![9100]);
+    new.routes.get_mut("api").unwrap().push(8443);
+
+    let mut next = Next::default();
+    Patch::diff(&old, &new, &mut ())?.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let patch = Patch::<Config>::from_packet(&mut (), &mut next)?;
+
+    let mut value = old.clone();
+    patch.apply(&mut value, &mut ())?;
+    assert_eq!(value, new);
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_patch() -> Result<(), Error> {
+    let old = config();
+
+    let mut new = old.clone();
+    new.limits.max = 50;
+    new.tags.clear();
+
+    let mut next = Next::default();
+    Patch::diff(&old, &new, &mut ())?.poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let patch = Patch::<Config>::poll_from_packet(&mut (), &mut next).await?;
+
+    let mut value = old.clone();
+    patch.apply(&mut value, &mut ())?;
+    assert_eq!(value, new);
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+#![cfg(any(feature = "sync", feature = "async"))]
+
+use npsd::{Error, Info, Next, NextBits, Quantizer};
+
+#[cfg(feature = "sync")]
+use npsd::{Schema, Payload};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncSchema, AsyncPayload};
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Transform {
+    #[npsd(quantize(min = -1000.0, max = 1000.0, bits = 16))]
+    position: [f32; 3],
+    #[npsd(quantize(min = -3.2, max = 3.2, bits = 10))]
+    yaw: f32,
+    #[npsd(quantize(min = 0, max = 1, bits = 4))]
+    alpha: f64,
+    #[npsd(quantize(min = 0.0, max = 1.0, bits = 8))]
+    weights: Vec<f32>,
+}
+
+fn transform() -> Transform {
+    Transform {
+        position: [12.345, -999.99, 1000.0],
+        yaw: 1.5707964,
+        alpha: 0.3,
+        weights: vec![0.0, 0.25, 0.5, 1.0],
+    }
+}
+
+fn assert_close(decoded: &Transform, value: &Transform) {
+    let position = Quantizer::new(-1000.0, 1000.0, 16).step() / 2.0;
+
+    for (decoded, value) in decoded.position.iter().zip(value.position) {
+        assert!(((decoded - value) as f64).abs() <= position + 1e-4, "{} is not {}", decoded, value);
+    }
+
+    assert!(((decoded.yaw - value.yaw) as f64).abs() <= Quantizer::new(-3.2, 3.2, 10).step() / 2.0 + 1e-6);
+    assert!((decoded.alpha - value.alpha).abs() <= Quantizer::new(0.0, 1.0, 4).step() / 2.0);
+    assert_eq!(decoded.weights.len(), value.weights.len());
+
+    for (decoded, value) in decoded.weights.iter().zip(&value.weights) {
+        assert!(((decoded - value) as f64).abs() <= Quantizer::new(0.0, 1.0, 8).step() / 2.0 + 1e-6);
+    }
+}
+
+#[test]
+fn test_quantizer() -> Result<(), Error> {
+    let quantizer = Quantizer::new(-1000.0, 1000.0, 16);
+    assert_eq!(quantizer.max_level(), 65535);
+    assert!(quantizer.step() / 2.0 < 0.0153);
+
+    assert_eq!(quantizer.quantize(-1000.0), 0);
+    assert_eq!(quantizer.quantize(1000.0), 65535);
+    assert_eq!(quantizer.quantize(5000.0), 65535);
+    assert_eq!(quantizer.quantize(f64::NEG_INFINITY), 0);
+    assert_eq!(quantizer.quantize(f64::NAN), 0);
+
+    assert_eq!(quantizer.dequantize(0)?, -1000.0);
+    assert_eq!(quantizer.dequantize(65535)?, 1000.0);
+    assert_eq!(quantizer.dequantize(65536), Err(Error::OutOfRange("Level `65536` is above `65535`, the highest of 16 bits".to_string())));
+
+    let quantizer = Quantizer::new(0.0, 1.0, 64);
+    assert_eq!(quantizer.quantize(1.0), u64::MAX);
+    assert_eq!(quantizer.dequantize(u64::MAX)?, 1.0);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_quantize_roundtrip() -> Result<(), Error> {
+    let value = transform();
+
+    // 3 * 2 bytes of position, 2 of yaw, 1 of alpha, a 1 byte length and 4 * 1 byte of weights.
+    let mut next = Next::default();
+    value.into_packet(&mut (), &mut next)?;
+    assert_eq!(next.serialized().len(), 14);
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let decoded = Transform::from_packet(&mut (), &mut next)?;
+    assert_close(&decoded, &value);
+    assert_eq!(decoded.position[2], 1000.0);
+
+    // 3 * 16 + 10 + 4 bits, then the length and 4 * 8 bits of weights.
+    let mut next = NextBits::new(Next::default());
+    value.into_packet(&mut (), &mut next)?;
+    assert_eq!(next.get_ref().serialized().len(), 13);
+
+    let bytes = next.into_inner().serialized();
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_close(&Transform::from_packet(&mut (), &mut next)?, &value);
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_quantize_clamping() -> Result<(), Error> {
+    let value = Transform {
+        position: [-5000.0, f32::INFINITY, f32::NAN],
+        yaw: 100.0,
+        alpha: -1.0,
+        weights: vec![2.0],
+    };
+
+    let mut next = Next::default();
+    value.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let decoded = Transform::from_packet(&mut (), &mut next)?;
+
+    assert_eq!(decoded.position, [-1000.0, 1000.0, -1000.0]);
+    assert_eq!(decoded.yaw, 3.2);
+    assert_eq!(decoded.alpha, 0.0);
+    assert_eq!(decoded.weights, [1.0]);
+
+    // Levels are only clamped when encoding, a yaw level of 0xffff is above the 10 bit maximum.
+    let mut next = Next::default();
+    (0u16, 0u16, 0u16, 0xffffu16, 0u8, 0usize).into_packet(&mut (), &mut next)?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    let error = Transform::from_packet(&mut (), &mut next).unwrap_err();
+    assert_eq!(error.kind(), &Error::OutOfRange("Level `65535` is above `1023`, the highest of 10 bits".to_string()));
+    assert_eq!(error.path(), Some(&["Transform", "yaw"].map(String::from)[..]));
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_quantize() -> Result<(), Error> {
+    let value = transform();
+
+    let mut next = NextBits::new(Next::default());
+    value.poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.into_inner().serialized();
+    let mut next = NextBits::new(Next::from(bytes.as_slice()));
+    assert_close(&Transform::poll_from_packet(&mut (), &mut next).await?, &value);
+
+    let mut next = Next::default();
+    value.poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.serialized();
+    let mut next = Next::from(bytes.as_slice());
+    assert_close(&Transform::poll_from_packet(&mut (), &mut next).await?, &value);
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+#![cfg(all(feature = "info", any(feature = "sync", feature = "async")))]
+
+use npsd::{Error, Info, Next, NextTyped, PayloadInfo};
+
+#[cfg(feature = "sync")]
+use npsd::{Schema, Payload, Registry};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncSchema, AsyncPayload, AsyncRegistry};
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Login {
+    user: String,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Logout {
+    user: String,
+}
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Ping(u64);
+
+#[derive(Default, Debug)]
+struct Session {
+    users: Vec<String>,
+    #[cfg(feature = "sync")]
+    unknown: Vec<(u64, usize)>,
+}
+
+#[cfg(feature = "sync")]
+fn encode<T: for<'a> Payload<'a, Session>>(value: &T) -> Result<Vec<u8>, Error> {
+    let mut next = NextTyped::new(Next::default());
+    value.into_packet(&mut Session::default(), &mut next)?;
+
+    Ok(next.into_inner().serialized())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_registry_dispatch() -> Result<(), Error> {
+    let mut registry = Registry::new();
+
+    registry
+        .on::<Login>(|login, session: &mut Session| {
+            session.users.push(login.user);
+            Ok(())
+        })
+        .on::<Logout>(|logout, session: &mut Session| {
+            session.users.retain(|user| *user != logout.user);
+            Ok(())
+        });
+
+    assert!(registry.contains::<Login>());
+    assert!(!registry.contains::<Ping>());
+
+    let mut session = Session::default();
+
+    registry.dispatch(&encode(&Login { user: "admin".to_string() })?, &mut session)?;
+    registry.dispatch(&encode(&Login { user: "guest".to_string() })?, &mut session)?;
+    registry.dispatch(&encode(&Logout { user: "admin".to_string() })?, &mut session)?;
+
+    assert_eq!(session.users, vec!["guest"]);
+
+    let ping = encode(&Ping(7))?;
+    assert_eq!(registry.dispatch(&ping, &mut session), Err(Error::UnknownType(Ping::HASH)));
+
+    registry.fallback(|hash, body, session: &mut Session| {
+        session.unknown.push((hash, body.len()));
+        Ok(())
+    });
+
+    registry.dispatch(&ping, &mut session)?;
+    assert_eq!(session.unknown, vec![(Ping::HASH, ping.len() - 8)]);
+
+    // Handlers take over from the fallback, and their errors are returned as is.
+    registry.on::<Ping>(|ping, _: &mut Session| Err(Error::Unsupported(format!("Ping {}", ping.0))));
+    assert_eq!(registry.dispatch(&ping, &mut session), Err(Error::Unsupported("Ping 7".to_string())));
+
+    let login = encode(&Login { user: "admin".to_string() })?;
+    assert_eq!(registry.dispatch(&login[..4], &mut session), Err(Error::InvalidLength { expected: 8, found: 4 }));
+
+    let error = registry.dispatch(&login[..login.len() - 1], &mut session).unwrap_err();
+    assert_eq!(error.kind(), &Error::InvalidLength { expected: 5, found: 4 });
+    assert_eq!(error.path(), Some(&["Login", "user"].map(String::from)[..]));
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_registry_async() -> Result<(), Error> {
+    use std::sync::{Arc, Mutex};
+
+    let pings = Arc::new(Mutex::new(Vec::new()));
+    let mut registry = AsyncRegistry::new();
+
+    registry.on::<Login, _>(|login, session: &mut Session| Box::pin(async move {
+        // The context stays borrowed across the handler's own awaits.
+        tokio::task::yield_now().await;
+        session.users.push(login.user);
+        Ok(())
+    }));
+
+    let received = pings.clone();
+
+    registry.on::<Ping, _>(move |ping, _| {
+        let received = received.clone();
+
+        Box::pin(async move {
+            tokio::task::yield_now().await;
+            received.lock().unwrap().push(ping.0);
+            Ok(())
+        })
+    });
+
+    let mut session = Session::default();
+
+    for value in [1, 2, 3] {
+        let mut next = NextTyped::new(Next::default());
+        Ping(value).poll_into_packet(&mut session, &mut next).await?;
+        registry.dispatch(next.get_ref().as_slice(), &mut session).await?;
+    }
+
+    let mut next = NextTyped::new(Next::default());
+    Login { user: "admin".to_string() }.poll_into_packet(&mut session, &mut next).await?;
+
+    registry.dispatch(next.get_ref().as_slice(), &mut session).await?;
+
+    let mut next = NextTyped::new(Next::default());
+    Logout { user: "admin".to_string() }.poll_into_packet(&mut session, &mut next).await?;
+    let result = registry.dispatch(next.get_ref().as_slice(), &mut session).await;
+
+    assert_eq!(*pings.lock().unwrap(), vec![1, 2, 3]);
+    assert_eq!(session.users, vec!["admin"]);
+    assert_eq!(result, Err(Error::UnknownType(Logout::HASH)));
+
+    Ok(())
+}
@@ -0,0 +1,255 @@
+#[cfg(feature = "rpc")]
+use std::sync::Mutex;
+
+#[cfg(feature = "rpc")]
+use npsd::{AsyncSchema, Error, Info, RpcClient, StreamTransport, loopback, serve, service};
+
+#[cfg(feature = "rpc")]
+#[derive(AsyncSchema, Info, Clone, PartialEq, Debug)]
+struct Item {
+    id: u64,
+    name: String,
+}
+
+#[cfg(feature = "rpc")]
+#[derive(AsyncSchema, Info, PartialEq, Debug)]
+enum InventoryError {
+    NotFound(u64),
+}
+
+#[cfg(feature = "rpc")]
+#[service]
+trait Inventory {
+    /// Looks up one item.
+    fn get(&self, id: u64) -> Result<Item, InventoryError>;
+
+    fn list(&self) -> Vec<Item>;
+
+    async fn rename(&self, id: u64, name: String) -> bool;
+}
+
+#[cfg(feature = "rpc")]
+struct Store(Mutex<Vec<Item>>);
+
+#[cfg(feature = "rpc")]
+impl Store {
+    fn new() -> Self {
+        Store(Mutex::new(vec![
+            Item { id: 1, name: "Hammer".to_string() },
+            Item { id: 2, name: "Saw".to_string() },
+        ]))
+    }
+}
+
+#[cfg(feature = "rpc")]
+impl Inventory for Store {
+    fn get(&self, id: u64) -> Result<Item, InventoryError> {
+        self.0.lock().unwrap().iter().find(|item| item.id == id).cloned().ok_or(InventoryError::NotFound(id))
+    }
+
+    fn list(&self) -> Vec<Item> {
+        self.0.lock().unwrap().clone()
+    }
+
+    async fn rename(&self, id: u64, name: String) -> bool {
+        tokio::task::yield_now().await;
+
+        match self.0.lock().unwrap().iter_mut().find(|item| item.id == id) {
+            Some(item) => {
+                item.name = name;
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(feature = "rpc")]
+#[tokio::test]
+async fn test_rpc_loopback() -> Result<(), Error> {
+    let (client, server) = loopback();
+    let store = InventoryServer(Store::new());
+
+    let client = async move {
+        let client = InventoryClient::new(client);
+
+        assert_eq!(client.get(1).await?, Ok(Item { id: 1, name: "Hammer".to_string() }));
+        assert_eq!(client.get(3).await?, Err(InventoryError::NotFound(3)));
+
+        assert!(client.rename(2, "Drill".to_string()).await?);
+        assert!(!client.rename(3, "Drill".to_string()).await?);
+
+        assert_eq!(client.list().await?.iter().map(|item| item.name.as_str()).collect::<Vec<_>>(), vec!["Hammer", "Drill"]);
+
+        Ok::<_, Error>(())
+    };
+
+    tokio::try_join!(serve(&store, server), client)?;
+
+    Ok(())
+}
+
+#[cfg(feature = "rpc")]
+#[tokio::test]
+async fn test_rpc_in_flight() -> Result<(), Error> {
+    let (client, server) = loopback();
+    let store = InventoryServer(Store::new());
+
+    let client = async move {
+        let client = InventoryClient::new(client);
+
+        // All requests are sent before any response is read.
+        let (first, second, missing, list) = tokio::try_join!(client.get(1), client.get(2), client.get(7), client.list())?;
+
+        assert_eq!(first.map(|item| item.name), Ok("Hammer".to_string()));
+        assert_eq!(second.map(|item| item.name), Ok("Saw".to_string()));
+        assert_eq!(missing, Err(InventoryError::NotFound(7)));
+        assert_eq!(list.len(), 2);
+
+        Ok::<_, Error>(())
+    };
+
+    tokio::try_join!(serve(&store, server), client)?;
+
+    Ok(())
+}
+
+#[cfg(feature = "rpc")]
+#[tokio::test]
+async fn test_rpc_out_of_order() -> Result<(), Error> {
+    use npsd::{FrameReceiver, FrameSender, Transport};
+
+    let (client, server) = loopback();
+
+    // Echoes the arguments of three requests back, in reverse order.
+    let server = async move {
+        let (mut sender, mut receiver) = server.split();
+        let mut requests = Vec::new();
+
+        while requests.len() < 3 {
+            requests.extend(receiver.recv().await?);
+        }
+
+        // Big-endian correlation ids, in the order of the calls, then the method hash.
+        for (id, request) in requests.iter().enumerate() {
+            assert_eq!(request[..16], [(id as u64).to_be_bytes(), [0; 8]].concat());
+        }
+
+        for request in requests.iter().rev() {
+            let mut response = request[..8].to_vec();
+            response.push(0);
+            response.extend_from_slice(&request[16..]);
+
+            sender.send(&response).await?;
+        }
+
+        Ok::<_, Error>(())
+    };
+
+    let client = async move {
+        let client = RpcClient::new(client);
+        let (a, b, c) = ((1u64,), (2u64,), (3u64,));
+        let echo = |args| client.call::<(u64,), u64>(0, args);
+
+        assert_eq!(tokio::try_join!(echo(&a), echo(&b), echo(&c))?, (1, 2, 3));
+
+        Ok::<_, Error>(())
+    };
+
+    tokio::try_join!(server, client)?;
+
+    Ok(())
+}
+
+/// Polls `future` once and drops it, returning whether it was still pending.
+#[cfg(feature = "rpc")]
+async fn poll_once<F: std::future::Future>(future: F) -> bool {
+    let mut future = std::pin::pin!(future);
+    std::future::poll_fn(|cx| std::task::Poll::Ready(future.as_mut().poll(cx).is_pending())).await
+}
+
+#[cfg(feature = "rpc")]
+#[tokio::test]
+async fn test_rpc_cancel() -> Result<(), Error> {
+    use tokio::io::AsyncWriteExt;
+    use npsd::{FrameReceiver, Transport};
+
+    let (client, server) = loopback();
+    let store = InventoryServer(Store::new());
+
+    let client = async move {
+        let client = RpcClient::new(client);
+        let get = |id| client.call::<(u64,), Result<Item, InventoryError>>(npsd::PayloadConstHash(b"Inventory::get"), id);
+
+        // Dropped after its request was sent, its response is discarded by the next call.
+        assert!(poll_once(get(&(1,))).await);
+        assert_eq!(client.pending(), 0);
+
+        assert_eq!(get(&(2,)).await?.map(|item| item.name), Ok("Saw".to_string()));
+        assert_eq!(client.pending(), 0);
+
+        Ok::<_, Error>(())
+    };
+
+    tokio::try_join!(serve(&store, server), client)?;
+
+    // A frame whose read was dropped halfway is still received whole.
+    let (mut writer, reader) = tokio::io::duplex(64);
+    let (_, mut receiver) = StreamTransport::new(reader).split();
+
+    writer.write_all(&[0, 0, 0, 3, 1]).await?;
+    assert!(poll_once(receiver.recv()).await);
+
+    writer.write_all(&[2, 3]).await?;
+    assert_eq!(receiver.recv().await?, Some(vec![1, 2, 3]));
+
+    Ok(())
+}
+
+#[cfg(feature = "rpc")]
+#[tokio::test]
+async fn test_rpc_unknown_method() -> Result<(), Error> {
+    let (client, server) = loopback();
+    let store = InventoryServer(Store::new());
+
+    let client = async move {
+        let client = RpcClient::new(client);
+
+        let result = client.call::<_, u64>(42, &(1u64,)).await;
+        assert_eq!(result, Err(Error::UnknownMethod(42)));
+
+        // Arguments that don't match the method signature.
+        let result = client.call::<_, Vec<Item>>(npsd::PayloadConstHash(b"Inventory::get"), &()).await;
+        assert!(result.is_err());
+
+        Ok::<_, Error>(())
+    };
+
+    tokio::try_join!(serve(&store, server), client)?;
+
+    Ok(())
+}
+
+#[cfg(feature = "rpc")]
+#[tokio::test]
+async fn test_rpc_tcp() -> Result<(), Error> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let store = InventoryServer(Store::new());
+
+    let server = async {
+        let (stream, _) = listener.accept().await?;
+        serve(&store, StreamTransport::new(stream)).await
+    };
+
+    let client = async move {
+        let client = InventoryClient::new(StreamTransport::connect(addr).await?);
+        assert_eq!(client.get(2).await?.map(|item| item.name), Ok("Saw".to_string()));
+
+        Ok::<_, Error>(())
+    };
+
+    tokio::try_join!(server, client)?;
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+#[cfg(feature = "chacha20poly1305")]
+use npsd::{Error, Info, NextPacket, Sealer, SEAL_NONCE_LEN, SEAL_TAG_LEN};
+
+#[cfg(all(feature = "chacha20poly1305", feature = "sync"))]
+use npsd::{Schema, Payload};
+
+#[cfg(all(feature = "chacha20poly1305", feature = "async"))]
+use npsd::{AsyncSchema, AsyncPayload};
+
+#[cfg(feature = "chacha20poly1305")]
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Credentials {
+    user: String,
+    token: Vec<u8>,
+}
+
+#[cfg(feature = "chacha20poly1305")]
+fn credentials() -> Credentials {
+    Credentials { user: "admin".to_string(), token: vec![0xde, 0xad, 0xbe, 0xef] }
+}
+
+#[cfg(all(feature = "chacha20poly1305", feature = "sync"))]
+fn seal(sealer: &Sealer, value: &Credentials) -> Result<Vec<u8>, Error> {
+    let mut next = NextPacket::new(sealer.clone());
+    value.into_packet(&mut (), &mut next)?;
+    next.seal()
+}
+
+#[cfg(all(feature = "chacha20poly1305", feature = "sync"))]
+#[test]
+fn test_seal_roundtrip() -> Result<(), Error> {
+    let sealer = Sealer::new(&Sealer::generate_key());
+    let first = seal(&sealer, &credentials())?;
+    let second = seal(&sealer, &credentials())?;
+
+    // A fresh nonce for every message.
+    assert_ne!(first[..SEAL_NONCE_LEN], second[..SEAL_NONCE_LEN]);
+    assert!(!first.windows(5).any(|window| window == b"admin"));
+
+    let mut next = NextPacket::open(sealer, &first)?;
+    assert_eq!(next.body().as_slice().len() + SEAL_NONCE_LEN + SEAL_TAG_LEN, first.len());
+    assert_eq!(Credentials::from_packet(&mut (), &mut next)?, credentials());
+
+    Ok(())
+}
+
+#[cfg(all(feature = "chacha20poly1305", feature = "sync"))]
+#[test]
+fn test_seal_tampered() -> Result<(), Error> {
+    let sealer = Sealer::new(&[7; 32]);
+    let packet = seal(&sealer, &credentials())?;
+
+    for i in 0..packet.len() {
+        let mut tampered = packet.clone();
+        tampered[i] ^= 0x01;
+
+        assert_eq!(NextPacket::open(sealer.clone(), &tampered).err(), Some(Error::AuthenticationFailed));
+    }
+
+    assert_eq!(NextPacket::open(sealer.clone(), &packet[..packet.len() - 1]).err(), Some(Error::AuthenticationFailed));
+    assert_eq!(NextPacket::open(sealer, &packet[..SEAL_NONCE_LEN]).err(), Some(Error::AuthenticationFailed));
+    assert_eq!(NextPacket::open(Sealer::new(&[8; 32]), &packet).err(), Some(Error::AuthenticationFailed));
+
+    Ok(())
+}
+
+#[cfg(all(feature = "chacha20poly1305", feature = "sync"))]
+#[test]
+fn test_seal_compressed() -> Result<(), Error> {
+    use npsd::{Compressor, Compression};
+
+    let filter = (Compressor::new(Compression::None).with_threshold(0), Sealer::new(&[7; 32]));
+
+    let mut next = NextPacket::new(filter.clone());
+    credentials().into_packet(&mut (), &mut next)?;
+
+    let packet = next.seal()?;
+    let mut next = NextPacket::open(filter, &packet)?;
+
+    assert_eq!(Credentials::from_packet(&mut (), &mut next)?, credentials());
+
+    Ok(())
+}
+
+#[cfg(all(feature = "chacha20poly1305", feature = "async"))]
+#[tokio::test]
+async fn test_seal_async() -> Result<(), Error> {
+    let sealer = Sealer::new(&[7; 32]);
+
+    let mut next = NextPacket::new(sealer.clone());
+    credentials().poll_into_packet(&mut (), &mut next).await?;
+
+    let mut packet = next.seal()?;
+    let mut next = NextPacket::open(sealer.clone(), &packet)?;
+
+    assert_eq!(Credentials::poll_from_packet(&mut (), &mut next).await?, credentials());
+
+    packet[SEAL_NONCE_LEN] ^= 0x80;
+    assert_eq!(NextPacket::open(sealer, &packet).err(), Some(Error::AuthenticationFailed));
+
+    Ok(())
+}
@@ -1,14 +1,15 @@
+#![cfg(feature = "sync")]
+
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::{collections::{HashMap, HashSet}, net::SocketAddr, time::Instant};
 
 use npsd::Info;
 
-#[cfg(feature = "sync")]
 use npsd::{Bitmap, Schema, Payload};
 
 #[cfg(feature = "async")]
-use npsd::{AsyncBitmap, AsyncSchema, AsyncPayload, Error};
+use npsd::{AsyncBitmap, AsyncSchema};
 
 #[cfg_attr(feature = "async", derive(AsyncSchema))]
 #[cfg_attr(feature = "sync", derive(Schema))]
@@ -151,6 +152,7 @@ struct T<'a> {
     field_name18: GenericStruct<String>,
     field_name19: Flags,
     field_name20: MapSet,
+    field_name21: TupleStruct,
 }
 
 #[cfg(feature = "sync")]
@@ -219,6 +221,7 @@ fn serde_test() {
                 "Bob".to_string(),
             ]),
         },
+        field_name21: TupleStruct(0x1337, 42, "Teppo".to_string()),
     };
 
     let start = Instant::now();
@@ -336,6 +339,7 @@ fn serde_serialize_test() {
                 "Bob".to_string(),
             ]),
         },
+        field_name21: TupleStruct(0x1337, 42, "Teppo".to_string()),
     };
 
     let start = Instant::now();
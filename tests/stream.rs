@@ -0,0 +1,90 @@
+#[cfg(feature = "sync")]
+use npsd::{Info, Schema, Payload, Error, NextReader, NextWriter};
+
+#[cfg(feature = "sync")]
+#[derive(Schema, Info, PartialEq, Debug)]
+enum Animal {
+    Dog,
+    Frog(String, Vec<isize>),
+    Cat { age: usize, name: String },
+    AntHive(Vec<String>),
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_stream_file() -> Result<(), Error> {
+    use std::fs::File;
+
+    let path = std::env::temp_dir().join(format!("npsd-stream-{}.bin", std::process::id()));
+
+    let animals = vec![
+        Animal::Dog,
+        Animal::Frog("Frog".to_string(), vec![12393818, -19383812, 11111, -1093838482]),
+        Animal::Cat { age: 7, name: "Tom".to_string() },
+        Animal::AntHive(vec!["Queen".to_string(), "Worker".to_string()]),
+    ];
+
+    let mut writer = NextWriter::new(File::create(&path).map_err(Error::from)?);
+
+    for animal in &animals {
+        animal.into_packet(&mut (), &mut writer)?;
+    }
+
+    writer.flush()?;
+    drop(writer);
+
+    let mut reader = NextReader::new(File::open(&path).map_err(Error::from)?);
+
+    for animal in &animals {
+        assert_eq!(&Animal::from_packet(&mut (), &mut reader)?, animal);
+    }
+
+    assert_eq!(reader.position() as u64, std::fs::metadata(&path).map_err(Error::from)?.len());
+
+    std::fs::remove_file(&path).map_err(Error::from)
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_stream_tcp() -> Result<(), Error> {
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(Error::from)?;
+    let addr = listener.local_addr().map_err(Error::from)?;
+
+    let sender = thread::spawn(move || -> Result<(), Error> {
+        let mut next = NextWriter::new(TcpStream::connect(addr).map_err(Error::from)?);
+
+        Animal::Cat { age: 3, name: "Felix".to_string() }.into_packet(&mut (), &mut next)?;
+        Animal::Frog("Frog".to_string(), vec![1, -2, 3]).into_packet(&mut (), &mut next)
+    });
+
+    let (stream, _) = listener.accept().map_err(Error::from)?;
+    let mut next = NextReader::new(stream);
+
+    assert_eq!(Animal::from_packet(&mut (), &mut next)?, Animal::Cat { age: 3, name: "Felix".to_string() });
+    assert_eq!(Animal::from_packet(&mut (), &mut next)?, Animal::Frog("Frog".to_string(), vec![1, -2, 3]));
+
+    sender.join().unwrap()
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_stream_borrowed() {
+    use std::io::Cursor;
+
+    let mut writer = NextWriter::new(Vec::new());
+    "Hello World!!!".into_packet(&mut (), &mut writer).unwrap();
+
+    let bytes = writer.into_inner();
+
+    let mut reader = NextReader::new(Cursor::new(bytes.as_slice()));
+    assert!(matches!(<&str>::from_packet(&mut (), &mut reader), Err(Error::Unsupported(_))));
+
+    let mut reader = NextReader::new(Cursor::new(bytes.as_slice()));
+    assert_eq!(String::from_packet(&mut (), &mut reader).unwrap(), "Hello World!!!");
+
+    let mut reader = NextReader::new(Cursor::new(&bytes[..6]));
+    assert!(matches!(String::from_packet(&mut (), &mut reader), Err(Error::InvalidLength { expected: 14, found: 5 })));
+}
@@ -0,0 +1,202 @@
+#![cfg(all(feature = "info", any(feature = "sync", feature = "async")))]
+
+use npsd::{Info, Error, NextTrace, CollectSink};
+
+#[cfg(feature = "sync")]
+use npsd::{Schema, Payload, TraceDirection};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncSchema, AsyncPayload};
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+enum Animal {
+    Dog,
+    Frog(String, Vec<isize>),
+    Cat { age: usize, name: String },
+    AntHive(Vec<String>),
+}
+
+fn count(node: &npsd::TraceNode, ty: &str, failed: bool) -> usize {
+    let own = (node.ty == ty && node.error.is_some() == failed) as usize;
+    own + node.children.iter().map(|child| count(child, ty, failed)).sum::<usize>()
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_trace_collect() -> Result<(), Error> {
+    let animal = Animal::Cat { age: 7, name: "Tom".to_string() };
+
+    let mut next = NextTrace::default().with_sink(CollectSink::new());
+    animal.into_packet(&mut (), &mut next)?;
+
+    let len = next.serialized().len();
+    let encoded = next.sink().roots().to_vec();
+
+    assert_eq!(encoded.len(), 1);
+    assert_eq!((encoded[0].ty, encoded[0].direction, encoded[0].offset, encoded[0].nbytes), ("Animal", TraceDirection::Encode, 0, len));
+    assert_eq!(encoded[0].children.iter().map(|node| node.ty).collect::<Vec<_>>(), vec!["usize", "usize", "String"]);
+
+    next.sink_mut().clear();
+    assert_eq!(Animal::from_packet(&mut (), &mut next)?, animal);
+
+    let decoded = next.into_sink().into_roots();
+    let name = &decoded[0].children[2];
+
+    assert_eq!((decoded[0].direction, decoded[0].nbytes), (TraceDirection::Decode, len));
+    assert_eq!((name.ty, name.offset, name.nbytes, name.error.clone()), ("String", 2, 4, None));
+    assert_eq!(name.children[0].ty, "usize");
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_trace_collect_error() {
+    let mut next = NextTrace::default();
+    Animal::Frog("Frog".to_string(), vec![1, 2, 3]).into_packet(&mut (), &mut next).unwrap();
+
+    let bytes = next.serialized();
+    let mut sink = CollectSink::new();
+    let mut next = NextTrace::from(&bytes[..bytes.len() - 1]).with_sink(&mut sink);
+
+    assert!(Animal::from_packet(&mut (), &mut next).is_err());
+
+    let root = &sink.roots()[0];
+
+    assert!(root.error.is_some());
+    assert!(root.children[1].error.is_none());
+    assert!(root.children[2].error.is_some());
+    assert_eq!((count(root, "isize", false), count(root, "isize", true)), (2, 1));
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_trace_collect_async() -> Result<(), Error> {
+    let animal = Animal::AntHive(vec!["Queen".to_string(), "Worker".to_string()]);
+
+    let mut next = NextTrace::default().with_sink(CollectSink::new());
+    animal.poll_into_packet(&mut (), &mut next).await?;
+    next.sink_mut().clear();
+
+    assert_eq!(Animal::poll_from_packet(&mut (), &mut next).await?, animal);
+
+    let root = &next.sink().roots()[0];
+
+    assert_eq!(root.ty, "Animal");
+    assert_eq!(root.nbytes, next.serialized().len());
+    assert_eq!(count(root, "String", false), 2);
+
+    Ok(())
+}
+
+#[cfg(all(feature = "tracing", feature = "sync"))]
+#[test]
+fn test_trace_tracing() -> Result<(), Error> {
+    use std::sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}};
+    use tracing::{field::{Field, Visit}, span::{Attributes, Id, Record}, Event, Metadata, Subscriber};
+    use npsd::TracingSink;
+
+    #[derive(Default)]
+    struct Spans {
+        ids: AtomicU64,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct Ty(String);
+
+    impl Visit for Ty {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "ty" {
+                self.0 = value.to_string();
+            }
+        }
+
+        fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+    }
+
+    impl Subscriber for Spans {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut ty = Ty(String::new());
+            span.record(&mut ty);
+
+            let id = self.ids.fetch_add(1, Ordering::SeqCst) + 1;
+            self.log.lock().unwrap().push(format!("new {} {}", id, ty.0));
+
+            Id::from_u64(id)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, span: &Id) {
+            self.log.lock().unwrap().push(format!("enter {}", span.into_u64()));
+        }
+
+        fn exit(&self, span: &Id) {
+            self.log.lock().unwrap().push(format!("exit {}", span.into_u64()));
+        }
+    }
+
+    let spans = Spans::default();
+    let log = spans.log.clone();
+
+    tracing::subscriber::with_default(spans, || {
+        let mut next = NextTrace::default().with_sink(TracingSink::new());
+        Animal::Dog.into_packet(&mut (), &mut next)
+    })?;
+
+    let log = log.lock().unwrap();
+
+    assert_eq!(log[..4], ["new 1 Animal", "enter 1", "new 2 usize", "enter 2"]);
+    assert_eq!(log[log.len() - 2..], ["exit 2", "exit 1"]);
+    assert_eq!(log.iter().filter(|line| line.starts_with("enter")).count(), log.iter().filter(|line| line.starts_with("exit")).count());
+
+    Ok(())
+}
+
+#[cfg(all(feature = "log", feature = "sync"))]
+#[test]
+fn test_trace_log() -> Result<(), Error> {
+    use std::sync::Mutex;
+    use npsd::LogSink;
+
+    static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct Logger;
+
+    impl log::Log for Logger {
+        fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+            metadata.target() == "npsd"
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            if self.enabled(record.metadata()) {
+                RECORDS.lock().unwrap().push(format!("{} {}", record.level(), record.args()));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    log::set_logger(&Logger).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let mut next = NextTrace::from(&[0x09u8][..]).with_sink(LogSink::new(log::Level::Debug));
+    assert!(Animal::from_packet(&mut (), &mut next).is_err());
+
+    let records = RECORDS.lock().unwrap();
+
+    assert_eq!(records[0], "DEBUG Animal at byte 0");
+    assert_eq!(records[1], "DEBUG Animal <- usize at byte 0");
+    assert!(records.contains(&"DEBUG Animal <- usize done, 1 bytes".to_string()));
+    assert!(records.last().unwrap().starts_with("WARN Animal failed at byte 0: "));
+
+    Ok(())
+}
@@ -37,6 +37,7 @@ fn test_send_recv<'p, T, E>(src: T, dst: E)
     assert_eq!(deserialized, dst);
 }
 
+#[cfg(feature = "sync")]
 macro_rules! test_tuples {
     ($value:expr) => {
         {
@@ -0,0 +1,114 @@
+#![cfg(all(feature = "info", any(feature = "sync", feature = "async")))]
+
+use npsd::{Error, Info, Next, NextTyped};
+
+#[cfg(feature = "sync")]
+use npsd::{Schema, Payload, NextBits, PayloadInfo, type_hash};
+
+#[cfg(feature = "async")]
+use npsd::{AsyncSchema, AsyncPayload};
+
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Login {
+    user: String,
+    session: u64,
+}
+
+/// Same layout as `Login`, so its bytes would decode as one.
+#[cfg_attr(feature = "async", derive(AsyncSchema))]
+#[cfg_attr(feature = "sync", derive(Schema))]
+#[derive(Info, PartialEq, Debug)]
+struct Logout {
+    user: String,
+    session: u64,
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_typed_roundtrip() -> Result<(), Error> {
+    let login = Login { user: "admin".to_string(), session: 42 };
+
+    let mut next = NextTyped::new(Next::default());
+    login.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.into_inner().serialized();
+    assert_eq!(bytes[..8], Login::HASH.to_be_bytes());
+    assert_eq!(type_hash(&bytes)?, Login::HASH);
+
+    let mut next = NextTyped::new(Next::from(bytes.as_slice()));
+    assert_eq!(Login::from_packet(&mut (), &mut next)?, login);
+
+    // Bits are packed by the `NextBits` inside, after the whole hash.
+    let mut next = NextTyped::new(NextBits::new(Next::default()));
+    (true, false, true).into_packet(&mut (), &mut next)?;
+
+    let bytes = next.into_inner().into_inner().serialized();
+    assert_eq!(bytes[8..], [0b101]);
+
+    let mut next = NextTyped::new(NextBits::new(Next::from(bytes.as_slice())));
+    assert_eq!(<(bool, bool, bool)>::from_packet(&mut (), &mut next)?, (true, false, true));
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_typed_mismatch() -> Result<(), Error> {
+    let mut next = NextTyped::new(Next::default());
+    Login { user: "admin".to_string(), session: 42 }.into_packet(&mut (), &mut next)?;
+
+    let bytes = next.into_inner().serialized();
+
+    // Without the envelope the bytes are happily misread.
+    let mut next = Next::from(&bytes[8..]);
+    assert_eq!(Logout::from_packet(&mut (), &mut next)?, Logout { user: "admin".to_string(), session: 42 });
+
+    let mut next = NextTyped::new(Next::from(bytes.as_slice()));
+    assert_eq!(Logout::from_packet(&mut (), &mut next), Err(Error::TypeMismatch {
+        ty: "Logout".to_string(),
+        expected: Logout::HASH,
+        found: Login::HASH,
+    }));
+
+    assert!(matches!(type_hash(&bytes[..7]), Err(Error::InvalidLength { .. })));
+
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_typed_stream() -> Result<(), Error> {
+    use npsd::{NextReader, NextWriter};
+
+    let mut bytes = Vec::new();
+    let mut next = NextTyped::new(NextWriter::new(&mut bytes));
+
+    Login { user: "admin".to_string(), session: 1 }.into_packet(&mut (), &mut next)?;
+    Logout { user: "admin".to_string(), session: 1 }.into_packet(&mut (), &mut next)?;
+
+    let mut next = NextTyped::new(NextReader::new(bytes.as_slice()));
+
+    assert_eq!(Login::from_packet(&mut (), &mut next)?.session, 1);
+    assert!(matches!(Login::from_packet(&mut (), &mut next), Err(Error::TypeMismatch { .. })));
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_typed_async() -> Result<(), Error> {
+    let mut next = NextTyped::new(Next::default());
+    Login { user: "admin".to_string(), session: 42 }.poll_into_packet(&mut (), &mut next).await?;
+
+    let bytes = next.into_inner().serialized();
+
+    let mut next = NextTyped::new(Next::from(bytes.as_slice()));
+    assert_eq!(Login::poll_from_packet(&mut (), &mut next).await?.session, 42);
+
+    let mut next = NextTyped::new(Next::from(bytes.as_slice()));
+    assert!(matches!(Logout::poll_from_packet(&mut (), &mut next).await, Err(Error::TypeMismatch { .. })));
+
+    Ok(())
+}
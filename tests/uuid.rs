@@ -1,5 +1,5 @@
 #[cfg(feature = "uuid")]
-use npsd::{Payload, Info, Schema, Next};
+use npsd::{Payload, Info, Schema};
 
 #[cfg(feature = "uuid")]
 use uuid::Uuid;